@@ -0,0 +1,37 @@
+//! Reference client for the editor socket of `vigil daemon --listen`.
+//!
+//! Sends each argument as one request and prints the response line, e.g.
+//!
+//! ```text
+//! cargo run --example endpoint_client -- '{"cmd":"hosts"}' '{"cmd":"list","host":"dev"}'
+//! ```
+//!
+//! An editor plugin does the same: ask `vigil --attach --print-endpoint` for the
+//! socket path, connect, write one JSON object per line, and read one line
+//! back for each. For `attach`, run the returned `argv` in a terminal.
+
+#[cfg(unix)]
+fn main() -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    use std::process::Command;
+
+    let output = Command::new("vigil").args(["--attach", "--print-endpoint"]).output()?;
+    let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let stream = UnixStream::connect(&path)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    for request in std::env::args().skip(1) {
+        writeln!(writer, "{}", request)?;
+        let mut response = String::new();
+        reader.read_line(&mut response)?;
+        print!("{}", response);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("the vigil daemon socket needs Unix domain sockets");
+}
@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use crate::config::Config;
+use crate::util;
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch when the action started
+    pub ts: u64,
+    /// attach, create, or kill
+    pub action: String,
+    pub host: String,
+    pub session: String,
+    /// How long the action lasted (for attach: time spent attached)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<u64>,
+}
+
+/// Path of the append-only audit log (JSON lines)
+pub fn log_path() -> PathBuf {
+    util::state_dir().join("audit.jsonl")
+}
+
+/// Append an action to the audit log. Failures are reported in debug output
+/// only, since logging must never break the action itself.
+pub fn log(config: &Config, action: &str, session: &str, ts: u64, duration_secs: Option<u64>) {
+    let entry = Entry {
+        ts,
+        action: action.to_string(),
+        host: config.host_alias.as_deref().or(config.destination()).unwrap_or_default().to_string(),
+        session: session.to_string(),
+        duration_secs,
+    };
+    if let Err(e) = append(&entry) {
+        config.debug_print(&format!("failed to write audit log: {:#}", e));
+    }
+}
+
+fn append(entry: &Entry) -> Result<()> {
+    let path = log_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+/// Hosts connected to (attach, create, or plain shell), most recent first,
+/// each once with the time it was last used
+pub fn recent_hosts() -> Vec<(String, u64)> {
+    let mut hosts: Vec<(String, u64)> = Vec::new();
+    for entry in read_all().unwrap_or_default().into_iter().rev() {
+        let connected = matches!(entry.action.as_str(), "attach" | "create" | "shell");
+        if connected && !entry.host.is_empty() && !hosts.iter().any(|(host, _)| *host == entry.host) {
+            hosts.push((entry.host, entry.ts));
+        }
+    }
+    hosts
+}
+
+/// Read all audit log entries, oldest first. Malformed lines are skipped.
+pub fn read_all() -> Result<Vec<Entry>> {
+    let path = log_path();
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
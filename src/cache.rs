@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use crate::config::Config;
+use crate::tmux::{self, SessionInfo};
+use crate::util;
+
+/// Last known sessions per host, so prompts and pickers can answer without SSH
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SessionCache {
+    pub hosts: BTreeMap<String, HostEntry>,
+}
+
+/// Cached sessions of one host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostEntry {
+    /// When the entry was refreshed (Unix time)
+    pub updated: u64,
+    pub sessions: Vec<SessionInfo>,
+}
+
+impl SessionCache {
+    pub fn path() -> PathBuf {
+        util::cache_dir().join("sessions.json")
+    }
+
+    /// Load the cache; a missing or unreadable cache is treated as empty
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the cache atomically (write to a temp file, then rename)
+    pub fn store(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension(format!("json.{}", std::process::id()));
+        fs::write(&tmp, serde_json::to_string(self)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Whether the host's entry is missing or older than `ttl` seconds
+    pub fn is_stale(&self, host: &str, ttl: u64) -> bool {
+        match self.hosts.get(host) {
+            Some(entry) => util::unix_now().saturating_sub(entry.updated) > ttl,
+            None => true,
+        }
+    }
+}
+
+/// Remote `tmux -V` output per host and tmux binary, checked at most once a day
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionCache {
+    hosts: BTreeMap<String, VersionEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionEntry {
+    updated: u64,
+    #[serde(flatten)]
+    probe: TmuxProbe,
+}
+
+/// What a host's tmux reported for `tmux -V`, and where it was found when
+/// that was one of the fallback paths rather than the configured binary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TmuxProbe {
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+const VERSION_TTL_SECS: u64 = 86_400;
+
+fn version_cache_path() -> PathBuf {
+    util::cache_dir().join("tmux-versions.json")
+}
+
+fn load_versions() -> VersionCache {
+    fs::read_to_string(version_cache_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+/// Remembered tmux probe for `key`, unless it is more than a day old
+pub fn tmux_version(key: &str) -> Option<TmuxProbe> {
+    load_versions()
+        .hosts
+        .remove(key)
+        .filter(|entry| util::unix_now().saturating_sub(entry.updated) <= VERSION_TTL_SECS)
+        .map(|entry| entry.probe)
+}
+
+/// Remember a tmux probe for `key`
+pub fn store_tmux_version(key: &str, probe: &TmuxProbe) -> Result<()> {
+    let mut cache = load_versions();
+    cache.hosts.insert(key.to_string(), VersionEntry { updated: util::unix_now(), probe: probe.clone() });
+    let path = version_cache_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("json.{}", std::process::id()));
+    fs::write(&tmp, serde_json::to_string(&cache)?)
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, &path)?;
+    Ok(())
+}
+
+/// Fetch the sessions of `config`'s host and store them in the cache under `key`
+pub fn refresh_host(config: &Config, key: &str) -> Result<Vec<SessionInfo>> {
+    let (sessions, _) = tmux::session_details(config)?;
+    let mut cache = SessionCache::load();
+    cache.hosts.insert(
+        key.to_string(),
+        HostEntry { updated: util::unix_now(), sessions: sessions.clone() },
+    );
+    cache.store()?;
+    Ok(sessions)
+}
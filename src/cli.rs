@@ -1,46 +1,980 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
-use crate::config::Config;
+use clap::{Parser, Subcommand};
+use crate::config::{Config, FileConfig, ProjectConfig};
+use crate::events::EventFormat;
 use crate::util;
-use crate::ssh;
+use crate::hostkey;
+use crate::init::InitShell;
+use crate::pty::{self, LogFormat};
+use crate::ssh::{self, Transport};
+use crate::tmux;
+use crate::ui::{self, ColorChoice};
 
 /// vigil: persistent remote shell sessions via SSH + tmux
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "vigil", version, about = "Persistent remote tmux sessions over SSH", trailing_var_arg = true)]
 pub struct Cli {
-    /// Base tmux session name (if omitted, uses "default" and will be suffixed with local user)
+    /// Base tmux session name (if omitted, uses "default" and will be suffixed with local user) [env: VIGIL_SESSION]
     #[arg(long = "session", value_name = "NAME")]
     pub session: Option<String>,
 
-    /// tmux binary on the remote host
-    #[arg(long = "tmux", default_value = "tmux")]
-    pub tmux_bin: String,
+    /// tmux binary on the remote host [default: tmux, or the bookmark's tmux_bin]
+    #[arg(long = "tmux", value_name = "TMUX_BIN", global = true, env = "VIGIL_TMUX_BIN")]
+    pub tmux_bin: Option<String>,
 
-    /// Extra arguments passed to tmux new-session
+    /// Extra arguments passed to tmux new-session, as one string (deprecated: use --tmux-arg)
     #[arg(long = "tmuxargs", default_value = "")]
     pub tmux_args: String,
 
+    /// One extra argument for tmux new-session, passed verbatim (repeatable)
+    #[arg(long = "tmux-arg", value_name = "ARG", allow_hyphen_values = true)]
+    pub tmux_arg: Vec<String>,
+
     /// Attach to a session (optionally by name). Alias: --select
     #[arg(long = "attach", alias = "select", value_name = "NAME", num_args = 0..=1)]
     pub attach: Option<Option<String>>,
 
+    /// Print the socket editor plugins use to list, attach, and kill sessions
+    /// (served by `vigil daemon --listen`) and exit
+    #[arg(long = "print-endpoint", conflicts_with_all = ["kill", "kill_server", "list", "exists"])]
+    pub print_endpoint: bool,
+
     /// Kill a session (optionally by name)
-    #[arg(long = "kill", value_name = "NAME", num_args = 0..=1)]
+    #[arg(long = "kill", value_name = "NAME", num_args = 0..=1, conflicts_with_all = ["attach", "list", "exists", "tmux_args", "tmux_arg"])]
     pub kill: Option<Option<String>>,
 
+    /// Kill the remote tmux server and every session on it (asks for confirmation)
+    #[arg(long = "kill-server", conflicts_with_all = ["kill", "attach", "list"])]
+    pub kill_server: bool,
+
+    /// Answer yes to confirmation prompts
+    #[arg(short = 'y', long = "yes")]
+    pub yes: bool,
+
+    /// Refuse kills and other destructive operations (also read_only_ops in the config file)
+    #[arg(long = "read-only-ops", global = true)]
+    pub read_only_ops: bool,
+
+    /// When another vigil is killing sessions on the host, wait for it instead of failing
+    #[arg(long = "wait")]
+    pub wait: bool,
+
     /// List sessions on the remote host and exit
-    #[arg(long = "list")]
+    #[arg(long = "list", conflicts_with_all = ["attach", "exists"])]
     pub list: bool,
 
+    /// Target another user's session (base_NAME) for attach and kill instead of your own
+    #[arg(long = "user", value_name = "NAME")]
+    pub user: Option<String>,
+
+    /// With --list, only show sessions named for the local user (per the session template)
+    #[arg(long = "mine")]
+    pub mine: bool,
+
+    /// With --list, first print where the host resolves to (user, address, port, jump host)
+    /// and its uptime, load, and disk space on $HOME
+    #[arg(long = "verbose")]
+    pub verbose: bool,
+
+    /// Check whether a session exists (exit status 1 if not)
+    #[arg(long = "exists", value_name = "NAME", num_args = 0..=1, conflicts_with = "attach")]
+    pub exists: Option<Option<String>>,
+
+    /// Read session names for --kill/--exists from stdin, one per line, using one SSH connection
+    #[arg(long = "stdin")]
+    pub stdin: bool,
+
+    /// When the session does not exist, rebuild its windows and directories from the
+    /// host's last autosave (see `vigil autosave`) before attaching
+    #[arg(long = "restore-if-missing")]
+    pub restore_if_missing: bool,
+
+    /// Refuse to attach when the host is in a bad state (disk nearly full, load or clock
+    /// skew past the `[health]` limits), naming the offending metric
+    #[arg(long = "require-healthy")]
+    pub require_healthy: bool,
+
+    /// Local terminal title while attached; {session}, {host} and {user} are substituted, and
+    /// {title} follows titles set by remote programs (empty disables)
+    #[arg(long = "title-template", value_name = "TEMPLATE", default_value = "vigil: {session}@{host}")]
+    pub title_template: String,
+
+    /// Enable OSC 52 clipboard bridging so remote tmux yanks reach the local clipboard
+    #[arg(long = "clipboard")]
+    pub clipboard: bool,
+
+    /// Force UTF-8 mode in tmux (tmux -u), regardless of the remote locale
+    #[arg(long = "utf8")]
+    pub utf8: bool,
+
+    /// Advertise truecolor (RGB) support to tmux and use a 256-color default-terminal
+    #[arg(long = "truecolor")]
+    pub truecolor: bool,
+
+    /// Install the local terminfo entry on the remote host (via tic) when it is missing
+    #[arg(long = "push-terminfo")]
+    pub push_terminfo: bool,
+
+    /// Skip checking whether the remote host knows the local $TERM
+    #[arg(long = "no-term-check")]
+    pub no_term_check: bool,
+
+    /// Enable systemd lingering and run tmux in a user scope so sessions survive logout
+    #[arg(long = "linger")]
+    pub linger: bool,
+
+    /// Lock the session after this many idle minutes (tmux lock-after-time)
+    // tmux keeps lock-after-time in seconds as a C int
+    #[arg(long = "auto-lock", value_name = "MINS", value_parser = clap::value_parser!(u32).range(..=i32::MAX as i64 / 60))]
+    pub auto_lock: Option<u32>,
+
+    /// Record the attached session to an asciicast v2 file (playable with asciinema)
+    #[arg(long = "record", value_name = "FILE")]
+    pub record: Option<std::path::PathBuf>,
+
+    /// Append everything displayed while attached to FILE (through a local pseudo-terminal)
+    #[arg(long = "log-output", value_name = "FILE", conflicts_with = "record")]
+    pub log_output: Option<std::path::PathBuf>,
+
+    /// Send a desktop notification when the attached session prints again after SECS of quiet
+    #[arg(long = "notify-activity", value_name = "SECS", conflicts_with = "record")]
+    pub notify_activity: Option<u64>,
+
+    /// Local key that detaches the remote tmux client, e.g. ctrl-\ (for nested tmux)
+    #[arg(long = "detach-key", value_name = "CHORD", conflicts_with_all = ["record", "no_tmux", "raw"])]
+    pub detach_key: Option<String>,
+
+    /// Detach from tmux after this long without keyboard input, e.g. 30m or 2h
+    #[arg(long = "idle-detach", value_name = "DURATION", conflicts_with_all = ["record", "no_tmux", "raw"])]
+    pub idle_detach: Option<String>,
+
+    /// Feed large pastes to the session in small chunks with pauses, so slow links don't garble them
+    #[arg(long = "paced-paste", conflicts_with = "record")]
+    pub paced_paste: bool,
+
+    /// Inside a local tmux, open a small pane below with a read-only view of the session
+    /// instead of attaching here
+    #[arg(long = "observe", conflicts_with_all = ["record", "log_output", "no_tmux", "raw", "kill", "kill_server"])]
+    pub observe: bool,
+
+    /// How --log-output writes: the raw bytes, or lines prefixed with the time
+    #[arg(long = "log-format", value_name = "FORMAT", value_enum, default_value = "raw")]
+    pub log_format: LogFormat,
+
+    /// Select this window (name or index) after attaching
+    #[arg(long = "window", value_name = "WINDOW")]
+    pub window: Option<String>,
+
+    /// Select this pane (index) after attaching
+    #[arg(long = "pane", value_name = "PANE")]
+    pub pane: Option<String>,
+
+    /// With --attach and no name: list, pick, and attach over a single SSH connection
+    #[arg(long = "single-shot")]
+    pub single_shot: bool,
+
+    /// Hide ssh banners and warnings (errors still show) and, for --no-tmux shells, the
+    /// remote MOTD and last-login lines
+    #[arg(long = "quiet-login")]
+    pub quiet_login: bool,
+
+    /// Report how long each phase took (parsing, listing, ssh, tmux) on exit
+    #[arg(long = "timings", global = true)]
+    pub timings: bool,
+
+    /// Suppress status messages (errors are still printed)
+    #[arg(long = "quiet", global = true)]
+    pub quiet: bool,
+
+    /// Keep password prompts on the terminal for listing and other captured commands
+    /// (uses sshpass with the password printed by $VIGIL_ASKPASS, when set)
+    #[arg(long = "password-auth")]
+    pub password_auth: bool,
+
+    /// Open a plain login shell over ssh without tmux (bookmarks and logging still apply)
+    #[arg(long = "no-tmux", conflicts_with_all = ["attach", "kill", "kill_server", "list", "exists"])]
+    pub no_tmux: bool,
+
+    /// Run ssh exactly as given, including a remote command after the host, without tmux
+    #[arg(long = "raw", conflicts_with_all = ["attach", "kill", "kill_server", "list", "exists"])]
+    pub raw: bool,
+
+    /// Run remote commands in a login shell (default: bash -lc '...') so profile-set
+    /// PATH, modules, and environment reach tmux and the session
+    #[arg(long = "login-shell", value_name = "SHELL", num_args = 0..=1, default_missing_value = "bash", require_equals = true)]
+    pub login_shell: Option<String>,
+
+    /// Start a new session's first window in a Slurm allocation (srun --pty) on a login node
+    #[arg(long = "slurm")]
+    pub slurm: bool,
+
+    /// Slurm partition for --slurm (implies --slurm)
+    #[arg(long = "partition", value_name = "NAME")]
+    pub partition: Option<String>,
+
+    /// Slurm time limit for --slurm, e.g. 8:00:00 (implies --slurm)
+    #[arg(long = "time", value_name = "LIMIT")]
+    pub time: Option<String>,
+
+    /// CPUs per task for --slurm (implies --slurm)
+    #[arg(long = "cpus", value_name = "N")]
+    pub cpus: Option<u32>,
+
+    /// Load this environment module (Lmod or Environment Modules) before a new session's
+    /// shell starts, after any in the bookmark; repeatable
+    #[arg(long = "module", value_name = "NAME")]
+    pub modules: Vec<String>,
+
+    /// Activate this conda environment (name or prefix) or virtualenv (directory) in new
+    /// sessions and in windows added to them, including with `vigil win new`
+    #[arg(long = "activate", value_name = "ENV", global = true)]
+    pub activate: Option<String>,
+
+    /// Start a new session's first window in the project's nix development shell
+    /// (nix develop with a flake.nix, nix-shell otherwise), in DIR or the bookmark's nix.dir
+    #[arg(long = "nix-develop", value_name = "DIR", num_args = 0..=1, default_missing_value = "", require_equals = true)]
+    pub nix_develop: Option<String>,
+
+    /// Run the remote tmux as root via sudo (prompting on the terminal when needed);
+    /// root's tmux server has its own set of sessions
+    #[arg(long = "sudo", conflicts_with_all = ["no_tmux", "raw"])]
+    pub sudo: bool,
+
+    /// Size windows to this terminal even when a smaller client is attached elsewhere
+    #[arg(long = "force-size", conflicts_with_all = ["no_tmux", "raw"])]
+    pub force_size: bool,
+
+    /// Tune for slow links: ssh compression and a less frequently redrawn tmux status line
+    #[arg(long = "slow-link")]
+    pub slow_link: bool,
+
+    /// Before attaching, make sure ssh-agent is running and holds a key (offers ssh-add)
+    #[arg(long = "check-agent")]
+    pub check_agent: bool,
+
+    /// Without a destination, choose the host from bookmarks and recent hosts
+    /// instead of reconnecting to the last one
+    #[arg(long = "interactive")]
+    pub interactive: bool,
+
+    /// Use the host and session of the project (.vigil.toml or .envrc) in this directory or above
+    #[arg(long = "project")]
+    pub project: bool,
+
+    /// Client used to reach the host (plink for PuTTY/Pageant setups)
+    #[arg(long = "transport", value_name = "PROG", value_enum)]
+    pub transport: Option<Transport>,
+
+    /// Write lifecycle events (connecting, connected, attached, detached, exited, error) to stderr
+    #[arg(long = "events", value_name = "FORMAT", value_enum, global = true)]
+    pub events: Option<EventFormat>,
+
+    /// When to use colored output (NO_COLOR is honored in auto mode)
+    #[arg(long = "color", value_name = "WHEN", value_enum, default_value = "auto", global = true)]
+    pub color: ColorChoice,
+
+    /// Line-based numbered prompts instead of the arrow-key picker, and no title escapes,
+    /// for screen readers and dumb terminals (automatic with TERM=dumb)
+    #[arg(long = "plain-prompts", global = true)]
+    pub plain_prompts: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// SSH arguments and destination (e.g. user@host)
     #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true)]
     pub ssh_args: Vec<String>,
+
+    /// Resolve the config without contacting the host (skips host key pinning)
+    #[arg(skip)]
+    pub offline: bool,
+
+    /// Without a destination, use the most recently used host (plain attach only)
+    #[arg(skip)]
+    pub default_to_recent: bool,
+}
+
+/// Subcommands. Each takes the SSH arguments and destination last, like the
+/// top-level invocation.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Share a session through tmate and print the join URLs
+    Share {
+        /// Session to share
+        session: String,
+
+        /// Stop sharing the session instead
+        #[arg(long = "stop")]
+        stop: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Print the session name, host, and ssh command an attach would use, without connecting
+    Which {
+        /// Print a JSON object instead of labeled lines
+        #[arg(long = "json")]
+        json: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Show sessions, attached clients, and last activity for a host (or all bookmarked hosts)
+    Status {
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Print a compact summary of detached sessions (e.g. "dev:2◉") for shell prompts
+    PromptSegment {
+        /// Seconds before cached session counts are refreshed in the background
+        #[arg(long = "ttl", value_name = "SECS", default_value_t = 60)]
+        ttl: u64,
+
+        /// Refresh the cache in the foreground and print nothing
+        #[arg(long = "refresh")]
+        refresh: bool,
+    },
+
+    /// Open several sessions at once, each in its own window of the local tmux,
+    /// e.g. `vigil open dev:build db1:logs`
+    Open {
+        /// HOST:SESSION, or HOST for its default session; bookmarks and aliases work too
+        #[arg(value_name = "HOST[:SESSION]", required = true)]
+        targets: Vec<String>,
+
+        /// Outside tmux, run each in a new terminal started with this command,
+        /// e.g. "gnome-terminal --tab --" (`open_terminal` in the config)
+        #[arg(long = "terminal", value_name = "COMMAND")]
+        terminal: Option<String>,
+    },
+
+    /// Stand up a working set from a `[profiles.<name>]` table: warm connections, create
+    /// missing sessions detached, and open them when the profile says so
+    Day {
+        /// Profile name; lists the profiles when omitted
+        profile: Option<String>,
+
+        /// Only create the sessions, even if the profile opens them
+        #[arg(long = "no-open")]
+        no_open: bool,
+
+        /// Outside tmux, open each in a new terminal started with this command (as for `vigil open`)
+        #[arg(long = "terminal", value_name = "COMMAND")]
+        terminal: Option<String>,
+    },
+
+    /// Pick any session on any bookmarked host from one list and attach to it
+    Switch {
+        /// Fetch every host's sessions now instead of using the session cache
+        #[arg(long = "refresh")]
+        refresh: bool,
+    },
+
+    /// Keep master connections to bookmarked hosts warm and the session cache fresh
+    Daemon {
+        /// Seconds between refreshes
+        #[arg(long = "interval", value_name = "SECS", default_value_t = 30)]
+        interval: u64,
+
+        /// Send a desktop notification when sessions appear or disappear
+        #[arg(long = "notify")]
+        notify: bool,
+
+        /// Accept list/kill/attach requests from editor plugins on a local socket
+        #[arg(long = "listen")]
+        listen: bool,
+
+        /// Write Prometheus metrics (sessions per host, connection failures) to FILE after every refresh
+        #[arg(long = "metrics", value_name = "FILE")]
+        metrics: Option<std::path::PathBuf>,
+
+        /// Print the path of the --listen socket and exit
+        #[arg(long = "print-endpoint")]
+        print_endpoint: bool,
+    },
+
+    /// Establish (or verify) the master connection to a host ahead of time
+    Warm {
+        /// Also check that ssh-agent is running and holds keys
+        #[arg(long = "check-agent")]
+        check_agent: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Measure SSH round-trip latency to a host
+    Ping {
+        /// Number of round trips to time
+        #[arg(long = "count", short = 'c', value_name = "N", default_value_t = 3)]
+        count: u32,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Run a command on the host (no tmux session), e.g. `vigil exec dev -- uname -a`
+    Exec {
+        /// Allocate a TTY for interactive commands
+        #[arg(long = "tty", short = 't', conflicts_with = "in_session")]
+        tty: bool,
+
+        /// Run in the working directory and tmux environment of a session's
+        /// active pane, or of a given one (e.g. work:2.1)
+        #[arg(long = "in", value_name = "SESSION[:WINDOW.PANE]")]
+        in_session: Option<String>,
+
+        /// With --in, type the command into the pane's shell so it also sees
+        /// the shell's own variables, aliases, and activated environments
+        #[arg(long = "keys", requires = "in_session")]
+        keys: bool,
+
+        /// With --keys, seconds to wait for the command to finish
+        #[arg(long = "timeout", value_name = "SECS", default_value_t = 30, requires = "keys")]
+        timeout: u64,
+
+        /// SSH arguments and destination, then `--` and the remote command
+        #[arg(value_name = "SSH_ARGS -- COMMAND", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+
+        /// Remote command (split off the arguments after `--`)
+        #[arg(skip)]
+        command: Vec<String>,
+    },
+
+    /// Run a command on several hosts at once, e.g. `vigil each @web -- uptime`;
+    /// output lines are prefixed with the host
+    Each {
+        /// How many hosts to run the command on at the same time
+        #[arg(long = "jobs", short = 'j', value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+
+        /// Bookmarks, destinations, or @groups, then `--` and the remote command
+        #[arg(value_name = "HOSTS -- COMMAND", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        hosts: Vec<String>,
+
+        /// Remote command (split off the arguments after `--`)
+        #[arg(skip)]
+        command: Vec<String>,
+    },
+
+    /// Keep a command running in its own detached session, restarting it when it exits,
+    /// e.g. `vigil keep dev -- npm run dev`
+    Keep {
+        /// Session name [default: keep-<command name>]
+        #[arg(long = "name", value_name = "SESSION")]
+        name: Option<String>,
+
+        /// Remote directory to run the command in
+        #[arg(long = "cwd", value_name = "DIR")]
+        cwd: Option<String>,
+
+        /// When to start the command again after it exits
+        #[arg(long = "restart", value_enum, default_value = "on-failure")]
+        restart: tmux::RestartPolicy,
+
+        /// Seconds to wait before each restart
+        #[arg(long = "delay", value_name = "SECS", default_value_t = 5)]
+        delay: u64,
+
+        /// SSH arguments and destination, then `--` and the remote command
+        #[arg(value_name = "SSH_ARGS -- COMMAND", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+
+        /// Remote command (split off the arguments after `--`)
+        #[arg(skip)]
+        command: Vec<String>,
+    },
+
+    /// Have the host recreate the bookmark's configured sessions after every reboot
+    Schedule {
+        /// Start them from a systemd user service instead of an @reboot crontab entry
+        #[arg(long = "systemd")]
+        systemd: bool,
+
+        /// Uninstall instead
+        #[arg(long = "remove")]
+        remove: bool,
+
+        /// Bookmarked host
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Snapshot every session's windows and directories on the host periodically, for --restore-if-missing
+    Autosave {
+        /// Minutes between snapshots
+        #[arg(long = "interval", value_name = "MINUTES", default_value_t = 5)]
+        interval: u32,
+
+        /// Stop autosaving (snapshots already taken are kept)
+        #[arg(long = "remove")]
+        remove: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Show the commands run in a session's panes, from their scrollback or the shells' history files
+    ShellHistory {
+        /// Session to look at
+        session: String,
+
+        /// Read the history files of the shells in the panes instead of the scrollback
+        /// (has timestamps when the shell records them, and reaches back further)
+        #[arg(long = "files")]
+        files: bool,
+
+        /// With --files, show at most this many of the most recent commands per file
+        #[arg(long = "limit", value_name = "N", default_value_t = 200)]
+        limit: usize,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Search the recent scrollback of every session for a pattern, then offer to
+    /// attach to a matching session, e.g. `vigil grep dev 'FAILED.*flaky'`
+    Grep {
+        /// Lines of scrollback to search in each pane
+        #[arg(long = "lines", value_name = "N", default_value_t = 2000)]
+        lines: usize,
+
+        /// Match regardless of case
+        #[arg(long = "ignore-case", short = 'i')]
+        ignore_case: bool,
+
+        /// Show at most this many of the most recent matching lines per pane
+        #[arg(long = "max", value_name = "N", default_value_t = 5)]
+        max: usize,
+
+        /// Only print the matches, without offering to attach
+        #[arg(long = "no-attach")]
+        no_attach: bool,
+
+        /// SSH arguments and destination, then the pattern (an extended regular expression, as for grep -E)
+        #[arg(value_name = "SSH_ARGS PATTERN", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+
+        /// Pattern (split off the last argument)
+        #[arg(skip)]
+        pattern: String,
+    },
+
+    /// Show the current directory of each pane of a session, e.g. to tell which
+    /// checkout a session is working in before attaching
+    Pwd {
+        /// Session to look at
+        session: String,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Create a new session with the same windows, layouts, and directories as another
+    Clone {
+        /// Session to copy
+        source: String,
+
+        /// Name of the new session
+        dest: String,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Print a pane's output since the last call, from a spool file on the host that
+    /// tmux keeps appending to (started on first use), e.g. `vigil logs -f build dev`
+    Logs {
+        /// Session (its active pane), or SESSION:WINDOW.PANE
+        target: String,
+
+        /// Keep printing new output; after a dropped connection, resume where it stopped
+        #[arg(long = "follow", short = 'f')]
+        follow: bool,
+
+        /// Start from the beginning of the spool instead of where the last call stopped
+        #[arg(long = "all", conflicts_with = "stop")]
+        all: bool,
+
+        /// Stop spooling the pane and delete its spool file
+        #[arg(long = "stop", conflicts_with = "follow")]
+        stop: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Save the full scrollback of every pane of every session to local files,
+    /// e.g. to archive a host before it is retired
+    Export {
+        /// Directory to write, with one folder per session and one file per pane
+        /// [default: vigil-export-<host>-<date>]
+        #[arg(long = "out", value_name = "DIR")]
+        out: Option<std::path::PathBuf>,
+
+        /// Keep colors and other attributes as escape sequences
+        #[arg(long = "escapes", short = 'e')]
+        escapes: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// List, create, or kill windows in a remote session
+    Win {
+        #[command(subcommand)]
+        action: WinAction,
+    },
+
+    /// Run any tmux command on the remote server, e.g. `vigil tmux dev -- list-windows -a`
+    Tmux {
+        /// SSH arguments and destination, then `--` and the tmux arguments
+        #[arg(value_name = "SSH_ARGS -- TMUX_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+
+        /// tmux arguments (split off the arguments after `--`)
+        #[arg(skip)]
+        args: Vec<String>,
+    },
+
+    /// Interactively create the config file, or print shell integration
+    /// (`eval "$(vigil init zsh)"`) when a shell is given
+    Init {
+        /// Print an `s HOST` shortcut, prompt segment, and completion for this shell
+        #[arg(value_name = "SHELL")]
+        shell: Option<InitShell>,
+    },
+
+    /// Generate man pages from the command definitions (for packagers)
+    GenMan {
+        /// Write vigil.1 and one page per subcommand here instead of printing vigil.1
+        #[arg(long = "out", value_name = "DIR")]
+        out: Option<std::path::PathBuf>,
+    },
+
+    /// Print a markdown reference of every command and option
+    GenDocs,
+
+    /// Replace this binary with the latest release after verifying its signature
+    SelfUpdate {
+        /// Only report whether a newer release exists (exit status 1 if so)
+        #[arg(long = "check")]
+        check: bool,
+    },
+
+    /// Leave a local note on a session, shown next to it in listings and pickers,
+    /// e.g. `vigil note dev work "don't kill: migration running until Friday"`;
+    /// without text, show notes
+    Note {
+        /// Host as used to connect (bookmark or destination); all hosts if omitted
+        host: Option<String>,
+
+        /// Session the note is about; all of the host's sessions if omitted
+        session: Option<String>,
+
+        /// The note; replaces an earlier one
+        #[arg(trailing_var_arg = true)]
+        text: Vec<String>,
+
+        /// Remove the session's note
+        #[arg(long = "clear", requires = "session", conflicts_with = "text")]
+        clear: bool,
+    },
+
+    /// Show the local audit log of attach, create, and kill actions
+    History {
+        /// Only show entries for this host
+        #[arg(long = "host", value_name = "HOST")]
+        host: Option<String>,
+
+        /// Only show entries for this session
+        #[arg(long = "session", value_name = "NAME")]
+        session: Option<String>,
+
+        /// Show at most this many of the most recent entries
+        #[arg(long = "limit", value_name = "N")]
+        limit: Option<usize>,
+
+        /// Print raw JSON lines instead of a table
+        #[arg(long = "json")]
+        json: bool,
+    },
+
+    /// Copy the host's tmux paste buffer to the local clipboard, without attaching
+    Yank {
+        /// Session whose tmux server holds the buffer (buffers are shared by all its sessions)
+        session: String,
+
+        /// Copy this named buffer instead of the most recent one
+        #[arg(long = "buffer", value_name = "NAME")]
+        buffer: Option<String>,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Load the local clipboard (or stdin) into the host's tmux paste buffer, without attaching
+    Paste {
+        /// Session whose tmux server gets the buffer (buffers are shared by all its sessions)
+        session: String,
+
+        /// Set this named buffer instead of adding a new one
+        #[arg(long = "buffer", value_name = "NAME")]
+        buffer: Option<String>,
+
+        /// Read the text from stdin instead of the clipboard
+        #[arg(long = "stdin")]
+        stdin: bool,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Lock every client attached to a session (tmux lock-session)
+    Lock {
+        /// Session to lock
+        session: String,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
 }
 
+/// Split an `open` target into host and session: "dev:build", or "dev" for
+/// the host's default session. The session follows the last colon, so the
+/// host may carry a user (me@dev:build).
+pub fn parse_target(target: &str) -> Result<(&str, Option<&str>)> {
+    let (host, session) = match target.rsplit_once(':') {
+        Some((host, session)) => (host, Some(session).filter(|s| !s.is_empty())),
+        None => (target, None),
+    };
+    if host.is_empty() || host.starts_with('-') {
+        return Err(anyhow!("invalid target '{}' (expected HOST or HOST:SESSION)", target));
+    }
+    Ok((host, session))
+}
+
+/// Split arguments at the first `--` into (before, after)
+fn split_at_double_dash(mut args: Vec<String>) -> (Vec<String>, Vec<String>) {
+    match args.iter().position(|a| a == "--") {
+        Some(pos) => {
+            let rest = args.split_off(pos + 1);
+            args.pop();
+            (args, rest)
+        }
+        None => (args, Vec::new()),
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum WinAction {
+    /// List the windows of a session
+    List {
+        /// Session whose windows are listed
+        session: String,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Add a window to an existing session
+    New {
+        /// Session to add the window to
+        session: String,
+
+        /// Name for the new window
+        #[arg(short = 'n', long = "window-name", value_name = "NAME")]
+        window_name: Option<String>,
+
+        /// Working directory for the new window (on the remote host)
+        #[arg(short = 'c', long = "cwd", value_name = "DIR")]
+        cwd: Option<String>,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+
+    /// Kill a window in a session
+    Kill {
+        /// Session containing the window
+        session: String,
+
+        /// Window to kill (index or name)
+        #[arg(short = 'w', long = "window", value_name = "WINDOW", required = true)]
+        window: String,
+
+        /// SSH arguments and destination (e.g. user@host)
+        #[arg(value_name = "SSH_ARGS", num_args = 0.., allow_hyphen_values = true, trailing_var_arg = true)]
+        ssh_args: Vec<String>,
+    },
+}
+
+impl Command {
+    /// Take the subcommand's SSH arguments, leaving an empty list behind
+    fn take_ssh_args(&mut self) -> Vec<String> {
+        match self {
+            Command::Share { ssh_args, .. }
+            | Command::Lock { ssh_args, .. }
+            | Command::Yank { ssh_args, .. }
+            | Command::Paste { ssh_args, .. }
+            | Command::Status { ssh_args }
+            | Command::Which { ssh_args, .. }
+            | Command::Warm { ssh_args, .. }
+            | Command::Ping { ssh_args, .. }
+            | Command::Schedule { ssh_args, .. }
+            | Command::Autosave { ssh_args, .. }
+            | Command::ShellHistory { ssh_args, .. }
+            | Command::Pwd { ssh_args, .. }
+            | Command::Clone { ssh_args, .. }
+            | Command::Export { ssh_args, .. }
+            | Command::Logs { ssh_args, .. } => std::mem::take(ssh_args),
+            Command::Exec { ssh_args, command: rest, .. }
+            | Command::Keep { ssh_args, command: rest, .. }
+            | Command::Tmux { ssh_args, args: rest } => {
+                let (args, after) = split_at_double_dash(std::mem::take(ssh_args));
+                *rest = after;
+                args
+            }
+            Command::Each { hosts, command, .. } => {
+                let (targets, after) = split_at_double_dash(std::mem::take(hosts));
+                *hosts = targets;
+                *command = after;
+                Vec::new()
+            }
+            Command::Grep { ssh_args, pattern, .. } => {
+                let mut args = std::mem::take(ssh_args);
+                *pattern = args.pop().unwrap_or_default();
+                args
+            }
+            Command::Win { action } => match action {
+                WinAction::List { ssh_args, .. }
+                | WinAction::New { ssh_args, .. }
+                | WinAction::Kill { ssh_args, .. } => std::mem::take(ssh_args),
+            },
+            Command::History { .. }
+            | Command::Note { .. }
+            | Command::Init { .. }
+            | Command::GenMan { .. }
+            | Command::GenDocs
+            | Command::SelfUpdate { .. }
+            | Command::PromptSegment { .. }
+            | Command::Switch { .. }
+            | Command::Open { .. }
+            | Command::Day { .. }
+            | Command::Daemon { .. } => Vec::new(),
+        }
+    }
+
+    /// Whether the subcommand runs tmux on its host, and so needs the version
+    /// check and the `tmux_fallbacks` search first
+    pub fn drives_tmux(&self) -> bool {
+        match self {
+            Command::Exec { in_session, .. } => in_session.is_some(),
+            Command::Share { .. }
+            | Command::Lock { .. }
+            | Command::Yank { .. }
+            | Command::Paste { .. }
+            | Command::Status { .. }
+            | Command::Schedule { .. }
+            | Command::Autosave { .. }
+            | Command::ShellHistory { .. }
+            | Command::Pwd { .. }
+            | Command::Keep { .. }
+            | Command::Tmux { .. }
+            | Command::Clone { .. }
+            | Command::Export { .. }
+            | Command::Logs { .. }
+            | Command::Win { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the subcommand works purely on local state (no SSH needed)
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            Command::History { .. }
+                | Command::Note { .. }
+                | Command::Init { .. }
+                | Command::GenMan { .. }
+                | Command::GenDocs
+                | Command::SelfUpdate { .. }
+                | Command::Open { .. }
+        )
+    }
+}
+
+/// Host for a plain `vigil` with no destination anywhere: the one used last
+/// (after a grace period to Ctrl-C), or with `interactive` a choice among
+/// bookmarks and recent hosts. Only on a terminal, so scripts are not sent
+/// somewhere unexpected.
+fn default_host(file: &FileConfig, interactive: bool) -> Result<Option<String>> {
+    use std::io::IsTerminal;
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        return Ok(None);
+    }
+    let recent = crate::audit::recent_hosts();
+    if interactive {
+        let mut hosts: Vec<String> = recent.into_iter().map(|(host, _)| host).collect();
+        let named = file.hosts.keys().chain(file.aliases.keys());
+        hosts.extend(named.filter(|name| !hosts.contains(name)).cloned().collect::<Vec<_>>());
+        if hosts.is_empty() {
+            return Err(anyhow!("--interactive: no bookmarks, aliases, or recently used hosts to choose from"));
+        }
+        return ui::prompt_user_to_select_host(&hosts).map(Some);
+    }
+    let Some((host, used)) = recent.into_iter().next() else {
+        return Ok(None);
+    };
+    ui::status(&format!(
+        "Connecting to {} (last used {} ago), Ctrl-C to abort...",
+        ui::host(&host),
+        util::format_duration(util::unix_now().saturating_sub(used))
+    ));
+    std::thread::sleep(RECENT_HOST_GRACE);
+    Ok(Some(host))
+}
+
+/// Pause before reconnecting to the last used host, to allow a Ctrl-C
+const RECENT_HOST_GRACE: std::time::Duration = std::time::Duration::from_secs(2);
+
 impl Cli {
     /// Parse CLI arguments with fallback flag hoisting
     pub fn parse_with_fallback() -> Result<Self> {
-        let mut parsed = Cli::parse();
+        Cli::parse().hoist_trailing_flags()
+    }
+
+    /// Move vigil flags that ended up among the trailing ssh arguments into
+    /// their fields, then check the resulting combination of modes
+    fn hoist_trailing_flags(self) -> Result<Self> {
+        let mut parsed = self;
+
+        // Subcommands carry their own trailing SSH arguments; fold them into the
+        // top-level list so hoisting and config conversion treat them alike.
+        if let Some(command) = parsed.command.as_mut() {
+            let mut sub_args = command.take_ssh_args();
+            parsed.ssh_args.append(&mut sub_args);
+        }
 
         // Be forgiving: if users place flags after the host (common habit), the
         // trailing var-arg will capture them. Scan ssh_args for our known flags and
@@ -53,6 +987,37 @@ impl Cli {
                 parsed.ssh_args.remove(i);
                 continue;
             }
+            if tok == "--mine" {
+                parsed.mine = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--project" {
+                parsed.project = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--verbose" {
+                parsed.verbose = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--timings" {
+                parsed.timings = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--quiet" {
+                parsed.quiet = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if let Some(val) = tok.strip_prefix("--color=") {
+                parsed.color = clap::ValueEnum::from_str(val, true)
+                    .map_err(|_| anyhow!("invalid --color value '{}' (expected auto, always or never)", val))?;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
             // Hoist --session NAME or --session=NAME when passed after the host
             if tok == "--session" || tok.starts_with("--session=") {
                 // Remove the token from ssh_args
@@ -60,7 +1025,7 @@ impl Cli {
 
                 // Handle --session=NAME form
                 if tok.starts_with("--session=") {
-                    if let Some(val) = tok.splitn(2, '=').nth(1) {
+                    if let Some((_, val)) = tok.split_once('=') {
                         parsed.session = Some(val.to_string());
                     }
                     continue;
@@ -92,6 +1057,68 @@ impl Cli {
                 }
                 continue;
             }
+            // Hoist --window/--pane/--user VALUE (or =VALUE); all always take a value
+            if let Some(slot) = match tok.split_once('=').map_or(tok.as_str(), |(flag, _)| flag) {
+                "--window" => Some(&mut parsed.window),
+                "--pane" => Some(&mut parsed.pane),
+                "--user" => Some(&mut parsed.user),
+                _ => None,
+            } {
+                parsed.ssh_args.remove(i);
+                if let Some((_, val)) = tok.split_once('=') {
+                    *slot = Some(val.to_string());
+                } else if i < parsed.ssh_args.len() {
+                    *slot = Some(parsed.ssh_args.remove(i));
+                } else {
+                    return Err(anyhow!("{} needs a value", tok));
+                }
+                continue;
+            }
+            if tok == "--raw" {
+                parsed.raw = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--sudo" {
+                parsed.sudo = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--no-tmux" {
+                parsed.no_tmux = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--kill-server" {
+                parsed.kill_server = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--yes" {
+                parsed.yes = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--stdin" {
+                parsed.stdin = true;
+                parsed.ssh_args.remove(i);
+                continue;
+            }
+            if tok == "--exists" && parsed.exists.is_none() {
+                parsed.ssh_args.remove(i);
+                if i < parsed.ssh_args.len() {
+                    let next = &parsed.ssh_args[i];
+                    if !next.starts_with('-') && !next.contains('@') && !next.contains(':') {
+                        let name = parsed.ssh_args.remove(i);
+                        parsed.exists = Some(Some(name));
+                    } else {
+                        parsed.exists = Some(None);
+                    }
+                } else {
+                    parsed.exists = Some(None);
+                }
+                continue;
+            }
             if tok == "--kill" && parsed.kill.is_none() {
                 parsed.ssh_args.remove(i);
                 if i < parsed.ssh_args.len() {
@@ -110,37 +1137,344 @@ impl Cli {
             i += 1;
         }
 
+        // Hoisted flags bypass clap's conflict rules; apply them again
+        parsed.check_conflicts()?;
+
         // Ensure we allocate a TTY by default for attach/create operations.
         if !parsed.ssh_args.iter().any(|a| a == "-t" || a == "-tt") {
-            parsed.ssh_args.insert(0, "-t".into());
+            parsed.ssh_args.insert(0, ssh::TTY_FLAG.into());
         }
 
         Ok(parsed)
     }
 
-    /// Convert CLI args to Config
-    pub fn to_config(self) -> Result<Config> {
-        // Check SSH is available
-        if !util::check_ssh_available() {
-            return Err(anyhow!("`ssh` not found in PATH"));
+    /// Reject combinations of modes where one would silently win
+    fn check_conflicts(&self) -> Result<()> {
+        let modes = [
+            ("--list", self.list),
+            ("--attach", self.attach.is_some()),
+            ("--kill", self.kill.is_some()),
+            ("--kill-server", self.kill_server),
+            ("--exists", self.exists.is_some()),
+            ("--no-tmux", self.no_tmux),
+            ("--raw", self.raw),
+        ];
+        let active: Vec<&str> = modes.iter().filter(|(_, on)| *on).map(|(flag, _)| *flag).collect();
+        if let [first, second, ..] = active.as_slice() {
+            return Err(anyhow!("the argument '{}' cannot be used with '{}'", first, second));
+        }
+        if self.kill.is_some() || self.kill_server {
+            if !self.tmux_args.is_empty() {
+                return Err(anyhow!("the argument '--tmuxargs' cannot be used with '--kill'"));
+            }
+            if !self.tmux_arg.is_empty() {
+                return Err(anyhow!("the argument '--tmux-arg' cannot be used with '--kill'"));
+            }
+        }
+        if self.sudo && (self.no_tmux || self.raw) {
+            let other = if self.raw { "--raw" } else { "--no-tmux" };
+            return Err(anyhow!("the argument '--sudo' cannot be used with '{}'", other));
         }
+        if self.mine && !self.list {
+            return Err(anyhow!("the argument '--mine' requires '--list'"));
+        }
+        Ok(())
+    }
 
+    /// Convert CLI args to Config, using config file settings as defaults
+    pub fn into_config(self, file: &FileConfig) -> Result<Config> {
         let local_user = util::get_local_username();
         let (ssh_prog, ssh_args) = ssh::infer_ssh_prog(&self.ssh_args)?;
         let debug = std::env::var_os("VIGIL_DEBUG").is_some();
 
-        let session_str = self.session.clone().unwrap_or_else(|| "default".to_string());
-        let session_provided = self.session.is_some();
+        // Precedence: command line, environment, project file, config file.
+        // A project file is consulted when no destination is given at all;
+        // --project requires one and lets it override the environment.
+        let env_value = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (env_host, env_session) = (env_value("VIGIL_HOST"), env_value("VIGIL_SESSION"));
+        let cli_destination = ssh::destination(&self.ssh_args).is_some();
+        let project = if self.project {
+            let cwd = std::env::current_dir()?;
+            let found = ProjectConfig::find(&cwd)?.ok_or_else(|| {
+                anyhow!(
+                    "--project: no {} or .envrc setting VIGIL_HOST/VIGIL_SESSION in {} or its parents",
+                    ProjectConfig::FILE,
+                    cwd.display()
+                )
+            })?;
+            Some(found)
+        } else if !cli_destination && env_host.is_none() {
+            ProjectConfig::find(&std::env::current_dir()?)?
+        } else {
+            None
+        };
+        let project = project.unwrap_or_default();
+        let (mut host, mut session) = if self.project {
+            (project.host.or(env_host), self.session.clone().or(project.session).or(env_session))
+        } else {
+            (env_host.or(project.host), self.session.clone().or(env_session).or(project.session))
+        };
+        if !cli_destination && host.is_none() && self.default_to_recent {
+            host = default_host(file, self.interactive)?;
+        }
+        // An alias names a host and session at once; a bookmark of the same name wins
+        let mut ssh_args = ssh_args;
+        let name = if cli_destination { ssh::destination(&ssh_args) } else { host.as_deref() };
+        if let Some(alias) = name.filter(|name| !file.hosts.contains_key(*name)).and_then(|name| file.aliases.get(name)) {
+            match ssh::destination_index(&ssh_args).filter(|_| cli_destination) {
+                Some(i) => ssh_args[i] = alias.host.clone(),
+                None => host = Some(alias.host.clone()),
+            }
+            if self.session.is_none() {
+                session = Some(alias.session.clone());
+            }
+        }
+
+        let session_provided = session.is_some();
+        let session_str = session
+            .or_else(|| file.session.clone())
+            .unwrap_or_else(|| "default".to_string());
 
-        Ok(Config::new(
+        let mut config = Config::new(
             session_str,
             session_provided,
-            self.tmux_bin,
+            self.tmux_bin.clone().unwrap_or_else(|| "tmux".into()),
             self.tmux_args,
             ssh_prog,
             ssh_args,
             local_user,
             debug,
-        ))
+        );
+        config.session_user_suffix = file.session_user_suffix;
+        config.session_template = file.session_template.clone();
+        config.protected_hosts = file.protected_hosts.clone();
+        config.protected_sessions = file.protected_sessions.clone();
+        config.read_only = self.read_only_ops || file.read_only_ops;
+        config.target_user = self.user;
+        config.tmux_bin_provided = self.tmux_bin.is_some();
+        config.sudo = self.sudo;
+        if let Some(path) = &project.path {
+            config.debug_print(&format!("project config: {}", path.display()));
+        }
+
+        // VIGIL_TMUX_BIN is applied by clap
+        if !cli_destination {
+            if let Some(host) = host {
+                config.ssh_args.push(host);
+            }
+        }
+        if let Some(opts) = std::env::var("VIGIL_SSH_OPTS").ok().filter(|v| !v.trim().is_empty()) {
+            let opts = shell_words::split(&opts).map_err(|e| anyhow!("invalid VIGIL_SSH_OPTS: {}", e))?;
+            // After the command line's options, so ssh lets those win
+            let at = ssh::destination_index(&config.ssh_args).unwrap_or(config.ssh_args.len());
+            config.ssh_args.splice(at..at, opts);
+        }
+        if !config.tmux_args.trim().is_empty() {
+            ui::warn("--tmuxargs is deprecated and may split quoted values wrongly; use --tmux-arg once per argument");
+        }
+        config.tmux_extra_args = self.tmux_arg;
+        config.login_shell = file.login_shell.clone().filter(|s| !s.is_empty());
+        config.title_template = self.title_template;
+        if self.clipboard {
+            config.session_setup.extend(tmux::clipboard_setup());
+        }
+        config.utf8 = self.utf8 || file.utf8;
+        if self.truecolor || file.truecolor {
+            config.session_setup.extend(tmux::truecolor_setup());
+        }
+        if let Some(mins) = self.auto_lock {
+            config.session_setup.push(tmux::auto_lock_setup(mins));
+        }
+        // Jump to a window/pane once attached; the chain runs in the session's context
+        if let Some(window) = &self.window {
+            config.session_setup.push(vec!["select-window".into(), "-t".into(), format!(":{}", window)]);
+        }
+        if let Some(pane) = &self.pane {
+            let window = self.window.as_deref().unwrap_or("");
+            config.session_setup.push(vec!["select-pane".into(), "-t".into(), format!(":{}.{}", window, pane)]);
+        }
+        if self.slow_link {
+            config.ssh_args.insert(0, "-C".into());
+            config.session_setup.push(tmux::slow_link_setup());
+        }
+        config.retries = file.retries;
+        config.retry_backoff_ms = file.retry_backoff_ms;
+        config.linger = self.linger || file.linger;
+        config.linger_check = file.linger_check;
+        config.record = self.record;
+        config.log_output = self.log_output;
+        config.log_format = self.log_format;
+        config.notify_activity = self.notify_activity;
+        if self.paced_paste || file.paced_paste {
+            config.paced_paste = Some(pty::PasteRate {
+                chunk: file.paste_chunk.max(1),
+                delay: std::time::Duration::from_millis(file.paste_delay_ms),
+            });
+        }
+        // Only tmux has a client to detach; the config file's settings are ignored for plain shells
+        if !(self.no_tmux || self.raw) {
+            if let Some(chord) = self.detach_key.as_ref().or(file.detach_key.as_ref()) {
+                config.detach_key = Some(pty::parse_chord(chord)?);
+            }
+            if let Some(idle) = self.idle_detach.as_ref().or(file.idle_detach.as_ref()) {
+                config.idle_detach = Some(util::parse_duration(idle)?);
+            }
+        }
+        if let Some(stale) = &file.stale_after {
+            config.stale_after = Some(util::parse_duration(stale)?);
+        }
+        config.single_shot = self.single_shot || file.single_shot;
+        config.force_size = self.force_size || file.force_size;
+
+        // ssh options after the destination (`vigil host -p 2222`) go before
+        // it; anything else there is a remote command, which would collide
+        // with the tmux command vigil appends
+        if !self.raw {
+            ssh::hoist_trailing_options(&mut config.ssh_args);
+            if let Some(i) = ssh::destination_index(&config.ssh_args) {
+                let extra = &config.ssh_args[i + 1..];
+                if !extra.is_empty() {
+                    return Err(anyhow!(
+                        "unexpected arguments after the destination: {}\n\
+                         vigil runs tmux itself; use --raw to run this command as given, \
+                         or `vigil exec HOST -- CMD`",
+                        shell_words::join(extra)
+                    ));
+                }
+            }
+        }
+
+        // Connection settings; a bookmark may override them (see Config::apply_host)
+        config.control_master = file.control_master;
+        config.control_persist = file.control_persist.clone();
+        config.keepalive = file.keepalive;
+        config.quiet_login = self.quiet_login || file.quiet_login;
+        config.quiet_login_provided = self.quiet_login;
+        config.transport_provided = self.transport.is_some();
+        config.set_client(self.transport.unwrap_or(file.transport), file.windows_ssh);
+
+        // Expand a bookmarked host name into its destination and ssh arguments
+        let bookmark = config
+            .destination()
+            .and_then(|dest| file.hosts.get_key_value(dest))
+            .map(|(name, profile)| (name.clone(), profile.clone()));
+        if let Some((name, profile)) = &bookmark {
+            config.apply_host(name, profile);
+        }
+
+        // Check the client is available
+        if !util::check_ssh_available(&config.ssh_prog) {
+            return Err(anyhow!("`{}` not found in PATH", config.ssh_prog));
+        }
+        if bookmark.is_some() && !self.offline {
+            hostkey::enforce_pin(&mut config)?;
+        }
+        if let Some(shell) = self.login_shell {
+            config.login_shell = Some(shell);
+        }
+        // Slurm flags refine the bookmark's allocation (or start one from scratch)
+        if self.slurm || self.partition.is_some() || self.time.is_some() || self.cpus.is_some() {
+            let allocation = config.slurm.get_or_insert_with(Default::default);
+            allocation.partition = self.partition.or(allocation.partition.take());
+            allocation.time = self.time.or(allocation.time.take());
+            allocation.cpus = self.cpus.or(allocation.cpus);
+        }
+        config.modules.extend(self.modules);
+        if let Some(env) = self.activate {
+            config.activate = Some(env);
+        }
+        if let Some(dir) = self.nix_develop {
+            let shell = config.nix.get_or_insert_with(Default::default);
+            if !dir.is_empty() {
+                shell.dir = Some(dir);
+            }
+        }
+        if config.slurm.is_some() && config.nix.is_some() {
+            return Err(anyhow!("a Slurm allocation and a nix development shell cannot be combined for one session"));
+        }
+
+
+        config.password_auth = self.password_auth || file.password_auth;
+        if config.password_auth {
+            if let Some(askpass) = std::env::var("VIGIL_ASKPASS").ok().filter(|v| !v.is_empty()) {
+                ssh::wrap_with_sshpass(&mut config, &askpass)?;
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli> {
+        Cli::try_parse_from(args)?.hoist_trailing_flags()
+    }
+
+    #[test]
+    fn conflicting_flags_before_host_are_usage_errors() {
+        assert!(Cli::try_parse_from(["vigil", "--list", "--kill", "--", "u@h"]).is_err());
+        assert!(Cli::try_parse_from(["vigil", "--tmuxargs=-d", "--kill", "old", "u@h"]).is_err());
+    }
+
+    #[test]
+    fn conflicting_hoisted_flags_are_rejected() {
+        let err = parse(&["vigil", "u@h", "--list", "--kill"]).unwrap_err();
+        assert_eq!(err.to_string(), "the argument '--list' cannot be used with '--kill'");
+        assert!(parse(&["vigil", "u@h", "--attach", "work", "--kill", "old"]).is_err());
+        assert!(parse(&["vigil", "--list", "u@h", "--attach"]).is_err());
+        assert!(parse(&["vigil", "--tmuxargs=-d", "u@h", "--kill", "old"]).is_err());
+    }
+
+    #[test]
+    fn ssh_options_after_the_host_move_before_it() {
+        let hoisted = |args: &[&str]| {
+            let mut ssh_args = parse(args).unwrap().ssh_args;
+            ssh::hoist_trailing_options(&mut ssh_args);
+            ssh_args
+        };
+        assert_eq!(hoisted(&["vigil", "h", "-p", "2222"]), ["-t", "-p", "2222", "h"]);
+        assert_eq!(hoisted(&["vigil", "h", "-4", "-oBatchMode=yes", "--list"]), ["-t", "-4", "-oBatchMode=yes", "h"]);
+        // A remote command stays behind the host, to be rejected without --raw
+        assert_eq!(hoisted(&["vigil", "h", "-p", "2222", "uptime"]), ["-t", "-p", "2222", "h", "uptime"]);
+    }
+
+    #[test]
+    fn auto_lock_minutes_must_fit_tmux() {
+        assert_eq!(parse(&["vigil", "--auto-lock", "30", "u@h"]).unwrap().auto_lock, Some(30));
+        assert!(parse(&["vigil", "--auto-lock", "4000000000", "u@h"]).is_err());
+        assert!(parse(&["vigil", "--auto-lock", "35791395", "u@h"]).is_err());
+    }
+
+    #[test]
+    fn open_targets_split_at_the_last_colon() {
+        assert_eq!(parse_target("dev:build").unwrap(), ("dev", Some("build")));
+        assert_eq!(parse_target("me@dev:build").unwrap(), ("me@dev", Some("build")));
+        assert_eq!(parse_target("dev").unwrap(), ("dev", None));
+        assert_eq!(parse_target("dev:").unwrap(), ("dev", None));
+        assert!(parse_target(":build").is_err());
+    }
+
+    #[test]
+    fn single_hoisted_mode_is_accepted() {
+        let cli = parse(&["vigil", "u@h", "--attach", "work", "--session", "base"]).unwrap();
+        assert_eq!(cli.attach, Some(Some("work".to_string())));
+        assert_eq!(cli.session.as_deref(), Some("base"));
+        assert_eq!(cli.ssh_args, ["-t", "u@h"]);
+
+        let cli = parse(&["vigil", "-p", "2222", "u@h", "--kill"]).unwrap();
+        assert_eq!(cli.kill, Some(None));
+        assert_eq!(cli.ssh_args, ["-t", "-p", "2222", "u@h"]);
+    }
+
+    #[test]
+    fn grep_pattern_is_the_last_argument() {
+        let cli = parse(&["vigil", "grep", "-i", "-p", "2222", "u@h", "-FAILED"]).unwrap();
+        let Some(Command::Grep { pattern, ignore_case, .. }) = &cli.command else { panic!("not grep") };
+        assert_eq!(pattern, "-FAILED");
+        assert!(ignore_case);
+        assert_eq!(cli.ssh_args, ["-t", "-p", "2222", "u@h"]);
     }
 }
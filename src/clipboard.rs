@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use crate::util;
+
+/// Put `text` on the local clipboard with the first tool that fits the
+/// desktop, or failing that through the terminal (OSC 52). Returns what was used.
+pub fn copy(text: &str) -> Result<&'static str> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = std::env::var_os("DISPLAY").is_some();
+    let tools: [(&'static str, &[&str], bool); 6] = [
+        ("pbcopy", &[], cfg!(target_os = "macos")),
+        ("clip.exe", &[], cfg!(windows) || util::is_wsl()),
+        ("wl-copy", &[], wayland),
+        ("xclip", &["-selection", "clipboard"], x11),
+        ("xsel", &["--clipboard", "--input"], x11),
+        ("termux-clipboard-set", &[], std::env::var_os("TERMUX_VERSION").is_some()),
+    ];
+    for (tool, args, applies) in tools {
+        if applies && pipe_to(tool, args, text)? {
+            return Ok(tool);
+        }
+    }
+    let stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        // The terminal sets the clipboard; many (and tmux without set-clipboard) ignore it
+        write!(stderr.lock(), "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+        return Ok("the terminal (OSC 52)");
+    }
+    Err(anyhow!("no clipboard tool found (pbcopy, wl-copy, xclip, xsel, clip.exe) and no terminal for OSC 52"))
+}
+
+/// Contents of the local clipboard, read with the first tool that fits the
+/// desktop, and what was used
+pub fn paste() -> Result<(Vec<u8>, &'static str)> {
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = std::env::var_os("DISPLAY").is_some();
+    let windows = cfg!(windows) || util::is_wsl();
+    let tools: [(&'static str, &[&str], bool); 6] = [
+        ("pbpaste", &[], cfg!(target_os = "macos")),
+        ("powershell.exe", &["-NoProfile", "-Command", "Get-Clipboard -Raw"], windows),
+        ("wl-paste", &["--no-newline"], wayland),
+        ("xclip", &["-selection", "clipboard", "-o"], x11),
+        ("xsel", &["--clipboard", "--output"], x11),
+        ("termux-clipboard-get", &[], std::env::var_os("TERMUX_VERSION").is_some()),
+    ];
+    for (tool, args, applies) in tools {
+        if !applies {
+            continue;
+        }
+        let output = match Command::new(tool).args(args).stdin(Stdio::null()).stderr(Stdio::inherit()).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("failed to run {}", tool)),
+        };
+        if !output.status.success() {
+            return Err(anyhow!("{} exited with {}", tool, output.status));
+        }
+        let mut text = output.stdout;
+        // Get-Clipboard ends the text with a CRLF of its own
+        if tool == "powershell.exe" && text.ends_with(b"\r\n") {
+            text.truncate(text.len() - 2);
+        }
+        return Ok((text, tool));
+    }
+    Err(anyhow!("no clipboard tool found (pbpaste, wl-paste, xclip, xsel, powershell.exe); pipe the text in with --stdin"))
+}
+
+/// Feed `text` to `tool`; false when the tool is not installed
+fn pipe_to(tool: &str, args: &[&str], text: &str) -> Result<bool> {
+    let mut child = match Command::new(tool).args(args).stdin(Stdio::piped()).stdout(Stdio::null()).spawn() {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("failed to run {}", tool)),
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).with_context(|| format!("failed to write to {}", tool))?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("{} exited with {}", tool, status));
+    }
+    Ok(true)
+}
+
+/// Standard base64 with padding, as OSC 52 expects
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_every_length() {
+        // RFC 4648 test vectors
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+}
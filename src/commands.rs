@@ -0,0 +1,1135 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::process::Stdio;
+use std::time::Duration;
+use crate::audit;
+use crate::cache::{self, SessionCache};
+use crate::cli::{self, Command, WinAction};
+use crate::config::{Config, FileConfig};
+use crate::clipboard;
+use crate::daemon;
+use crate::docs;
+use crate::endpoint;
+use crate::events;
+use crate::init;
+use crate::notes::{self, Notes};
+use crate::logs;
+use crate::schedule;
+use crate::shellhist;
+use crate::ssh::{self, AgentState};
+use crate::tmux;
+use crate::ui;
+use crate::update;
+use crate::util;
+
+/// Run a subcommand that only needs local state
+pub fn run_local(command: Command, file: &FileConfig) -> Result<()> {
+    match command {
+        Command::History { host, session, limit, json } => {
+            history(host.as_deref(), session.as_deref(), limit, json)
+        }
+        Command::Note { host, session, text, clear } => note(host.as_deref(), session.as_deref(), &text, clear),
+        Command::Init { shell: Some(shell) } => {
+            print!("{}", init::integration(shell, file));
+            Ok(())
+        }
+        Command::Init { shell: None } => init::run(),
+        Command::GenMan { out } => docs::man_pages(out.as_deref()),
+        Command::GenDocs => {
+            print!("{}", docs::markdown());
+            Ok(())
+        }
+        Command::SelfUpdate { check } => update::run(file, check),
+        Command::Open { targets, terminal } => open(&targets, terminal.or_else(|| file.open_terminal.clone())),
+        _ => unreachable!("not a local command"),
+    }
+}
+
+/// Run a subcommand against the configured host
+pub fn run(command: Command, config: &Config, file: &FileConfig) -> Result<()> {
+    match command {
+        Command::Which { json, .. } => which(config, json),
+        Command::Status { .. } => status(config, file),
+        Command::PromptSegment { ttl, refresh } => prompt_segment(config, file, ttl, refresh),
+        Command::Daemon { print_endpoint: true, .. } => {
+            println!("{}", endpoint::path().display());
+            Ok(())
+        }
+        Command::Daemon { interval, notify, listen, metrics, .. } => {
+            daemon::run(config, file, interval, notify, listen, metrics.as_deref())
+        }
+        Command::Warm { check_agent, .. } => warm(config, check_agent),
+        Command::Ping { count, .. } => ping(config, count),
+        Command::Exec { tty, in_session, keys, timeout, command, .. } => {
+            // An arbitrary command can destroy as much as any kill
+            config.ensure_writable("exec")?;
+            match in_session {
+                Some(target) => exec_in(config, &target, &command, keys.then_some(timeout)),
+                None => exec(config, &command, tty),
+            }
+        }
+        Command::Schedule { systemd, remove, .. } => {
+            if remove {
+                config.ensure_writable("schedule --remove")?;
+            }
+            schedule(config, file, systemd, remove)
+        }
+        Command::Autosave { interval, remove, .. } => {
+            if remove {
+                config.ensure_writable("autosave --remove")?;
+            }
+            autosave(config, interval, remove)
+        }
+        Command::ShellHistory { session, files, limit, .. } => shell_history(config, &session, files, limit),
+        Command::Pwd { session, .. } => pwd(config, &session),
+        Command::Keep { name, cwd, restart, delay, command, .. } => {
+            config.ensure_writable("keep")?;
+            keep(config, name, cwd.as_deref(), restart, delay, &command)
+        }
+        Command::Tmux { args, .. } => {
+            if args.is_empty() {
+                return Err(anyhow!("no tmux arguments given (usage: vigil tmux HOST -- ARGS...)"));
+            }
+            if !tmux::is_read_only_command(&args) {
+                config.ensure_writable(&format!("tmux {}", shell_words::join(&args)))?;
+            }
+            if kills_protected(config, &args)?
+                && !ui::confirm_by_typing("This host or one of its sessions is protected.", config.host_label())?
+            {
+                ui::status("Aborted.");
+                return Ok(());
+            }
+            let status = tmux::passthrough(config, &args)?;
+            if !status.success() {
+                events::exit(status.code().unwrap_or(1));
+            }
+            Ok(())
+        }
+        Command::Clone { source, dest, .. } => {
+            tmux::clone_session(config, &source, &dest)?;
+            ui::status(&format!(
+                "Cloned session '{}' to '{}'.",
+                ui::session(&source),
+                ui::session(&dest)
+            ));
+            Ok(())
+        }
+        Command::Export { out, escapes, .. } => export(config, out, escapes),
+        Command::Logs { target, follow, all, stop, .. } => {
+            if config.destination().is_none() {
+                return Err(anyhow!("no destination given"));
+            }
+            if stop {
+                config.ensure_writable("logs --stop")?;
+                logs::stop(config, &target)?;
+                ui::status(&format!("Stopped spooling '{}'.", ui::session(&target)));
+                return Ok(());
+            }
+            logs::show(config, &target, follow, all)
+        }
+        Command::Win { action } => win(config, action),
+        Command::Share { session, stop, .. } => {
+            if stop {
+                config.ensure_writable("share --stop")?;
+            }
+            share(config, &session, stop)
+        }
+        Command::Yank { session, buffer, .. } => {
+            let text = tmux::save_buffer(config, &session, buffer.as_deref())?;
+            let via = clipboard::copy(&text)?;
+            let host = ui::host(config.host_label());
+            ui::status(&format!("Copied {} byte(s) from {} to the clipboard via {}.", text.len(), host, via));
+            Ok(())
+        }
+        Command::Paste { session, buffer, stdin, .. } => {
+            let (data, from) = if stdin {
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin(), &mut data)?;
+                (data, "stdin")
+            } else {
+                clipboard::paste()?
+            };
+            if data.is_empty() {
+                return Err(anyhow!("nothing to paste: {} is empty", if stdin { "stdin" } else { "the clipboard" }));
+            }
+            tmux::load_buffer(config, &session, buffer.as_deref(), &data)?;
+            let host = ui::host(config.host_label());
+            ui::status(&format!("Loaded {} byte(s) from {} into the tmux buffer on {}.", data.len(), from, host));
+            Ok(())
+        }
+        Command::Lock { session, .. } => {
+            tmux::lock_remote_session(config, &session)?;
+            ui::status(&format!("Locked session '{}'.", ui::session(&session)));
+            Ok(())
+        }
+        Command::History { .. }
+        | Command::Note { .. }
+        | Command::Init { .. }
+        | Command::GenMan { .. }
+        | Command::GenDocs
+        | Command::SelfUpdate { .. }
+        | Command::Open { .. } => run_local(command, file),
+        Command::Switch { .. } | Command::Grep { .. } => unreachable!("switch and grep turn into a plain attach"),
+        Command::Each { .. } => unreachable!("each runs per host"),
+        Command::Day { profile, no_open, terminal } => day(config, file, profile.as_deref(), no_open, terminal),
+    }
+}
+
+/// Window management within an existing session
+fn win(config: &Config, action: WinAction) -> Result<()> {
+    match action {
+        WinAction::List { session, .. } => tmux::list_windows(config, &session),
+        WinAction::New { session, window_name, cwd, .. } => {
+            tmux::new_window(config, &session, window_name.as_deref(), cwd.as_deref())?;
+            ui::status(&format!("Added window to session '{}'.", ui::session(&session)));
+            Ok(())
+        }
+        WinAction::Kill { session, window, .. } => {
+            config.ensure_writable("win kill")?;
+            if config.session_protected(&session) {
+                let question = format!("'{}' on {} is protected.", session, config.host_label());
+                if !ui::confirm_by_typing(&question, &session)? {
+                    ui::status("Aborted.");
+                    return Ok(());
+                }
+            }
+            tmux::kill_window(config, &session, &window)?;
+            ui::status(&format!("Killed window '{}' in session '{}'.", window, ui::session(&session)));
+            Ok(())
+        }
+    }
+}
+
+/// Whether a `vigil tmux` command line kills something protected: anything
+/// on a protected host, or a protected session among the ones it may kill
+/// (every session for kill-server or a target that names no session)
+fn kills_protected(config: &Config, args: &[String]) -> Result<bool> {
+    let names = tmux::command_names(args);
+    if !names.iter().any(|name| name.starts_with("kill")) {
+        return Ok(false);
+    }
+    if config.host_protected() {
+        return Ok(true);
+    }
+    if config.protected_sessions.is_empty() {
+        return Ok(false);
+    }
+    match tmux::target_sessions(args).filter(|_| !names.iter().any(|name| name.starts_with("kill-ser"))) {
+        Some(sessions) => Ok(sessions.iter().any(|session| config.session_protected(session))),
+        None => Ok(tmux::list_remote_sessions(config)?.iter().any(|session| config.session_protected(session))),
+    }
+}
+
+/// Configs for the given destination, or for every bookmarked host when none was given
+fn target_configs(config: &Config, file: &FileConfig) -> Result<Vec<Config>> {
+    if config.destination().is_some() {
+        return Ok(vec![config.clone()]);
+    }
+    if file.hosts.is_empty() {
+        return Err(anyhow!("no destination given and no hosts bookmarked in {}", FileConfig::path().display()));
+    }
+    Ok(file
+        .hosts
+        .iter()
+        .map(|(name, profile)| {
+            let mut c = config.clone();
+            c.apply_host(name, profile);
+            c
+        })
+        .collect())
+}
+
+/// How many hosts `--list` of a group queries at the same time
+const LIST_JOBS: usize = 8;
+
+/// `f` applied to every item, at most `jobs` at a time, in the items' order
+fn in_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results = std::sync::Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(item) = items.get(i) else { break };
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+/// Run `command` on every host, `jobs` at a time, then print each host's
+/// output with its name before every line; fails when any host did
+pub fn each(configs: &[Config], command: &[String], jobs: usize) -> Result<()> {
+    if command.is_empty() {
+        return Err(anyhow!("no remote command given (usage: vigil each HOST... -- COMMAND...)"));
+    }
+    let command = command.join(" ");
+    let results = in_parallel(configs, jobs, |config| ssh::exec_remote_capture(config, &command));
+    let width = configs.iter().map(|c| c.host_label().chars().count()).max().unwrap_or(0);
+    let mut failed = 0;
+    for (config, result) in configs.iter().zip(results) {
+        let label = config.host_label();
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                ui::error(&format!("{}: {:#}", label, e));
+                failed += 1;
+                continue;
+            }
+        };
+        for line in output.stdout.lines() {
+            println!("{:<width$}  {}", label, line, width = width);
+        }
+        for line in output.stderr.lines() {
+            eprintln!("{:<width$}  {}", label, line, width = width);
+        }
+        if !output.success() {
+            match output.status.code() {
+                Some(code) => ui::error(&format!("{}: exited with status {}", label, code)),
+                None => ui::error(&format!("{}: the command was killed", label)),
+            }
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("the command failed on {} of {} host(s)", failed, configs.len()));
+    }
+    Ok(())
+}
+
+/// `--list` for several hosts: their sessions, fetched [`LIST_JOBS`] at a
+/// time, under a heading per host
+pub fn list_hosts(configs: &[Config], mine_only: bool) -> Result<()> {
+    let results = in_parallel(configs, LIST_JOBS, tmux::list_remote_sessions);
+    let mut failed = 0;
+    for (config, result) in configs.iter().zip(results) {
+        ui::status(&format!("{}:", ui::host(config.host_label())));
+        match result {
+            Ok(sessions) if sessions.is_empty() => eprintln!("  (no sessions)"),
+            Ok(sessions) => print_session_list(config, &sessions, mine_only),
+            Err(e) => {
+                ui::error(&format!("Failed to list sessions: {:#}", e));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("listing failed on {} of {} host(s)", failed, configs.len()));
+    }
+    Ok(())
+}
+
+/// Print session names grouped by owner under the session template: the
+/// local user's first, then other users', then names that fit no owner.
+/// Group headings go to stderr so stdout stays one name per line.
+pub fn print_session_list(config: &Config, sessions: &[String], mine_only: bool) {
+    // Notes only for a reader; piped output stays one name per line
+    let notes = if std::io::stdout().is_terminal() { Notes::load().for_host(config.host_label()) } else { BTreeMap::new() };
+    let print_name = |name: &str| match notes.get(name) {
+        Some(note) => println!("{}  — {}", name, note),
+        None => println!("{}", name),
+    };
+    let mut groups: BTreeMap<Option<String>, Vec<&String>> = BTreeMap::new();
+    for name in sessions {
+        groups.entry(config.session_owner(name)).or_default().push(name);
+    }
+    let me = Some(config.local_user.clone());
+    let mine = groups.remove(&me).unwrap_or_default();
+    if mine_only {
+        if mine.is_empty() {
+            ui::status(&format!("No sessions of {} found.", config.local_user));
+        }
+        for name in mine {
+            print_name(name);
+        }
+        return;
+    }
+
+    // Headings only help when there is more than one group
+    let unowned = groups.remove(&None).unwrap_or_default();
+    let grouped = !groups.is_empty() || (!mine.is_empty() && !unowned.is_empty());
+    let print_group = |heading: String, names: Vec<&String>| {
+        if names.is_empty() {
+            return;
+        }
+        if grouped {
+            ui::status(&heading);
+        }
+        for name in names {
+            print_name(name);
+        }
+    };
+    print_group(format!("Mine ({}):", config.local_user), mine);
+    for (owner, names) in groups {
+        print_group(format!("{}:", owner.unwrap_or_default()), names);
+    }
+    print_group("Other:".into(), unowned);
+}
+
+/// Warn about the user's sessions (or unowned ones) idle longer than
+/// stale_after, other than `current`; a failed check only shows up in --debug
+pub fn warn_stale_sessions(config: &Config, current: Option<&str>) {
+    let Some(limit) = config.stale_after else { return };
+    let sessions = match tmux::session_details(config) {
+        Ok((sessions, _)) => sessions,
+        Err(e) => {
+            config.debug_print(&format!("could not check for idle sessions: {:#}", e));
+            return;
+        }
+    };
+    let now = util::unix_now();
+    for s in sessions {
+        let idle = now.saturating_sub(s.activity);
+        if idle < limit.as_secs() || s.attached > 0 || current == Some(s.name.as_str()) || config.foreign_owner(&s.name).is_some() {
+            continue;
+        }
+        ui::warn(&format!(
+            "Session '{}' idle {}; consider `vigil {} --kill {}`.",
+            s.name,
+            util::format_duration(idle),
+            config.host_label(),
+            shell_words::quote(&s.name)
+        ));
+    }
+}
+
+/// What a plain attach would do, resolved entirely offline
+fn which(config: &Config, json: bool) -> Result<()> {
+    let session = config.target_session();
+    let mut argv = config.ssh_argv();
+    argv.extend(tmux::build_attach_command(config, &session));
+    let destination = config.destination().unwrap_or("");
+
+    if json {
+        let value = serde_json::json!({
+            "session": session,
+            "host": config.host_label(),
+            "destination": destination,
+            "bookmark": config.host_alias,
+            "argv": argv,
+        });
+        println!("{}", value);
+        return Ok(());
+    }
+    println!("session:     {}", session);
+    println!("host:        {}", config.host_label());
+    println!("destination: {}", destination);
+    println!("command:     {}", shell_words::join(&argv));
+    Ok(())
+}
+
+/// Print sessions, their attached clients, and last activity per host
+fn status(config: &Config, file: &FileConfig) -> Result<()> {
+    let now = util::unix_now();
+    for host_config in target_configs(config, file)? {
+        // Show where the name really leads, e.g. "dev (me@10.0.0.7:22 via bastion)"
+        let label = match ssh::resolve_endpoint(&host_config) {
+            Ok(endpoint) => format!("{} ({})", ui::host(host_config.host_label()), endpoint),
+            Err(_) => ui::host(host_config.host_label()),
+        };
+        let (sessions, clients) = match tmux::session_details(&host_config) {
+            Ok(details) => details,
+            Err(e) => {
+                ui::error(&format!("{}: {:#}", host_config.host_label(), e));
+                continue;
+            }
+        };
+        if sessions.is_empty() {
+            println!("{}: no sessions", label);
+            continue;
+        }
+        println!("{}:", label);
+        for s in &sessions {
+            let state = match s.attached {
+                0 => "detached".to_string(),
+                1 => "attached (1 client)".to_string(),
+                n => format!("attached ({} clients)", n),
+            };
+            println!(
+                "  {}  {}, {} window(s), last activity {} ago",
+                ui::session(&s.name),
+                state,
+                s.windows,
+                util::format_duration(now.saturating_sub(s.activity))
+            );
+            for c in clients.iter().filter(|c| c.session == s.name) {
+                println!(
+                    "    client {} since {}",
+                    c.tty,
+                    util::format_timestamp(c.created)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Config and session name for an `open`/`day` target, resolving aliases
+/// and bookmarks the way a plain `vigil HOST` does
+fn target_config(config: &Config, file: &FileConfig, target: &str) -> Result<(Config, String)> {
+    let (mut host, mut session) = cli::parse_target(target)?;
+    if let Some(alias) = file.aliases.get(host).filter(|_| !file.hosts.contains_key(host)) {
+        host = &alias.host;
+        session = session.or(Some(&alias.session));
+    }
+    let mut host_config = config.clone();
+    match file.hosts.get(host) {
+        Some(profile) => host_config.apply_host(host, profile),
+        None => host_config.ssh_args.push(host.to_string()),
+    }
+    let session = session.map(str::to_string).unwrap_or_else(|| host_config.default_session_name());
+    Ok((host_config, session))
+}
+
+/// Stand up a profile's working set: one master connection per host (when
+/// connection sharing is on, so `vigil daemon` keeps them warm afterwards),
+/// every missing session created detached, then opened if asked for
+fn day(config: &Config, file: &FileConfig, profile: Option<&str>, no_open: bool, terminal: Option<String>) -> Result<()> {
+    let Some(name) = profile else {
+        if file.profiles.is_empty() {
+            ui::status(&format!("No [profiles.<name>] tables in {}.", FileConfig::path().display()));
+        }
+        for (name, profile) in &file.profiles {
+            println!("{}: {}", name, profile.targets.join(" "));
+        }
+        return Ok(());
+    };
+    let profile = file
+        .profiles
+        .get(name)
+        .ok_or_else(|| anyhow!("no profile '{}' in {}", name, FileConfig::path().display()))?;
+    if profile.targets.is_empty() {
+        return Err(anyhow!("profile '{}' has no targets", name));
+    }
+
+    let mut warmed = Vec::new();
+    let mut failed = 0;
+    for target in &profile.targets {
+        let (host_config, session) = target_config(config, file, target)?;
+        let host = host_config.host_label().to_string();
+        if host_config.shares_connections() && !warmed.contains(&host) {
+            if let Err(e) = warm(&host_config, false) {
+                ui::error(&format!("{}: {:#}", host, e));
+            }
+            warmed.push(host.clone());
+        }
+        let started = util::unix_now();
+        match tmux::ensure_session(&host_config, &session) {
+            Ok(true) => {
+                audit::log(&host_config, "create", &session, started, None);
+                ui::status(&format!("Created '{}' on {}.", ui::session(&session), ui::host(&host)));
+            }
+            Ok(false) => ui::status(&format!("'{}' on {} is already running.", ui::session(&session), ui::host(&host))),
+            Err(e) => {
+                ui::error(&format!("{}: {:#}", target, e));
+                failed += 1;
+            }
+        }
+    }
+    if profile.open && !no_open {
+        open(&profile.targets, terminal.or_else(|| file.open_terminal.clone()))?;
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} targets could not be set up", failed, profile.targets.len()));
+    }
+    Ok(())
+}
+
+/// Start a vigil for each target in a new window of the local tmux (the
+/// first one focused), or in new terminals started with `terminal`. A
+/// failed connection keeps its window open until Enter.
+fn open(targets: &[String], terminal: Option<String>) -> Result<()> {
+    let in_tmux = std::env::var_os("TMUX").is_some();
+    let terminal = match terminal.filter(|_| !in_tmux) {
+        Some(command) => {
+            let words = shell_words::split(&command).map_err(|e| anyhow!("invalid terminal command: {}", e))?;
+            if words.is_empty() {
+                return Err(anyhow!("the terminal command is empty"));
+            }
+            Some(words)
+        }
+        None if in_tmux => None,
+        None => return Err(anyhow!("vigil open needs a local tmux, or --terminal (open_terminal in the config) to start terminals")),
+    };
+    let exe = std::env::current_exe()?.to_string_lossy().into_owned();
+    for (i, target) in targets.iter().enumerate() {
+        let (host, session) = cli::parse_target(target)?;
+        let mut argv = vec![exe.clone(), host.to_string()];
+        if let Some(session) = session {
+            argv.extend(["--session".to_string(), session.to_string()]);
+        }
+        let script = format!(
+            "{} || {{ echo \"[vigil] exited with status $?; press Enter to close.\"; read -r _; }}",
+            shell_words::join(&argv)
+        );
+        // One shell word for tmux, which runs it through the user's default-shell
+        let command = format!("sh -c {}", util::shell_escape(&script));
+        let result = match &terminal {
+            None => {
+                let mut tmux = std::process::Command::new("tmux");
+                tmux.args(["new-window", "-n", target]);
+                if i > 0 {
+                    tmux.arg("-d");
+                }
+                match tmux.arg(&command).status() {
+                    Ok(status) if status.success() => Ok(()),
+                    Ok(status) => Err(anyhow!("tmux new-window failed ({})", status)),
+                    Err(e) => Err(anyhow!("failed to run the local tmux: {}", e)),
+                }
+            }
+            Some(terminal) => std::process::Command::new(&terminal[0])
+                .args(&terminal[1..])
+                .args(["sh", "-c", &script])
+                .stdin(Stdio::null())
+                .spawn()
+                .map(drop)
+                .map_err(|e| anyhow!("failed to start {}: {}", terminal[0], e)),
+        };
+        match result {
+            Ok(()) => ui::status(&format!("Opened {}.", ui::session(target))),
+            Err(e) => ui::error(&format!("{}: {:#}", target, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Let the user choose among the sessions of all bookmarked hosts, most
+/// recently active first. Cached session lists are used as they are (the
+/// daemon keeps them fresh); hosts missing from the cache, or all of them
+/// with `refresh`, are fetched in parallel.
+pub fn switch_target(config: &Config, file: &FileConfig, refresh: bool) -> Result<(String, String)> {
+    if file.hosts.is_empty() {
+        return Err(anyhow!("no hosts bookmarked in {}", FileConfig::path().display()));
+    }
+    let mut cache = SessionCache::load();
+    let missing: Vec<(&String, Config)> = file
+        .hosts
+        .iter()
+        .filter(|(name, _)| refresh || !cache.hosts.contains_key(*name))
+        .map(|(name, profile)| {
+            let mut host_config = config.clone();
+            host_config.apply_host(name, profile);
+            host_config.make_non_interactive();
+            (name, host_config)
+        })
+        .collect();
+    if !missing.is_empty() {
+        ui::status(&format!("Fetching sessions from {} host(s)...", missing.len()));
+    }
+    let fetched: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = missing
+            .iter()
+            .map(|(name, host_config)| (name, scope.spawn(|| tmux::session_details(host_config))))
+            .collect();
+        handles.into_iter().map(|(name, handle)| (name, handle.join())).collect()
+    });
+    for (name, result) in fetched {
+        match result {
+            Ok(Ok((sessions, _))) => {
+                cache.hosts.insert(name.to_string(), cache::HostEntry { updated: util::unix_now(), sessions });
+            }
+            Ok(Err(e)) => ui::warn(&format!("{}: {:#}", name, e)),
+            Err(_) => ui::warn(&format!("{}: listing sessions failed", name)),
+        }
+    }
+    if let Err(e) = cache.store() {
+        config.debug_print(&format!("failed to store the session cache: {:#}", e));
+    }
+
+    let mut workspaces: Vec<(&String, &tmux::SessionInfo)> = file
+        .hosts
+        .keys()
+        .filter_map(|name| cache.hosts.get(name).map(|entry| (name, entry)))
+        .flat_map(|(name, entry)| entry.sessions.iter().map(move |s| (name, s)))
+        .collect();
+    if workspaces.is_empty() {
+        return Err(anyhow!("no sessions on any bookmarked host"));
+    }
+    workspaces.sort_by_key(|(_, s)| std::cmp::Reverse(s.activity));
+
+    let now = util::unix_now();
+    let notes = Notes::load();
+    let host_width = workspaces.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let session_width = workspaces.iter().map(|(_, s)| s.name.chars().count()).max().unwrap_or(0);
+    let lines: Vec<String> = workspaces
+        .iter()
+        .map(|(name, s)| {
+            let state = if s.attached > 0 { "attached" } else { "detached" };
+            let note = notes.hosts.get(*name).and_then(|n| n.get(&s.name)).map(|n| format!("  — {}", n.text));
+            format!(
+                "{:<hw$}  {:<sw$}  {}, {} window(s), active {} ago{}",
+                name,
+                s.name,
+                state,
+                s.windows,
+                util::format_duration(now.saturating_sub(s.activity)),
+                note.unwrap_or_default(),
+                hw = host_width,
+                sw = session_width
+            )
+        })
+        .collect();
+    let chosen = ui::prompt_user_to_select_workspace(&lines)?;
+    let index = lines.iter().position(|line| *line == chosen).unwrap_or(0);
+    let (name, session) = workspaces[index];
+    Ok((name.clone(), session.name.clone()))
+}
+
+/// Make sure a master connection to the host is up, authenticating now if needed
+fn warm(config: &Config, check_agent: bool) -> Result<()> {
+    if !config.shares_connections() {
+        return Err(anyhow!(
+            "connection sharing is disabled; set `control_master = true` in {}",
+            FileConfig::path().display()
+        ));
+    }
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+
+    if check_agent {
+        match ssh::agent_state() {
+            AgentState::Keys(n) => ui::status(&format!("ssh-agent holds {} key(s).", n)),
+            AgentState::NoKeys => ui::warn("ssh-agent is running but holds no keys; run `ssh-add`."),
+            AgentState::NoAgent => ui::warn("no ssh-agent reachable (SSH_AUTH_SOCK unset or stale)."),
+        }
+    }
+
+    let host = ui::host(config.host_label());
+    if ssh::check_master(config) {
+        ui::status(&format!("Master connection to {} is already up.", host));
+        return Ok(());
+    }
+    let started = std::time::Instant::now();
+    ssh::start_master(config)?;
+    ui::status(&format!(
+        "Master connection to {} established in {:.1}s.",
+        host,
+        started.elapsed().as_secs_f64()
+    ));
+    Ok(())
+}
+
+/// Time round trips to the host and report whether a master connection is reused
+fn ping(config: &Config, count: u32) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let reused = config.shares_connections() && ssh::check_master(config);
+
+    let mut samples = Vec::new();
+    for _ in 0..count.max(1) {
+        samples.push(ssh::ping(config)?.as_secs_f64() * 1000.0);
+    }
+    let min = samples.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().cloned().fold(0.0, f64::max);
+    let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+
+    let connection = if reused {
+        "reusing master connection"
+    } else if config.shares_connections() {
+        "first round trip opened a new master connection"
+    } else {
+        "new connection each time; set control_master = true to reuse"
+    };
+    println!(
+        "{}: {} round trip(s), min/avg/max {:.0}/{:.0}/{:.0} ms ({})",
+        ui::host(config.host_label()),
+        samples.len(),
+        min,
+        avg,
+        max,
+        connection
+    );
+    Ok(())
+}
+
+/// Run a remote command and exit with its status
+fn exec(config: &Config, command: &[String], tty: bool) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    if command.is_empty() {
+        return Err(anyhow!("no remote command given (usage: vigil exec HOST -- COMMAND...)"));
+    }
+    let status = ssh::exec_remote_passthrough(config, command, tty)?;
+    if !status.success() {
+        events::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Run a command in the context of a session's pane (`exec --in`), typed
+/// into its shell when `keys` gives a timeout
+fn exec_in(config: &Config, target: &str, command: &[String], keys: Option<u64>) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    if command.is_empty() {
+        return Err(anyhow!("no remote command given (usage: vigil exec --in SESSION HOST -- COMMAND...)"));
+    }
+    let script = match keys {
+        Some(timeout) => tmux::exec_keys_script(config, target, command, timeout),
+        None => tmux::exec_in_script(config, target, command),
+    };
+    let status = ssh::exec_remote_passthrough(config, &[script], false)?;
+    if !status.success() {
+        events::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
+
+/// Start a supervised command in its own session (or leave a running one be)
+fn keep(
+    config: &Config,
+    name: Option<String>,
+    cwd: Option<&str>,
+    restart: tmux::RestartPolicy,
+    delay: u64,
+    command: &[String],
+) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let Some(program) = command.first() else {
+        return Err(anyhow!("no remote command given (usage: vigil keep HOST -- COMMAND...)"));
+    };
+    // tmux reads '.' and ':' in session names as window and pane separators
+    let session = name.unwrap_or_else(|| {
+        let program = program.split_whitespace().next().unwrap_or(program);
+        let base = program.rsplit('/').next().unwrap_or(program);
+        format!("keep-{}", base).replace(['.', ':'], "_")
+    });
+    let started = util::unix_now();
+    let host = ui::host(config.host_label());
+    if tmux::keep_running(config, &session, command, cwd, restart, delay)? {
+        audit::log(config, "keep", &session, started, None);
+        ui::status(&format!("Started '{}' on {}.", ui::session(&session), host));
+    } else {
+        ui::status(&format!("'{}' is already running on {}; left as is.", ui::session(&session), host));
+    }
+    ui::status(&format!("Attach with: vigil {} --session {}", config.host_label(), session));
+    Ok(())
+}
+
+/// Install (or remove) the boot-time recreation of a bookmark's sessions
+fn schedule(config: &Config, file: &FileConfig, systemd: bool, remove: bool) -> Result<()> {
+    let Some(name) = config.host_alias.as_deref() else {
+        return Err(anyhow!("vigil schedule needs a bookmarked host with [[hosts.<name>.sessions]]"));
+    };
+    let host = ui::host(name);
+    if remove {
+        schedule::remove(config)?;
+        ui::status(&format!("Removed scheduled sessions from {}.", host));
+        return Ok(());
+    }
+    let sessions = file.hosts.get(name).map(|profile| profile.sessions.as_slice()).unwrap_or_default();
+    if sessions.is_empty() {
+        return Err(anyhow!("no [[hosts.{}.sessions]] configured in {}", name, FileConfig::path().display()));
+    }
+    let runner = if systemd { schedule::Runner::Systemd } else { schedule::Runner::Cron };
+    schedule::install(config, sessions, runner)?;
+    let names: Vec<String> = sessions.iter().map(|s| ui::session(&s.name)).collect();
+    ui::status(&format!("{} will recreate {} after a reboot.", host, names.join(", ")));
+    if systemd {
+        ui::status("User services start at boot only with lingering enabled (`loginctl enable-linger` on the host).");
+    }
+    Ok(())
+}
+
+/// Turn periodic session snapshots on the host on or off
+fn autosave(config: &Config, interval: u32, remove: bool) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let host = ui::host(config.host_label());
+    if remove {
+        schedule::remove_autosave(config)?;
+        ui::status(&format!("Stopped autosaving sessions on {}.", host));
+    } else {
+        schedule::install_autosave(config, interval)?;
+        ui::status(&format!("Sessions on {} are saved every {} minute(s).", host, interval));
+    }
+    Ok(())
+}
+
+/// Print the commands found for each pane or history file; headings go to
+/// stderr so stdout is just the commands
+fn shell_history(config: &Config, session: &str, files: bool, limit: usize) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let sources = if files {
+        shellhist::from_files(config, session, limit)?
+    } else {
+        shellhist::from_scrollback(config, session)?
+    };
+    if sources.is_empty() {
+        ui::status("No readable history files for the shells in this session.");
+    }
+    for source in sources {
+        ui::status(&format!("{}:", source.label));
+        if source.entries.is_empty() {
+            eprintln!("  (nothing found)");
+        }
+        for entry in source.entries {
+            match entry.time {
+                Some(time) => println!("{}  {}", util::format_timestamp(time), entry.command),
+                None => println!("{}", entry.command),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the panes whose scrollback matches `pattern` as `session:pane: line`,
+/// then let the user pick one of their sessions to attach to. None when
+/// there is nothing to attach to: no prompt wanted, or no terminal to ask on.
+pub fn grep_target(config: &Config, pattern: &str, lines: usize, ignore_case: bool, max: usize, no_attach: bool) -> Result<Option<String>> {
+    if config.destination().is_none() || pattern.is_empty() {
+        return Err(anyhow!("no destination or no pattern given (usage: vigil grep HOST PATTERN)"));
+    }
+    let matches = tmux::search_scrollback(config, pattern, lines, ignore_case, max)?;
+    if matches.is_empty() {
+        return Err(anyhow!("no session on {} has '{}' in its last {} lines", config.host_label(), pattern, lines));
+    }
+    let mut sessions: Vec<String> = Vec::new();
+    for m in &matches {
+        println!("{}:{}: {}", m.session, m.pane, m.line);
+        if !sessions.contains(&m.session) {
+            sessions.push(m.session.clone());
+        }
+    }
+    if no_attach || !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return Ok(None);
+    }
+    let notes = Notes::load().for_host(config.host_label());
+    ui::prompt_user_to_select_session("attach", &sessions, &notes).map(Some)
+}
+
+/// Write the scrollback of every pane to `out`/<session>/<window>.<pane>.txt
+fn export(config: &Config, out: Option<std::path::PathBuf>, escapes: bool) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let out = out.unwrap_or_else(|| {
+        let stamp = util::format_timestamp(util::unix_now()).replace([' ', ':'], "-");
+        format!("vigil-export-{}-{}", config.host_label(), stamp.trim_end_matches('Z')).into()
+    });
+    if fs::read_dir(&out).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(anyhow!("{} already exists and is not empty; choose another --out", out.display()));
+    }
+    let panes = tmux::capture_all(config, escapes)?;
+    if panes.is_empty() {
+        return Err(anyhow!("no tmux sessions on {}", config.host_label()));
+    }
+    let mut sessions = 0;
+    for (i, pane) in panes.iter().enumerate() {
+        let dir = out.join(pane.session.replace('/', "_"));
+        if i == 0 || panes[i - 1].session != pane.session {
+            fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+            sessions += 1;
+        }
+        let path = dir.join(format!("{}.txt", pane.pane));
+        fs::write(&path, &pane.text).with_context(|| format!("failed to write {}", path.display()))?;
+        println!("{}", path.display());
+    }
+    ui::status(&format!(
+        "Saved {} pane(s) of {} session(s) on {} to {}.",
+        panes.len(),
+        sessions,
+        ui::host(config.host_label()),
+        out.display()
+    ));
+    Ok(())
+}
+
+/// Print the current directory of each pane of `session`
+fn pwd(config: &Config, session: &str) -> Result<()> {
+    if config.destination().is_none() {
+        return Err(anyhow!("no destination given"));
+    }
+    let panes = tmux::pane_paths(config, session)?;
+    if panes.is_empty() {
+        return Err(anyhow!("session '{}' not found on the remote host", session));
+    }
+    let width = panes.iter().map(|p| p.pane.len() + p.window_name.chars().count() + 3).max().unwrap_or(0);
+    for pane in panes {
+        let label = format!("{} ({})", pane.pane, pane.window_name);
+        println!("{:<width$}  {}  [{}]", label, pane.path, pane.command, width = width);
+    }
+    Ok(())
+}
+
+/// Bookmarks covered by the prompt segment
+fn prompt_hosts(file: &FileConfig) -> Vec<String> {
+    if file.prompt_hosts.is_empty() {
+        file.hosts.keys().cloned().collect()
+    } else {
+        file.prompt_hosts.clone()
+    }
+}
+
+/// Print detached session counts from the cache, refreshing stale entries in
+/// a background process so the prompt never waits on SSH
+fn prompt_segment(config: &Config, file: &FileConfig, ttl: u64, refresh: bool) -> Result<()> {
+    let hosts = prompt_hosts(file);
+    let lock = util::cache_dir().join("prompt-refresh.lock");
+
+    if refresh {
+        for name in &hosts {
+            let Some(profile) = file.hosts.get(name) else { continue };
+            let mut host_config = config.clone();
+            host_config.apply_host(name, profile);
+            host_config.make_non_interactive();
+            if let Err(e) = cache::refresh_host(&host_config, name) {
+                config.debug_print(&format!("refresh of {} failed: {:#}", name, e));
+            }
+        }
+        let _ = fs::remove_file(&lock);
+        return Ok(());
+    }
+
+    let cache = SessionCache::load();
+    if hosts.iter().any(|h| cache.is_stale(h, ttl)) && !refresh_in_progress(&lock) {
+        spawn_prompt_refresh(&lock);
+    }
+
+    let segments: Vec<String> = hosts
+        .iter()
+        .filter_map(|h| {
+            let detached = cache.hosts.get(h)?.sessions.iter().filter(|s| s.attached == 0).count();
+            (detached > 0).then(|| format!("{}:{}◉", h, detached))
+        })
+        .collect();
+    if !segments.is_empty() {
+        println!("{}", segments.join(" "));
+    }
+    Ok(())
+}
+
+/// A refresh started less than a minute ago is assumed to still be running
+fn refresh_in_progress(lock: &std::path::Path) -> bool {
+    fs::metadata(lock)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|age| age < Duration::from_secs(60))
+}
+
+fn spawn_prompt_refresh(lock: &std::path::Path) {
+    if let Some(dir) = lock.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(lock, std::process::id().to_string());
+    if let Ok(exe) = std::env::current_exe() {
+        let _ = std::process::Command::new(exe)
+            .args(["prompt-segment", "--refresh"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+    }
+}
+
+/// Print the audit log, newest last
+fn history(host: Option<&str>, session: Option<&str>, limit: Option<usize>, json: bool) -> Result<()> {
+    let mut entries: Vec<audit::Entry> = audit::read_all()?
+        .into_iter()
+        .filter(|e| host.is_none() || host == Some(e.host.as_str()))
+        .filter(|e| session.is_none() || session == Some(e.session.as_str()))
+        .collect();
+    if let Some(limit) = limit {
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+    }
+
+    if entries.is_empty() {
+        ui::status("No matching history entries.");
+        return Ok(());
+    }
+    for e in entries {
+        if json {
+            println!("{}", serde_json::to_string(&e)?);
+            continue;
+        }
+        let duration = e.duration_secs.map(util::format_duration).unwrap_or_else(|| "-".into());
+        println!(
+            "{}  {:<6}  {:>7}  {}  {}",
+            util::format_timestamp(e.ts),
+            e.action,
+            duration,
+            e.host,
+            e.session
+        );
+    }
+    Ok(())
+}
+
+/// Write, clear, or show the notes left on sessions
+fn note(host: Option<&str>, session: Option<&str>, text: &[String], clear: bool) -> Result<()> {
+    let mut notes = Notes::load();
+    if let (Some(host), Some(session)) = (host, session) {
+        if clear {
+            let host_notes = notes.hosts.entry(host.to_string()).or_default();
+            if host_notes.remove(session).is_none() {
+                ui::status(&format!("'{}' on {} has no note.", ui::session(session), ui::host(host)));
+                return Ok(());
+            }
+            if host_notes.is_empty() {
+                notes.hosts.remove(host);
+            }
+            notes.store()?;
+            ui::status(&format!("Cleared the note on '{}'.", ui::session(session)));
+            return Ok(());
+        }
+        if !text.is_empty() {
+            let note = notes::Note { text: text.join(" "), written: util::unix_now() };
+            notes.hosts.entry(host.to_string()).or_default().insert(session.to_string(), note);
+            notes.store()?;
+            ui::status(&format!("Noted on '{}' ({}).", ui::session(session), ui::host(host)));
+            return Ok(());
+        }
+    }
+
+    let mut found = false;
+    for (note_host, sessions) in notes.hosts.iter().filter(|(h, _)| host.is_none() || host == Some(h.as_str())) {
+        for (name, note) in sessions.iter().filter(|(name, _)| session.is_none() || session == Some(name.as_str())) {
+            found = true;
+            println!("{}  {}  {}  {}", util::format_timestamp(note.written), note_host, name, note.text);
+        }
+    }
+    if !found {
+        ui::status("No matching notes.");
+    }
+    Ok(())
+}
+
+/// Share a session via tmate, or stop sharing it
+fn share(config: &Config, session: &str, stop: bool) -> Result<()> {
+    if stop {
+        tmux::stop_share(config, session)?;
+        ui::status(&format!("Stopped sharing session '{}'.", ui::session(session)));
+        return Ok(());
+    }
+
+    let info = tmux::share_session(config, session)?;
+    ui::status(&format!(
+        "Sharing session '{}' via tmate; stop with `vigil share --stop {}`.",
+        ui::session(session),
+        session
+    ));
+    println!("read-write: {}", info.ssh_rw);
+    println!("read-only:  {}", info.ssh_ro);
+    if !info.web_rw.is_empty() {
+        println!("web (rw):   {}", info.web_rw);
+    }
+    if !info.web_ro.is_empty() {
+        println!("web (ro):   {}", info.web_ro);
+    }
+    Ok(())
+}
@@ -1,18 +1,126 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use crate::health::HealthLimits;
+use crate::hostkey;
+use crate::pty::{LogFormat, PasteRate};
+use crate::schedule::SessionTemplate;
+use crate::nix;
+use crate::slurm;
+use crate::ssh::{self, Transport};
+use crate::tmux::TmuxVersion;
+use crate::ui::Theme;
+use crate::util;
+
 /// Core configuration for vigil operations
 #[derive(Debug, Clone)]
 pub struct Config {
     pub session: String,
     /// Whether the session name was explicitly provided by the user
     pub session_provided: bool,
+    /// Append "_<local user>" to the base name when no session is named
+    pub session_user_suffix: bool,
+    /// How the base name and local user combine, e.g. "{base}_{user}"
+    pub session_template: String,
+    /// Another user whose default session is targeted instead (--user)
+    pub target_user: Option<String>,
+    /// Hosts and sessions (patterns with `*`) whose kills must be confirmed by typing the name
+    pub protected_hosts: Vec<String>,
+    pub protected_sessions: Vec<String>,
+    /// Refuse kills and other destructive operations (--read-only-ops)
+    pub read_only: bool,
     pub tmux_bin: String,
+    /// Whether the tmux binary came from --tmux or VIGIL_TMUX_BIN (beats a bookmark's)
+    pub tmux_bin_provided: bool,
+    /// tmux server socket: a name for `-L`, or a path (containing "/") for `-S`
+    pub tmux_socket: Option<String>,
+    /// Shell that runs remote commands as a login shell (`bash -lc '...'`), if any
+    pub login_shell: Option<String>,
+    /// Slurm allocation new sessions start their first window in
+    pub slurm: Option<slurm::Allocation>,
+    /// Nix development shell new sessions start their first window in
+    pub nix: Option<nix::DevShell>,
+    /// Environment modules loaded (`module load`) before a new session's first window starts
+    pub modules: Vec<String>,
+    /// Conda environment or virtualenv activated in new sessions and windows
+    pub activate: Option<String>,
+    /// Run tmux as root through sudo (--sudo); root's tmux server keeps its own sessions
+    pub sudo: bool,
     pub tmux_args: String,
+    /// Arguments for tmux new-session from --tmux-arg, each passed verbatim
+    pub tmux_extra_args: Vec<String>,
     pub ssh_prog: String,
+    /// Program ssh runs under and its leading arguments (e.g. `sshpass -e`);
+    /// empty to run ssh directly. Kept apart so ssh_args holds only ssh's own.
+    pub ssh_wrapper: Vec<String>,
     pub ssh_args: Vec<String>,
     pub local_user: String,
     pub debug: bool,
+    /// Template for the local terminal title while attached (empty disables)
+    pub title_template: String,
+    /// tmux commands chained after new-session (each as argv), e.g. set-option calls
+    pub session_setup: Vec<Vec<String>>,
+    /// Size windows to this client rather than the smallest attached one (--force-size)
+    pub force_size: bool,
+    /// Pass -u to tmux so it assumes a UTF-8 terminal
+    pub utf8: bool,
+    /// TERM override for the interactive ssh invocation
+    pub term: Option<String>,
+    /// Enable lingering and run tmux in a systemd user scope so it survives logout
+    pub linger: bool,
+    /// Warn when a new session would be killed at logout by systemd-logind
+    pub linger_check: bool,
+    /// Record the interactive attach to this asciicast file
+    pub record: Option<PathBuf>,
+    /// Append everything shown during the interactive attach to this file
+    pub log_output: Option<PathBuf>,
+    pub log_format: LogFormat,
+    /// Desktop notification when the attached session prints after this many quiet seconds
+    pub notify_activity: Option<u64>,
+    /// Byte of a local key that detaches the remote tmux client (--detach-key)
+    pub detach_key: Option<u8>,
+    /// Detach the remote tmux client after this long without keyboard input
+    pub idle_detach: Option<std::time::Duration>,
+    /// Warn about the user's sessions idle longer than this when listing or attaching
+    pub stale_after: Option<std::time::Duration>,
+    /// Feed large pastes to the session in chunks (--paced-paste)
+    pub paced_paste: Option<PasteRate>,
+    /// Bookmark name from the config file, when the destination is a bookmark
+    pub host_alias: Option<String>,
+    /// Whether ssh invocations share a master connection (ControlMaster),
+    /// where the client supports it (see [`Config::shares_connections`])
+    pub control_master: bool,
+    /// How long an idle master connection stays open (ControlPersist)
+    pub control_persist: String,
+    /// Seconds between ssh keepalive probes (ServerAliveInterval); 0 disables
+    pub keepalive: u32,
+    /// Run the attach picker remotely so listing and attaching share one connection
+    pub single_shot: bool,
+    /// Hide ssh banners, and the MOTD and last login of plain shells
+    pub quiet_login: bool,
+    /// --quiet-login was given, so a bookmark cannot turn quiet_login off
+    pub quiet_login_provided: bool,
+    /// Client program family; decides how ssh_args are spelled
+    pub transport: Transport,
+    /// --transport was given, so it beats a bookmark's transport
+    pub transport_provided: bool,
+    /// Under WSL, connect with the Windows OpenSSH client (ssh.exe)
+    pub windows_ssh: bool,
+    /// Keep password/keyboard-interactive prompts on the TTY for capture commands
+    pub password_auth: bool,
+    /// Host key fingerprint the bookmark is pinned to
+    pub host_fingerprint: Option<String>,
+    /// Extra attempts for captured commands when ssh itself fails (exit 255)
+    pub retries: u32,
+    /// Delay before the first retry; doubled for each further one
+    pub retry_backoff_ms: u64,
+    /// Remote tmux version, once learned by tmux::handshake
+    pub tmux_version: Option<TmuxVersion>,
 }
 
 impl Config {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         session: String,
         session_provided: bool,
@@ -26,13 +134,315 @@ impl Config {
         Config {
             session,
             session_provided,
+            session_user_suffix: true,
+            session_template: DEFAULT_SESSION_TEMPLATE.into(),
+            target_user: None,
+            protected_hosts: Vec::new(),
+            protected_sessions: Vec::new(),
+            read_only: false,
             tmux_bin,
+            tmux_bin_provided: false,
+            tmux_socket: None,
+            login_shell: None,
+            slurm: None,
+            nix: None,
+            modules: Vec::new(),
+            activate: None,
+            sudo: false,
             tmux_args,
+            tmux_extra_args: Vec::new(),
             ssh_prog,
+            ssh_wrapper: Vec::new(),
             ssh_args,
             local_user,
             debug,
+            title_template: String::new(),
+            session_setup: Vec::new(),
+            force_size: false,
+            utf8: false,
+            term: None,
+            linger: false,
+            linger_check: true,
+            record: None,
+            log_output: None,
+            log_format: LogFormat::Raw,
+            notify_activity: None,
+            detach_key: None,
+            idle_detach: None,
+            stale_after: None,
+            paced_paste: None,
+            host_alias: None,
+            control_master: false,
+            control_persist: "10m".into(),
+            keepalive: 0,
+            single_shot: false,
+            quiet_login: false,
+            quiet_login_provided: false,
+            transport: Transport::Ssh,
+            transport_provided: false,
+            windows_ssh: false,
+            password_auth: false,
+            host_fingerprint: None,
+            retries: 2,
+            retry_backoff_ms: 500,
+            tmux_version: None,
+        }
+    }
+
+    /// Whether the remote tmux has `new-session -A` and `-F` formats
+    /// (assumed when the version is unknown)
+    pub fn tmux_supports_formats(&self) -> bool {
+        match self.tmux_version {
+            Some(version) => version >= TmuxVersion::FORMATS,
+            None => true,
+        }
+    }
+
+    /// Point this config at a bookmarked host: the bookmark's ssh arguments and
+    /// destination replace the destination given on the command line (if any).
+    pub fn apply_host(&mut self, name: &str, profile: &HostProfile) {
+        // The client first, so the bookmark's arguments are spelled for it
+        let transport = profile.transport.filter(|_| !self.transport_provided).unwrap_or(self.transport);
+        self.set_client(transport, profile.windows_ssh.unwrap_or(self.windows_ssh));
+        // Some hosts must show their banner, so a bookmark can opt out of the global setting
+        if let Some(quiet) = profile.quiet_login.filter(|_| !self.quiet_login_provided) {
+            self.quiet_login = quiet;
+        }
+
+        let mut args = profile.ssh_args.clone();
+        args.push(profile.destination.clone().unwrap_or_else(|| name.to_string()));
+        if self.transport == Transport::Plink {
+            args = ssh::plink_options(&args);
         }
+        match ssh::destination_index(&self.ssh_args) {
+            Some(i) => {
+                self.ssh_args.splice(i..=i, args);
+            }
+            None => self.ssh_args.extend(args),
+        }
+        self.host_alias = Some(name.to_string());
+
+        // --tmux (or VIGIL_TMUX_BIN) beats the bookmark's binary; the
+        // bookmark's new-session arguments come before any --tmux-arg
+        if let Some(bin) = profile.tmux_bin.as_ref().filter(|_| !self.tmux_bin_provided) {
+            self.tmux_bin = bin.clone();
+        }
+        if profile.socket.is_some() {
+            self.tmux_socket = profile.socket.clone();
+        }
+        self.tmux_extra_args.splice(0..0, profile.tmux_args.iter().cloned());
+        if profile.slurm.is_some() {
+            self.slurm = profile.slurm.clone();
+        }
+        if profile.nix.is_some() {
+            self.nix = profile.nix.clone();
+        }
+        self.modules.splice(0..0, profile.modules.iter().cloned());
+        if profile.activate.is_some() {
+            self.activate = profile.activate.clone();
+        }
+        if let Some(shell) = &profile.login_shell {
+            self.login_shell = Some(shell.clone()).filter(|s| !s.is_empty());
+        }
+        self.read_only |= profile.read_only;
+
+        // A pin verified earlier is enforced by ssh itself (see hostkey::enforce_pin)
+        self.host_fingerprint = profile.fingerprint.clone();
+        let pinned = hostkey::pinned_known_hosts(name);
+        if self.host_fingerprint.is_some() && self.transport == Transport::Ssh && pinned.exists() {
+            let at = ssh::destination_index(&self.ssh_args).unwrap_or(self.ssh_args.len());
+            self.ssh_args.splice(at..at, hostkey::pin_options(&pinned));
+        }
+    }
+
+    /// Use `transport` (and under WSL, with `windows_ssh`, the Windows
+    /// OpenSSH client), respelling ssh_args for a client of another family
+    pub fn set_client(&mut self, transport: Transport, windows_ssh: bool) {
+        if transport != self.transport {
+            self.ssh_args = match transport {
+                Transport::Plink => ssh::plink_args(&self.ssh_args),
+                Transport::Ssh => ssh::openssh_args(&self.ssh_args),
+            };
+            self.transport = transport;
+        }
+        self.windows_ssh = windows_ssh;
+        self.ssh_prog = match transport {
+            Transport::Plink => ssh::PLINK_PROG,
+            Transport::Ssh if windows_ssh && util::is_wsl() => {
+                for arg in self.ssh_args.iter_mut().filter(|a| *a == "-t") {
+                    *arg = "-tt".into();
+                }
+                self.debug_print("WSL: using Windows ssh.exe");
+                ssh::WINDOWS_SSH_PROG
+            }
+            Transport::Ssh => ssh::SSH_PROG,
+        }
+        .to_string();
+    }
+
+    /// Whether ssh invocations actually share a master connection: neither
+    /// Windows OpenSSH nor plink support ControlMaster
+    pub fn shares_connections(&self) -> bool {
+        self.control_master && self.transport == Transport::Ssh && self.ssh_prog != ssh::WINDOWS_SSH_PROG
+    }
+
+    /// The ssh program with its wrapper, if any, ahead of it, and the options
+    /// of the connection settings after it: the start of every ssh argv
+    pub fn ssh_argv(&self) -> Vec<String> {
+        let mut argv = self.ssh_wrapper.clone();
+        argv.push(self.ssh_prog.clone());
+        if self.transport == Transport::Ssh {
+            if self.quiet_login {
+                argv.extend(ssh::quiet_args());
+            }
+            if self.keepalive > 0 {
+                argv.extend(ssh::keepalive_args(self.keepalive));
+            }
+            if self.shares_connections() {
+                argv.extend(ssh::control_args(&self.control_persist));
+            }
+        }
+        argv
+    }
+
+    /// A process running ssh (through the wrapper, if any); ssh_args go after it
+    pub fn ssh_command(&self) -> std::process::Command {
+        let mut argv = self.ssh_argv();
+        let mut cmd = std::process::Command::new(argv.remove(0));
+        cmd.args(argv);
+        cmd
+    }
+
+    /// Never prompt for passwords or host keys and give up quickly on
+    /// unreachable hosts; for background work where nobody can answer
+    pub fn make_non_interactive(&mut self) {
+        if self.transport == Transport::Plink {
+            self.ssh_args.insert(0, "-batch".into());
+            return;
+        }
+        let opts = ["-o", "BatchMode=yes", "-o", "ConnectTimeout=5"];
+        self.ssh_args.splice(0..0, opts.iter().map(|s| s.to_string()));
+    }
+
+    /// The tmux binary with the server socket option, if any
+    fn tmux_invocation(&self) -> String {
+        match &self.tmux_socket {
+            Some(socket) => {
+                let flag = if socket.contains('/') { "-S" } else { "-L" };
+                format!("{} {} {}", self.tmux_bin, flag, util::shell_escape(socket))
+            }
+            None => self.tmux_bin.clone(),
+        }
+    }
+
+    /// tmux for commands whose output is captured: with --sudo through
+    /// `sudo -n`, since there is no terminal to ask for a password on
+    pub fn tmux_cmd(&self) -> String {
+        if self.sudo {
+            format!("sudo -n {}", self.tmux_invocation())
+        } else {
+            self.tmux_invocation()
+        }
+    }
+
+    /// tmux for commands run on a remote TTY, where sudo may prompt for a password
+    pub fn tty_tmux_cmd(&self) -> String {
+        if self.sudo {
+            format!("sudo -p {} {}", util::shell_escape(SUDO_PROMPT), self.tmux_invocation())
+        } else {
+            self.tmux_invocation()
+        }
+    }
+
+    /// A remote command as handed to ssh: with a login shell configured,
+    /// wrapped so profile-set PATH, modules, and environment apply
+    pub fn remote_command(&self, command: &str) -> String {
+        match &self.login_shell {
+            Some(shell) => format!("{} -lc {}", shell, util::shell_escape(command)),
+            None => command.to_string(),
+        }
+    }
+
+    /// Flag forcing TTY allocation for this config's client
+    pub fn tty_flag(&self) -> &'static str {
+        match self.transport {
+            Transport::Plink => "-t",
+            Transport::Ssh => ssh::TTY_FLAG,
+        }
+    }
+
+    /// Session used when none is named: the base name, by default suffixed
+    /// with the local user (or the --user) so several people can share an account
+    pub fn default_session_name(&self) -> String {
+        if self.session_user_suffix {
+            let user = self.target_user.as_deref().unwrap_or(&self.local_user);
+            self.session_template
+                .replace("{base}", &self.session)
+                .replace("{user}", user)
+        } else {
+            self.session.clone()
+        }
+    }
+
+    /// The user a session name belongs to under the session template, e.g.
+    /// "alice" for "default_alice" with "{base}_{user}"
+    pub fn session_owner(&self, name: &str) -> Option<String> {
+        match_template(&self.session_template, name).filter(|user| !user.is_empty())
+    }
+
+    /// Fail when read-only mode forbids `operation` (e.g. "--kill")
+    pub fn ensure_writable(&self, operation: &str) -> Result<()> {
+        if self.read_only {
+            return Err(anyhow!(
+                "{} is disabled in read-only mode (--read-only-ops, or read_only_ops or a host's read_only in {})",
+                operation,
+                FileConfig::path().display()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether this host is listed in protected_hosts (by bookmark name,
+    /// destination, or host name without the user)
+    pub fn host_protected(&self) -> bool {
+        let destination = self.destination();
+        let names = [self.host_alias.as_deref(), destination, destination.map(|d| d.rsplit('@').next().unwrap_or(d))];
+        self.protected_hosts
+            .iter()
+            .any(|pattern| names.iter().flatten().any(|name| util::wildcard_match(pattern, name)))
+    }
+
+    /// Whether killing `session` needs its name typed out: it is on a
+    /// protected host or matches protected_sessions
+    pub fn session_protected(&self, session: &str) -> bool {
+        self.host_protected() || self.protected_sessions.iter().any(|pattern| util::wildcard_match(pattern, session))
+    }
+
+    /// Owner of `name` when that is someone other than the local user
+    pub fn foreign_owner(&self, name: &str) -> Option<String> {
+        self.session_owner(name).filter(|owner| *owner != self.local_user)
+    }
+
+    /// Session a plain attach uses: a --session name verbatim, else the default
+    pub fn target_session(&self) -> String {
+        if self.session_provided {
+            self.session.clone()
+        } else {
+            self.default_session_name()
+        }
+    }
+
+    /// Name to show for the host: the bookmark name, else the destination
+    pub fn host_label(&self) -> &str {
+        self.host_alias
+            .as_deref()
+            .or_else(|| self.destination())
+            .unwrap_or("remote host")
+    }
+
+    /// The SSH destination (e.g. user@host), if one was given
+    pub fn destination(&self) -> Option<&str> {
+        ssh::destination(&self.ssh_args)
     }
 
     pub fn debug_print(&self, msg: &str) {
@@ -41,3 +451,459 @@ impl Config {
         }
     }
 }
+
+/// Default way the base session name and local user are combined
+pub const DEFAULT_SESSION_TEMPLATE: &str = "{base}_{user}";
+
+/// Password prompt sudo shows for --sudo (%u is the user, %h the host)
+const SUDO_PROMPT: &str = "[vigil] sudo password for %u@%h: ";
+
+/// Match `name` against a template of literal text, "{base}", and "{user}"
+/// (each standing for at least one character) and return the user part.
+/// Earlier placeholders take as much as they can, so with "{base}_{user}"
+/// the user is what follows the last underscore.
+fn match_template(template: &str, name: &str) -> Option<String> {
+    let Some(start) = template.find('{') else {
+        return (template == name).then(String::new);
+    };
+    let (literal, rest) = template.split_at(start);
+    let name = name.strip_prefix(literal)?;
+    let (placeholder, rest) = match rest.find('}') {
+        Some(end) => rest.split_at(end + 1),
+        None => return (rest == name).then(String::new),
+    };
+    let is_user = placeholder == "{user}";
+    // Longest first; the captured part must be non-empty
+    for split in (1..=name.len()).rev().filter(|&i| name.is_char_boundary(i)) {
+        let (part, remainder) = name.split_at(split);
+        if let Some(user) = match_template(rest, remainder) {
+            return Some(if is_user { part.to_string() } else { user });
+        }
+    }
+    None
+}
+
+/// Settings loaded from the user's config file (~/.config/vigil/config.toml)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FileConfig {
+    /// Colors used for status, errors, and highlighted names
+    pub theme: Theme,
+    /// Always behave as if --plain-prompts were given
+    pub plain_prompts: bool,
+    /// Always start tmux in UTF-8 mode (same as --utf8)
+    pub utf8: bool,
+    /// Always enable truecolor settings on created sessions (same as --truecolor)
+    pub truecolor: bool,
+    /// Always behave as if --linger were given
+    pub linger: bool,
+    /// Check for systemd KillUserProcesses when creating sessions
+    pub linger_check: bool,
+    /// Bookmarked hosts, keyed by the name used on the command line
+    pub hosts: BTreeMap<String, HostProfile>,
+    /// Short names for a host and session together, keyed by the name used on the command line
+    pub aliases: BTreeMap<String, SessionAlias>,
+    /// Working sets for `vigil day`, keyed by profile name
+    pub profiles: BTreeMap<String, DayProfile>,
+    /// Hosts addressed together as `@name`, keyed by group name
+    pub groups: BTreeMap<String, HostGroup>,
+    /// Bookmarks summarized by `vigil prompt-segment` (all bookmarks when empty)
+    pub prompt_hosts: Vec<String>,
+    /// Hosts (bookmark names or destinations, `*` wildcards) where kills need the name typed
+    pub protected_hosts: Vec<String>,
+    /// Sessions (`*` wildcards) whose kills need the name typed, on any host
+    pub protected_sessions: Vec<String>,
+    /// Always behave as if --read-only-ops were given; the command line cannot turn it off
+    pub read_only_ops: bool,
+    /// Reuse one SSH master connection per host (ControlMaster)
+    pub control_master: bool,
+    /// How long an idle master connection stays open (ssh ControlPersist)
+    pub control_persist: String,
+    /// Always behave as if --single-shot were given
+    pub single_shot: bool,
+    /// Always behave as if --quiet-login were given
+    pub quiet_login: bool,
+    /// Under WSL, run the Windows ssh.exe (Windows agent, Hello/PIN prompts)
+    pub windows_ssh: bool,
+    /// Client used to reach hosts: "ssh" (default) or "plink"
+    pub transport: Transport,
+    /// Always behave as if --password-auth were given
+    pub password_auth: bool,
+    /// Always behave as if --check-agent were given
+    pub check_agent: bool,
+    /// Always behave as if --require-healthy were given
+    pub require_healthy: bool,
+    /// Limits for --require-healthy and the warnings of `--list --verbose`
+    pub health: HealthLimits,
+    /// Retries for listing and other captured commands after ssh connection failures
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds; doubled for each further one
+    pub retry_backoff_ms: u64,
+    /// Base name of the default session (same as --session without the verbatim naming)
+    pub session: Option<String>,
+    /// Append "_<local user>" to the default session name
+    pub session_user_suffix: bool,
+    /// How the default session name is formed from {base} and {user}
+    pub session_template: String,
+    /// Seconds between ssh keepalive probes (ServerAliveInterval); 0 disables
+    pub keepalive: u32,
+    /// Warn when the remote tmux is older than this (e.g. "2.1"); empty skips the check
+    pub min_tmux_version: String,
+    /// Run remote commands through this shell as a login shell (e.g. "bash")
+    pub login_shell: Option<String>,
+    /// Local key that detaches from tmux without the remote prefix (e.g. "ctrl-\\")
+    pub detach_key: Option<String>,
+    /// Always behave as if --idle-detach were given with this duration (e.g. "2h")
+    pub idle_detach: Option<String>,
+    /// Warn about your sessions idle longer than this (e.g. "14d") when listing or attaching
+    pub stale_after: Option<String>,
+    /// Always behave as if --force-size were given
+    pub force_size: bool,
+    /// Always behave as if --paced-paste were given
+    pub paced_paste: bool,
+    /// Bytes per chunk of a paced paste
+    pub paste_chunk: usize,
+    /// Pause between chunks of a paced paste, in milliseconds
+    pub paste_delay_ms: u64,
+    /// Outside tmux, `vigil open` starts each session with this command (e.g. "kitty --")
+    pub open_terminal: Option<String>,
+    /// Where else to look for tmux when it is not on the remote non-interactive PATH
+    pub tmux_fallbacks: Vec<String>,
+    /// ssh allowed_signers file with the release key; `vigil self-update` installs nothing without it
+    pub release_signers: Option<PathBuf>,
+}
+
+/// A bookmarked host from a `[hosts.<name>]` table of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HostProfile {
+    /// SSH destination (e.g. user@host); defaults to the bookmark name
+    pub destination: Option<String>,
+    /// Extra ssh arguments placed before the destination
+    pub ssh_args: Vec<String>,
+    /// Overrides the top-level `windows_ssh` setting for this host
+    pub windows_ssh: Option<bool>,
+    /// Overrides the top-level `quiet_login` setting for this host (false keeps a required banner)
+    pub quiet_login: Option<bool>,
+    /// Overrides the top-level `transport` setting for this host
+    pub transport: Option<Transport>,
+    /// Expected host key fingerprint (e.g. "SHA256:..."); connecting is refused on mismatch
+    pub fingerprint: Option<String>,
+    /// tmux binary on this host (e.g. "~/bin/tmux"), unless --tmux is given
+    pub tmux_bin: Option<String>,
+    /// Extra arguments for tmux new-session on this host, before any --tmux-arg
+    pub tmux_args: Vec<String>,
+    /// tmux server socket on this host: a name (tmux -L) or a path (tmux -S)
+    pub socket: Option<String>,
+    /// Overrides the top-level `login_shell` setting for this host ("" turns it off)
+    pub login_shell: Option<String>,
+    /// Start new sessions inside a Slurm allocation on this host (`[hosts.<name>.slurm]`)
+    pub slurm: Option<slurm::Allocation>,
+    /// Start new sessions in this host's nix development shell (`[hosts.<name>.nix]`)
+    pub nix: Option<nix::DevShell>,
+    /// Lmod / Environment Modules loaded when a session is created on this host, e.g. ["gcc/12"]
+    pub modules: Vec<String>,
+    /// Conda environment name or virtualenv directory activated in new sessions and windows on this host
+    pub activate: Option<String>,
+    /// Refuse kills and other destructive operations on this host
+    pub read_only: bool,
+    /// Sessions `vigil schedule` recreates after a reboot (`[[hosts.<name>.sessions]]`)
+    pub sessions: Vec<SessionTemplate>,
+}
+
+/// An entry of the `[aliases]` table, e.g. `pg = { host = "db1", session = "psql" }`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionAlias {
+    /// Destination or bookmark name
+    pub host: String,
+    /// Session name, used verbatim
+    pub session: String,
+}
+
+/// A `[profiles.<name>]` table: sessions `vigil day <name>` stands up together
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DayProfile {
+    /// HOST:SESSION, HOST (its default session), or an alias, as for `vigil open`
+    pub targets: Vec<String>,
+    /// Also open each session in a local tmux window (or terminal)
+    pub open: bool,
+}
+
+/// A `[groups.<name>]` table: hosts that `@name` stands for
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HostGroup {
+    /// Bookmark names or destinations
+    pub hosts: Vec<String>,
+}
+
+/// Per-project defaults from a `.vigil.toml` (or the VIGIL_* exports of a
+/// direnv `.envrc`) in the working directory or one of its parents
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    /// Destination or bookmark name, like VIGIL_HOST
+    pub host: Option<String>,
+    /// Session name, used verbatim like VIGIL_SESSION
+    pub session: Option<String>,
+    /// File the settings came from
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+impl ProjectConfig {
+    pub const FILE: &'static str = ".vigil.toml";
+
+    /// The nearest project config at or above `dir`. A `.vigil.toml` always
+    /// counts; an `.envrc` only when it sets VIGIL_HOST or VIGIL_SESSION.
+    pub fn find(dir: &Path) -> Result<Option<Self>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(Self::FILE);
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                let mut project: Self = toml::from_str(&text)
+                    .with_context(|| format!("invalid project file {}", path.display()))?;
+                project.path = Some(path);
+                return Ok(Some(project));
+            }
+            let path = dir.join(".envrc");
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                let project = Self::from_envrc(&text);
+                if project.host.is_some() || project.session.is_some() {
+                    return Ok(Some(Self { path: Some(path), ..project }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pick up plain `export VIGIL_HOST=...` style assignments, for shells
+    /// where direnv has not loaded the file
+    fn from_envrc(text: &str) -> Self {
+        let mut project = Self::default();
+        for line in text.lines() {
+            let line = line.trim();
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let Some((name, value)) = line.split_once('=') else { continue };
+            let value = shell_words::split(value).ok().and_then(|words| words.into_iter().next());
+            match name.trim() {
+                "VIGIL_HOST" => project.host = value,
+                "VIGIL_SESSION" => project.session = value,
+                _ => {}
+            }
+        }
+        project
+    }
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        FileConfig {
+            theme: Theme::default(),
+            plain_prompts: false,
+            utf8: false,
+            truecolor: false,
+            linger: false,
+            linger_check: true,
+            hosts: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            prompt_hosts: Vec::new(),
+            protected_hosts: Vec::new(),
+            protected_sessions: Vec::new(),
+            read_only_ops: false,
+            control_master: false,
+            control_persist: "10m".into(),
+            single_shot: false,
+            quiet_login: false,
+            windows_ssh: false,
+            transport: Transport::Ssh,
+            password_auth: false,
+            check_agent: false,
+            require_healthy: false,
+            health: HealthLimits::default(),
+            retries: 2,
+            retry_backoff_ms: 500,
+            session: None,
+            session_user_suffix: true,
+            session_template: DEFAULT_SESSION_TEMPLATE.into(),
+            keepalive: 0,
+            min_tmux_version: "1.8".into(),
+            login_shell: None,
+            detach_key: None,
+            idle_detach: None,
+            stale_after: None,
+            force_size: false,
+            paced_paste: false,
+            paste_chunk: 256,
+            paste_delay_ms: 20,
+            open_terminal: None,
+            tmux_fallbacks: ["/usr/local/bin/tmux", "~/.local/bin/tmux", "/opt/homebrew/bin/tmux"]
+                .map(String::from)
+                .to_vec(),
+            release_signers: None,
+        }
+    }
+}
+
+impl FileConfig {
+    /// Path of the config file
+    pub fn path() -> PathBuf {
+        util::config_dir().join("config.toml")
+    }
+
+    /// The hosts `target` stands for: a group's members for `@name`, else
+    /// the target itself
+    pub fn expand_group(&self, target: &str) -> Result<Vec<String>> {
+        let Some(name) = target.strip_prefix('@') else {
+            return Ok(vec![target.to_string()]);
+        };
+        match self.groups.get(name) {
+            Some(group) if !group.hosts.is_empty() => Ok(group.hosts.clone()),
+            Some(_) => Err(anyhow!("host group '{}' has no hosts", name)),
+            None => Err(anyhow!("no host group '{}' in {}", name, Self::path().display())),
+        }
+    }
+
+    /// Load the config file, returning defaults when it does not exist
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        toml::from_str(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_config(tmux_bin: &str) -> Config {
+        let ssh_args = vec!["-t".to_string(), "box".to_string()];
+        Config::new("default".into(), false, tmux_bin.into(), String::new(), "ssh".into(), ssh_args, "me".into(), false)
+    }
+
+    fn profile(toml: &str) -> HostProfile {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn host_profile_sets_tmux_binary_socket_and_args() {
+        let mut config = base_config("tmux");
+        config.tmux_extra_args = vec!["-x".into(), "200".into()];
+        config.apply_host("box", &profile(r#"tmux_bin = "~/bin/tmux"
+socket = "worksocket"
+tmux_args = ["-c", "/srv"]"#));
+
+        assert_eq!(config.tmux_bin, "~/bin/tmux");
+        assert_eq!(config.tmux_cmd(), "~/bin/tmux -L 'worksocket'");
+        assert_eq!(config.tmux_extra_args, ["-c", "/srv", "-x", "200"]);
+    }
+
+    #[test]
+    fn command_line_tmux_binary_beats_host_profile() {
+        let mut config = base_config("/opt/tmux");
+        config.tmux_bin_provided = true;
+        config.apply_host("box", &profile(r#"tmux_bin = "~/bin/tmux""#));
+        assert_eq!(config.tmux_cmd(), "/opt/tmux");
+
+        // Without a profile value the default stays
+        let mut config = base_config("tmux");
+        config.apply_host("box", &HostProfile::default());
+        assert_eq!(config.tmux_cmd(), "tmux");
+        assert!(config.tmux_extra_args.is_empty());
+    }
+
+    #[test]
+    fn ssh_options_stay_behind_a_wrapper() {
+        let mut config = base_config("tmux");
+        config.ssh_wrapper = vec!["sshpass".into(), "-e".into()];
+        config.make_non_interactive();
+        assert_eq!(config.ssh_argv(), ["sshpass", "-e", "ssh"]);
+        assert_eq!(config.ssh_args, ["-o", "BatchMode=yes", "-o", "ConnectTimeout=5", "-t", "box"]);
+        let cmd = config.ssh_command();
+        assert_eq!(cmd.get_program(), "sshpass");
+        assert_eq!(cmd.get_args().collect::<Vec<_>>(), ["-e", "ssh"]);
+    }
+
+    #[test]
+    fn host_profile_switches_the_client_and_connection_options() {
+        let mut config = base_config("tmux");
+        config.control_master = true;
+        config.quiet_login = true;
+        assert!(config.ssh_argv().contains(&"LogLevel=ERROR".to_string()));
+        assert!(config.ssh_argv().iter().any(|arg| arg.starts_with("ControlPath=")));
+
+        let mut plink = config.clone();
+        plink.apply_host("win", &profile(r#"transport = "plink"
+ssh_args = ["-p", "2222"]"#));
+        assert_eq!(plink.ssh_argv(), [ssh::PLINK_PROG]);
+        assert_eq!(plink.ssh_args, ["-ssh", "-no-antispoof", "-t", "-P", "2222", "win"]);
+        assert!(!plink.shares_connections());
+
+        // --quiet-login beats the bookmark; otherwise a bookmark can keep its banner
+        config.apply_host("box", &profile("quiet_login = false"));
+        assert!(!config.ssh_argv().contains(&"LogLevel=ERROR".to_string()));
+        let mut forced = base_config("tmux");
+        forced.quiet_login = true;
+        forced.quiet_login_provided = true;
+        forced.apply_host("box", &profile("quiet_login = false"));
+        assert!(forced.quiet_login);
+
+        // --transport beats the bookmark, and plink arguments go back to OpenSSH ones
+        let mut flag = base_config("tmux");
+        flag.set_client(Transport::Plink, false);
+        flag.transport_provided = true;
+        flag.apply_host("win", &profile(r#"transport = "ssh""#));
+        assert_eq!(flag.transport, Transport::Plink);
+        plink.apply_host("win", &profile(r#"transport = "ssh""#));
+        assert_eq!(plink.ssh_args, ["-t", "-p", "2222", "win"]);
+        assert_eq!(plink.ssh_prog, ssh::SSH_PROG);
+    }
+
+    #[test]
+    fn login_shell_wraps_remote_commands_unless_the_host_turns_it_off() {
+        let mut config = base_config("tmux");
+        config.login_shell = Some("bash".into());
+        assert_eq!(config.remote_command("tmux ls"), "bash -lc 'tmux ls'");
+
+        config.apply_host("box", &profile(r#"login_shell = """#));
+        assert_eq!(config.remote_command("tmux ls"), "tmux ls");
+    }
+
+    #[test]
+    fn socket_path_uses_dash_s_and_sudo_wraps_it() {
+        let mut config = base_config("tmux");
+        config.sudo = true;
+        config.apply_host("box", &profile(r#"socket = "/run/tmux/shared""#));
+        assert_eq!(config.tmux_cmd(), "sudo -n tmux -S '/run/tmux/shared'");
+        assert!(config.tty_tmux_cmd().ends_with(" tmux -S '/run/tmux/shared'"));
+    }
+
+    #[test]
+    fn protected_hosts_and_sessions_match_wildcards() {
+        let mut config = base_config("tmux");
+        config.protected_sessions = vec!["prod-*".into()];
+        assert!(config.session_protected("prod-db"));
+        assert!(!config.session_protected("dev-db"));
+
+        config.protected_hosts = vec!["bastion*".into()];
+        assert!(!config.host_protected());
+        config.ssh_args = vec!["-t".into(), "ops@bastion-eu".into()];
+        assert!(config.host_protected());
+        assert!(config.session_protected("scratch"));
+    }
+
+    #[test]
+    fn group_references_expand_to_their_hosts() {
+        let file: FileConfig = toml::from_str("[groups.web]\nhosts = [\"web1\", \"ops@web2\"]\n[groups.none]").unwrap();
+        assert_eq!(file.expand_group("@web").unwrap(), ["web1", "ops@web2"]);
+        assert_eq!(file.expand_group("me@db1").unwrap(), ["me@db1"]);
+        assert!(file.expand_group("@none").is_err());
+        assert!(file.expand_group("@db").is_err());
+    }
+}
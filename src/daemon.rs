@@ -0,0 +1,157 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+use crate::cache;
+use crate::config::{Config, FileConfig};
+use crate::endpoint;
+use crate::ssh;
+use crate::ui;
+use crate::util;
+
+/// Run in the foreground, keeping a master connection open to every
+/// bookmarked host and refreshing the session cache every `interval` seconds.
+/// Meant to be run under a service manager (systemd --user, launchd) or `&`.
+/// With `listen`, editor plugins can also reach it on [`endpoint::path`];
+/// with `metrics`, the file is rewritten after every round.
+pub fn run(
+    config: &Config,
+    file: &FileConfig,
+    interval: u64,
+    notify: bool,
+    listen: bool,
+    metrics: Option<&Path>,
+) -> Result<()> {
+    if !config.shares_connections() {
+        return Err(anyhow!(
+            "the daemon needs shared connections; set `control_master = true` in {}",
+            FileConfig::path().display()
+        ));
+    }
+    if file.hosts.is_empty() {
+        return Err(anyhow!("no hosts bookmarked in {}", FileConfig::path().display()));
+    }
+
+    if listen {
+        let path = endpoint::serve(config.clone(), file.clone())?;
+        ui::status(&format!("Listening for editor requests on {}", path.display()));
+    }
+    ui::status(&format!(
+        "Daemon watching {} host(s), refreshing every {}s.",
+        file.hosts.len(),
+        interval
+    ));
+    let mut stats: BTreeMap<String, HostStats> = BTreeMap::new();
+    loop {
+        for (name, profile) in &file.hosts {
+            let mut host_config = config.clone();
+            host_config.apply_host(name, profile);
+            host_config.make_non_interactive();
+            tick(&host_config, name, notify, stats.entry(name.clone()).or_default());
+        }
+        if let Some(path) = metrics {
+            if let Err(e) = write_metrics(path, &stats) {
+                ui::warn(&format!("failed to write metrics to {}: {:#}", path.display(), e));
+            }
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+/// What the daemon has seen of one host, for --metrics
+#[derive(Debug, Default)]
+struct HostStats {
+    /// Whether the last refresh succeeded
+    up: bool,
+    /// Sessions, and those without an attached client, at the last refresh
+    sessions: usize,
+    detached: usize,
+    /// Time of the last successful refresh (Unix seconds)
+    refreshed: u64,
+    /// Failed master connections and refreshes
+    failures: u64,
+    /// Master connections started after the first one
+    reconnects: u64,
+    masters_started: u64,
+    /// Session names at the last successful refresh; None until there was
+    /// one, so the first refresh only establishes a baseline
+    known: Option<BTreeSet<String>>,
+}
+
+/// One refresh of a single host: ensure the master, then update the cache
+fn tick(config: &Config, name: &str, notify: bool, stats: &mut HostStats) {
+    stats.up = false;
+    if !ssh::check_master(config) {
+        config.debug_print(&format!("starting master connection to {}", name));
+        if stats.masters_started > 0 {
+            stats.reconnects += 1;
+        }
+        stats.masters_started += 1;
+        if let Err(e) = ssh::start_master(config) {
+            stats.failures += 1;
+            ui::warn(&format!("{}: {:#}", name, e));
+            return;
+        }
+    }
+
+    let after: BTreeSet<String> = match cache::refresh_host(config, name) {
+        Ok(sessions) => {
+            stats.up = true;
+            stats.sessions = sessions.len();
+            stats.detached = sessions.iter().filter(|s| s.attached == 0).count();
+            stats.refreshed = util::unix_now();
+            sessions.into_iter().map(|s| s.name).collect()
+        }
+        Err(e) => {
+            stats.failures += 1;
+            ui::warn(&format!("{}: {:#}", name, e));
+            return;
+        }
+    };
+
+    let Some(before) = stats.known.replace(after.clone()) else {
+        return;
+    };
+    for gone in before.difference(&after) {
+        announce(notify, &format!("Session '{}' on {} ended", gone, name));
+    }
+    for new in after.difference(&before) {
+        announce(notify, &format!("Session '{}' started on {}", new, name));
+    }
+}
+
+/// Write the stats in the Prometheus text format, replacing `path` atomically
+/// so a scraper (node_exporter's textfile collector) never sees half a file
+fn write_metrics(path: &Path, stats: &BTreeMap<String, HostStats>) -> Result<()> {
+    type Metric = (&'static str, &'static str, &'static str, fn(&HostStats) -> u64);
+    const METRICS: [Metric; 6] = [
+        ("vigil_host_up", "gauge", "Whether the last refresh of the host succeeded", |s| s.up as u64),
+        ("vigil_sessions", "gauge", "tmux sessions on the host", |s| s.sessions as u64),
+        ("vigil_sessions_detached", "gauge", "tmux sessions with no attached client", |s| s.detached as u64),
+        ("vigil_last_refresh_timestamp_seconds", "gauge", "When the host was last refreshed", |s| s.refreshed),
+        ("vigil_connection_failures_total", "counter", "Failed master connections and refreshes", |s| s.failures),
+        ("vigil_reconnects_total", "counter", "Master connections re-established after dropping", |s| s.reconnects),
+    ];
+
+    let mut text = String::new();
+    for (metric, kind, help, value) in METRICS {
+        let _ = writeln!(text, "# HELP {} {}\n# TYPE {} {}", metric, help, metric, kind);
+        for (host, stats) in stats {
+            let host = host.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+            let _ = writeln!(text, "{}{{host=\"{}\"}} {}", metric, host, value(stats));
+        }
+    }
+
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn announce(notify: bool, msg: &str) {
+    ui::status(msg);
+    if notify {
+        ui::desktop_notify(msg);
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use clap_mangen::Man;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use crate::cli::Cli;
+use crate::ui;
+
+/// The CLI definition with subcommand names (e.g. "vigil win list") filled in
+fn command() -> clap::Command {
+    let mut cmd = Cli::command();
+    cmd.build();
+    cmd
+}
+
+/// Subcommands worth documenting (not clap's generated `help`)
+fn documented(cmd: &clap::Command) -> impl Iterator<Item = &clap::Command> {
+    cmd.get_subcommands().filter(|sub| sub.get_name() != "help" && !sub.is_hide_set())
+}
+
+/// Write vigil.1 plus one page per subcommand (vigil-win-list.1, ...) into
+/// `dir`, or print the top-level page when no directory is given
+pub fn man_pages(dir: Option<&Path>) -> Result<()> {
+    let cmd = command();
+    match dir {
+        Some(dir) => {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+            let count = write_man_pages(&cmd, dir)?;
+            ui::status(&format!("Wrote {} man pages to {}.", count, dir.display()));
+        }
+        None => std::io::stdout().write_all(&render_man(&cmd)?)?,
+    }
+    Ok(())
+}
+
+fn render_man(cmd: &clap::Command) -> Result<Vec<u8>> {
+    let mut page = Vec::new();
+    // Subcommands carry no version of their own; every page names the release
+    Man::new(cmd.clone())
+        .source(format!("vigil {}", env!("CARGO_PKG_VERSION")))
+        .manual("vigil manual")
+        .render(&mut page)?;
+    Ok(page)
+}
+
+fn write_man_pages(cmd: &clap::Command, dir: &Path) -> Result<usize> {
+    let name = cmd.get_display_name().unwrap_or_else(|| cmd.get_name());
+    let path = dir.join(format!("{}.1", name));
+    fs::write(&path, render_man(cmd)?).with_context(|| format!("failed to write {}", path.display()))?;
+
+    let mut count = 1;
+    for sub in documented(cmd) {
+        count += write_man_pages(sub, dir)?;
+    }
+    Ok(count)
+}
+
+/// Markdown reference of every command, one section of full help text each
+pub fn markdown() -> String {
+    let cmd = command();
+    let mut out = String::from("# vigil command reference\n\nGenerated by `vigil gen-docs`; do not edit.\n");
+    append_markdown(&cmd, &mut out);
+    out
+}
+
+fn append_markdown(cmd: &clap::Command, out: &mut String) {
+    let name = cmd.get_bin_name().unwrap_or_else(|| cmd.get_name());
+    *out += &format!("\n## {}\n\n", name);
+    let help = cmd.clone().render_long_help().to_string();
+    *out += &format!("```text\n{}\n```\n", help.trim_end());
+    for sub in documented(cmd) {
+        append_markdown(sub, out);
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use crate::audit;
+use crate::cache;
+use crate::config::{Config, FileConfig};
+use crate::lock;
+use crate::tmux;
+use crate::util;
+
+/// Socket a `vigil daemon --listen` accepts editor requests on:
+/// $XDG_RUNTIME_DIR/vigil/daemon.sock, or the state directory without one
+pub fn path() -> PathBuf {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .filter(|v| !v.is_empty())
+        .map(|dir| PathBuf::from(dir).join("vigil"))
+        .unwrap_or_else(util::state_dir);
+    dir.join("daemon.sock")
+}
+
+/// One request line from a client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+enum Request {
+    /// Bookmarked hosts
+    Hosts,
+    /// Sessions on a host, fetched fresh (which also refreshes the cache)
+    List { host: String },
+    /// Kill a session; sessions of other users need "force", and protected
+    /// ones "confirm" with the session name
+    Kill {
+        host: String,
+        session: String,
+        #[serde(default)]
+        force: bool,
+        confirm: Option<String>,
+    },
+    /// The command to run in a terminal to attach (the daemon has no terminal)
+    Attach { host: String, session: Option<String> },
+}
+
+/// Answer one request line with one response line
+fn respond(config: &Config, file: &FileConfig, line: &str) -> Value {
+    let result = serde_json::from_str::<Request>(line)
+        .map_err(|e| anyhow!("invalid request: {}", e))
+        .and_then(|request| handle(config, file, request));
+    match result {
+        Ok(Value::Object(mut fields)) => {
+            fields.insert("ok".into(), true.into());
+            Value::Object(fields)
+        }
+        Ok(other) => json!({ "ok": true, "result": other }),
+        Err(e) => json!({ "ok": false, "error": format!("{:#}", e) }),
+    }
+}
+
+fn handle(config: &Config, file: &FileConfig, request: Request) -> Result<Value> {
+    match request {
+        Request::Hosts => {
+            let hosts: Vec<Value> = file
+                .hosts
+                .iter()
+                .map(|(name, profile)| json!({ "name": name, "destination": profile.destination.as_deref().unwrap_or(name) }))
+                .collect();
+            Ok(json!({ "hosts": hosts }))
+        }
+        Request::List { host } => {
+            let config = host_config(config, file, &host, false)?;
+            let sessions = cache::refresh_host(&config, &host)?;
+            Ok(json!({ "sessions": sessions }))
+        }
+        Request::Kill { host, session, force, confirm } => {
+            let config = host_config(config, file, &host, false)?;
+            config.ensure_writable("kill")?;
+            if config.session_protected(&session) && confirm.as_deref() != Some(session.as_str()) {
+                return Err(anyhow!("'{}' is protected; send \"confirm\" with the session name to kill it", session));
+            }
+            if let Some(owner) = config.foreign_owner(&session).filter(|_| !force) {
+                return Err(anyhow!("'{}' belongs to {}; send \"force\": true to kill it anyway", session, owner));
+            }
+            let _lock = lock::HostLock::acquire(&config, false)?;
+            let started = util::unix_now();
+            tmux::kill_remote_session(&config, &session)?;
+            audit::log(&config, "kill", &session, started, None);
+            Ok(json!({}))
+        }
+        Request::Attach { host, session } => {
+            let config = host_config(config, file, &host, true)?;
+            let session = session.unwrap_or_else(|| config.target_session());
+            let mut argv = config.ssh_argv();
+            argv.extend(tmux::build_attach_command(&config, &session));
+            Ok(json!({ "session": session, "argv": argv }))
+        }
+    }
+}
+
+/// The daemon's config pointed at a bookmarked host
+fn host_config(config: &Config, file: &FileConfig, host: &str, interactive: bool) -> Result<Config> {
+    let profile = file.hosts.get(host).ok_or_else(|| anyhow!("'{}' is not a bookmarked host", host))?;
+    let mut config = config.clone();
+    config.apply_host(host, profile);
+    if !interactive {
+        config.make_non_interactive();
+    }
+    Ok(config)
+}
+
+/// Listen on [`path`] in the background, one thread per client. Each client
+/// sends JSON requests one per line and gets one JSON line back for each.
+#[cfg(unix)]
+pub fn serve(config: Config, file: FileConfig) -> Result<PathBuf> {
+    use anyhow::Context;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::net::UnixListener;
+    use std::sync::Arc;
+
+    let path = path();
+    if let Some(dir) = path.parent() {
+        // Private directory: whoever can reach the socket can kill sessions
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    // A socket left behind by a daemon that did not exit cleanly
+    if std::os::unix::net::UnixStream::connect(&path).is_err() {
+        let _ = std::fs::remove_file(&path);
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to listen on {} (is another daemon running?)", path.display()))?;
+
+    let shared = Arc::new((config, file));
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let shared = Arc::clone(&shared);
+            std::thread::spawn(move || {
+                let (config, file) = &*shared;
+                let Ok(reader) = stream.try_clone() else { return };
+                let mut writer = stream;
+                for line in BufReader::new(reader).lines() {
+                    let Ok(line) = line else { break };
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let response = respond(config, file, &line);
+                    if writeln!(writer, "{}", response).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(path)
+}
+
+#[cfg(not(unix))]
+pub fn serve(_config: Config, _file: FileConfig) -> Result<PathBuf> {
+    Err(anyhow!("the daemon's editor socket needs Unix domain sockets"))
+}
@@ -0,0 +1,41 @@
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::timings;
+use crate::util;
+
+/// Format of the --events stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EventFormat {
+    /// One JSON object per line
+    Jsonl,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Write lifecycle events to stderr from now on (--events)
+pub fn enable(format: EventFormat) {
+    match format {
+        EventFormat::Jsonl => ENABLED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Emit one event, e.g. `emit("attached", json!({"session": name}))`. Every
+/// line carries "event" and "time" (Unix seconds) besides the given fields.
+pub fn emit(event: &str, fields: Value) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut line = json!({ "event": event, "time": util::unix_now() });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    eprintln!("{}", line);
+}
+
+/// End the process with `code`, emitting "exited" with it first as a normal
+/// return from main would
+pub fn exit(code: i32) -> ! {
+    timings::report();
+    emit("exited", json!({ "code": code }));
+    std::process::exit(code)
+}
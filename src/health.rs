@@ -0,0 +1,219 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::fmt;
+use crate::config::Config;
+use crate::ssh;
+use crate::util;
+
+/// Marks the summary line in the output of a session listing
+const MARK: &str = "@@vigil-health";
+
+/// Shell snippet printing one tab-separated line: `uptime`, the CPU count,
+/// the remote clock, and the df lines for $HOME and /. Errors are dropped so
+/// a missing tool only blanks its field, and it always succeeds so the
+/// listing after it decides the exit status.
+pub fn command() -> String {
+    format!(
+        "{{ printf '{}\\t%s\\t%s\\t%s\\t%s\\t%s\\n' \"$(uptime)\" \"$(getconf _NPROCESSORS_ONLN)\" \"$(date +%s)\" \
+         \"$(df -Pk \"$HOME\" | tail -n 1)\" \"$(df -Pk / | tail -n 1)\"; }} 2>/dev/null; ",
+        MARK
+    )
+}
+
+/// Fetch the summary on its own
+pub fn fetch(config: &Config) -> Result<HostHealth> {
+    let output = ssh::exec_remote_capture(config, &command())?.into_stdout()?;
+    Ok(split(&output).1.unwrap_or_default())
+}
+
+/// Thresholds past which a host counts as unhealthy, configurable via the
+/// `[health]` table of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HealthLimits {
+    /// Highest acceptable use of / or $HOME's filesystem, in percent
+    pub max_disk_percent: u32,
+    /// Highest acceptable 1-minute load average per CPU
+    pub max_load_per_cpu: f64,
+    /// Largest acceptable difference between the remote and local clocks, in seconds
+    pub max_clock_skew: u64,
+}
+
+impl Default for HealthLimits {
+    fn default() -> Self {
+        HealthLimits { max_disk_percent: 95, max_load_per_cpu: 2.0, max_clock_skew: 60 }
+    }
+}
+
+/// Use of one filesystem, from a `df -P` line
+#[derive(Debug, PartialEq)]
+pub struct Disk {
+    pub mount: String,
+    /// Percentage in use
+    pub used: u32,
+    /// Free space in KiB
+    pub free_kb: u64,
+}
+
+/// Uptime, load, clock, and disk space of a remote host
+#[derive(Debug, Default, PartialEq)]
+pub struct HostHealth {
+    /// As `uptime` words it, e.g. "up 3 days, 2:01"
+    pub uptime: Option<String>,
+    /// 1, 5, and 15 minute load averages
+    pub load: Option<[f64; 3]>,
+    pub cpus: Option<u32>,
+    /// Remote clock minus local clock, in seconds
+    pub clock_skew: Option<i64>,
+    /// The filesystems holding $HOME and /, once each
+    pub disks: Vec<Disk>,
+}
+
+impl HostHealth {
+    /// Each metric past its limit, worded for the user
+    pub fn problems(&self, limits: &HealthLimits) -> Vec<String> {
+        let mut problems = Vec::new();
+        for disk in self.disks.iter().filter(|disk| disk.used >= limits.max_disk_percent) {
+            problems.push(format!("{} is {}% full ({} free)", disk.mount, disk.used, human_kb(disk.free_kb)));
+        }
+        if let (Some([load, ..]), Some(cpus)) = (self.load, self.cpus) {
+            if load > limits.max_load_per_cpu * cpus as f64 {
+                problems.push(format!("load {:.2} on {} CPUs (limit {} per CPU)", load, cpus, limits.max_load_per_cpu));
+            }
+        }
+        if let Some(skew) = self.clock_skew.filter(|skew| skew.unsigned_abs() > limits.max_clock_skew) {
+            let direction = if skew > 0 { "ahead" } else { "behind" };
+            problems.push(format!("clock is {} {}", util::format_duration(skew.unsigned_abs()), direction));
+        }
+        problems
+    }
+}
+
+impl fmt::Display for HostHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(uptime) = &self.uptime {
+            parts.push(uptime.clone());
+        }
+        if let Some([one, five, fifteen]) = self.load {
+            let cpus = self.cpus.map(|n| format!(" ({} CPUs)", n)).unwrap_or_default();
+            parts.push(format!("load {:.2} {:.2} {:.2}{}", one, five, fifteen, cpus));
+        }
+        for disk in &self.disks {
+            parts.push(format!("{} {}% used, {} free", disk.mount, disk.used, human_kb(disk.free_kb)));
+        }
+        if parts.is_empty() {
+            return write!(f, "no host summary");
+        }
+        write!(f, "{}", parts.join(" · "))
+    }
+}
+
+/// Take the summary line out of `output`, returning the rest and the parsed summary
+pub fn split(output: &str) -> (String, Option<HostHealth>) {
+    let mut health = None;
+    let mut rest = String::new();
+    for line in output.lines() {
+        match line.strip_prefix(MARK).and_then(|rest| rest.strip_prefix('\t')) {
+            Some(fields) => health = Some(parse(fields, util::unix_now())),
+            None => {
+                rest += line;
+                rest.push('\n');
+            }
+        }
+    }
+    (rest, health)
+}
+
+/// Parse the fields after the marker, received at local time `now`
+fn parse(fields: &str, now: u64) -> HostHealth {
+    let mut fields = fields.split('\t');
+    let (uptime, load) = fields.next().map(parse_uptime).unwrap_or_default();
+    let cpus = fields.next().and_then(|n| n.trim().parse().ok());
+    let clock_skew = fields.next().and_then(|t| t.trim().parse::<i64>().ok()).map(|t| t - now as i64);
+    let mut disks: Vec<Disk> = Vec::new();
+    for disk in fields.filter_map(parse_df) {
+        if !disks.iter().any(|d| d.mount == disk.mount) {
+            disks.push(disk);
+        }
+    }
+    HostHealth { uptime, load, cpus, clock_skew, disks }
+}
+
+/// A `df -Pk` line: Filesystem 1024-blocks Used Available Capacity Mounted-on
+fn parse_df(line: &str) -> Option<Disk> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [_, _, _, avail, capacity, mount @ ..] = words.as_slice() else { return None };
+    Some(Disk {
+        mount: mount.join(" "),
+        used: capacity.trim_end_matches('%').parse().ok()?,
+        free_kb: avail.parse().ok()?,
+    })
+}
+
+/// The "up ..." part and the load averages of an `uptime` line, in the
+/// wordings of Linux ("load average: 0.00, 0.01, 0.05"), BSD and macOS
+/// ("load averages: 1.95 2.01 2.03"), and BusyBox (no user count)
+fn parse_uptime(line: &str) -> (Option<String>, Option<[f64; 3]>) {
+    let (before, after) = match line.find("load average") {
+        Some(at) => (&line[..at], Some(&line[at..])),
+        None => (line, None),
+    };
+    let uptime = before.find("up ").map(|at| {
+        let parts = before[at..].split(',').map(str::trim).filter(|p| !p.is_empty() && !p.contains("user"));
+        parts.map(|p| p.split_whitespace().collect::<Vec<_>>().join(" ")).collect::<Vec<_>>().join(", ")
+    });
+    let load = after.and_then(|after| {
+        let numbers = after.split_once(':')?.1;
+        let mut values = numbers.split([',', ' ']).filter_map(|n| n.trim().parse().ok());
+        Some([values.next()?, values.next()?, values.next()?])
+    });
+    (uptime, load)
+}
+
+/// KiB as a short size, e.g. "812M", "21.9G"
+fn human_kb(kb: u64) -> String {
+    match kb {
+        0..=1023 => format!("{}K", kb),
+        1024..=1_048_575 => format!("{}M", kb / 1024),
+        _ => format!("{:.1}G", kb as f64 / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_wordings_are_parsed() {
+        let linux = " 13:05:54 up 3 days,  2:01,  1 user,  load average: 0.00, 0.01, 0.05";
+        assert_eq!(parse_uptime(linux), (Some("up 3 days, 2:01".into()), Some([0.0, 0.01, 0.05])));
+        let mac = "13:05  up 12 mins, 2 users, load averages: 1.95 2.01 2.03";
+        assert_eq!(parse_uptime(mac), (Some("up 12 mins".into()), Some([1.95, 2.01, 2.03])));
+        let busybox = " 13:05:54 up 5 min,  load average: 0.10, 0.20, 0.30";
+        assert_eq!(parse_uptime(busybox), (Some("up 5 min".into()), Some([0.1, 0.2, 0.3])));
+    }
+
+    #[test]
+    fn summary_line_is_split_from_the_listing() {
+        let output = "@@vigil-health\t 10:00:00 up 1 day,  1 user,  load average: 9.00, 4.00, 1.00\t4\t\
+                      1700000000\t/dev/sda1 102400 97280 5120 95% /\t/dev/sda1 102400 97280 5120 95% /\nmain\nlogs\n";
+        let (rest, health) = split(output);
+        assert_eq!(rest, "main\nlogs\n");
+        let health = health.unwrap();
+        assert_eq!(health.cpus, Some(4));
+        assert_eq!(health.disks, [Disk { mount: "/".into(), used: 95, free_kb: 5120 }]);
+        assert_eq!(health.to_string(), "up 1 day · load 9.00 4.00 1.00 (4 CPUs) · / 95% used, 5M free");
+    }
+
+    #[test]
+    fn metrics_past_their_limits_are_problems() {
+        let fields = "up 2:00, load average: 3.00, 1.00, 1.00\t2\t1000\t/dev/a 10 9 1 90% /home\t/dev/b 10 1 9 10% /";
+        let health = parse(fields, 1200);
+        assert_eq!(health.clock_skew, Some(-200));
+        let limits = HealthLimits::default();
+        assert_eq!(health.problems(&limits), ["clock is 3m20s behind"]);
+        let strict = HealthLimits { max_disk_percent: 90, max_load_per_cpu: 1.0, max_clock_skew: 300 };
+        assert_eq!(health.problems(&strict), ["/home is 90% full (1K free)", "load 3.00 on 2 CPUs (limit 1 per CPU)"]);
+    }
+}
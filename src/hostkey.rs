@@ -0,0 +1,115 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::config::Config;
+use crate::ssh::{self, Transport};
+use crate::util;
+
+/// known_hosts file holding only the verified keys of a pinned bookmark
+pub fn pinned_known_hosts(name: &str) -> PathBuf {
+    util::cache_dir().join(format!("known_hosts-{}", name))
+}
+
+/// ssh options restricting host key checks to the pinned file
+pub fn pin_options(path: &Path) -> Vec<String> {
+    vec![
+        "-o".into(),
+        format!("UserKnownHostsFile={}", path.display()),
+        "-o".into(),
+        "StrictHostKeyChecking=yes".into(),
+    ]
+}
+
+/// Fingerprints compare without the optional "SHA256:" prefix
+fn same_fingerprint(a: &str, b: &str) -> bool {
+    a.trim_start_matches("SHA256:") == b.trim_start_matches("SHA256:")
+}
+
+/// Fingerprints (one per key line) of known_hosts-formatted `keys`
+fn fingerprints(keys: &[u8]) -> Result<Vec<String>> {
+    let mut child = Command::new("ssh-keygen")
+        .args(["-l", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to run ssh-keygen")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(keys)?;
+    }
+    let output = child.wait_with_output()?;
+    // Each line reads "256 SHA256:... host (ED25519)"
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1).map(str::to_string))
+        .collect())
+}
+
+/// Make sure a bookmark with a pinned fingerprint only ever talks to a host
+/// presenting that key. The host's keys are fetched with ssh-keyscan and
+/// checked against the pin; the matching key is stored in a private
+/// known_hosts file that ssh is then told to trust exclusively. Later runs
+/// reuse the file as long as it still matches the pin.
+pub fn enforce_pin(config: &mut Config) -> Result<()> {
+    let (Some(name), Some(expected)) = (config.host_alias.clone(), config.host_fingerprint.clone()) else {
+        return Ok(());
+    };
+    if config.transport != Transport::Ssh {
+        return Err(anyhow!("host key pinning for '{}' requires the ssh transport", name));
+    }
+
+    let path = pinned_known_hosts(&name);
+    let pinned = fs::read(&path).unwrap_or_default();
+    let current = !pinned.is_empty() && fingerprints(&pinned)?.iter().all(|fp| same_fingerprint(fp, &expected));
+    if !current {
+        let endpoint = ssh::resolve_endpoint(config)?;
+        let (host, port) = (endpoint.hostname, endpoint.port);
+        let output = Command::new("ssh-keyscan")
+            .args(["-T", "5", "-p", &port, &host])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .context("failed to run ssh-keyscan")?;
+        let lines: Vec<&str> = std::str::from_utf8(&output.stdout)
+            .unwrap_or_default()
+            .lines()
+            .filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .collect();
+        if lines.is_empty() {
+            return Err(anyhow!("could not fetch the host key of {} ({}:{})", name, host, port));
+        }
+
+        let offered = fingerprints(lines.join("\n").as_bytes())?;
+        let matching: Vec<&str> = lines
+            .iter()
+            .zip(&offered)
+            .filter(|(_, fp)| same_fingerprint(fp, &expected))
+            .map(|(line, _)| *line)
+            .collect();
+        if matching.is_empty() {
+            return Err(anyhow!(
+                "host key mismatch for '{}': pinned {}, but {}:{} offers {}. Refusing to connect.",
+                name,
+                expected,
+                host,
+                port,
+                offered.join(", ")
+            ));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, matching.join("\n") + "\n")
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        config.debug_print(&format!("pinned host key for {} stored in {}", name, path.display()));
+    }
+
+    let opts = pin_options(&path);
+    if !config.ssh_args.contains(&opts[1]) {
+        let at = ssh::destination_index(&config.ssh_args).unwrap_or(config.ssh_args.len());
+        config.ssh_args.splice(at..at, opts);
+    }
+    Ok(())
+}
@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+use crate::util;
+
+/// Messages by id. Placeholders such as {count} are filled in by [`tr`].
+const ENGLISH: &[(&str, &str)] = &[
+    ("cancelled", "cancelled"),
+    ("select.attach", "Select a session to attach:"),
+    ("select.kill", "Select a session to kill:"),
+    ("select.host", "Select a host to connect to:"),
+    ("select.switch", "Select a session to switch to:"),
+    ("picker.hint", "(↑/↓ or click, Enter to choose, Esc to cancel)"),
+    ("prompt.number", "Enter number (or press Enter for 1): "),
+    ("prompt.choose", "Choose 1 to {count}, Enter for 1, or q to cancel: "),
+    ("prompt.not_a_choice", "'{answer}' is not one of the choices."),
+    ("confirm.yes_no", "[y/N]"),
+    ("confirm.yes_words", "y yes"),
+    ("confirm.type", "Type '{expected}' to confirm: "),
+    ("label.warning", "WARNING:"),
+    ("label.error", "ERROR:"),
+    ("tmux.missing", "tmux not found on remote host."),
+    ("tmux.install_unknown", "Install it with the system's package manager (apt-get, dnf, apk, pacman, zypper, pkg, or brew)"),
+    ("tmux.elsewhere", "Installed somewhere else? Add the path to tmux_fallbacks in the config file, or pass --tmux PATH"),
+];
+
+/// Built-in translations; anything missing falls back to English
+const TRANSLATIONS: &[(&str, &[(&str, &str)])] = &[
+    (
+        "de",
+        &[
+            ("cancelled", "abgebrochen"),
+            ("select.attach", "Sitzung zum Verbinden auswählen:"),
+            ("select.kill", "Sitzung zum Beenden auswählen:"),
+            ("select.host", "Host zum Verbinden auswählen:"),
+            ("select.switch", "Sitzung zum Wechseln auswählen:"),
+            ("picker.hint", "(↑/↓ oder Klick, Enter zum Auswählen, Esc zum Abbrechen)"),
+            ("prompt.number", "Nummer eingeben (oder Enter für 1): "),
+            ("prompt.choose", "1 bis {count} wählen, Enter für 1 oder q zum Abbrechen: "),
+            ("prompt.not_a_choice", "'{answer}' ist keine der Möglichkeiten."),
+            ("confirm.yes_no", "[j/N]"),
+            ("confirm.yes_words", "j ja y yes"),
+            ("confirm.type", "Zum Bestätigen '{expected}' eingeben: "),
+            ("label.warning", "WARNUNG:"),
+            ("label.error", "FEHLER:"),
+            ("tmux.missing", "tmux wurde auf dem entfernten Host nicht gefunden."),
+            ("tmux.install_unknown", "Mit dem Paketmanager des Systems installieren (apt-get, dnf, apk, pacman, zypper, pkg oder brew)"),
+            ("tmux.elsewhere", "Anderswo installiert? Den Pfad in tmux_fallbacks der Konfigurationsdatei eintragen oder --tmux PFAD angeben"),
+        ],
+    ),
+    (
+        "es",
+        &[
+            ("cancelled", "cancelado"),
+            ("select.attach", "Elige una sesión a la que conectarte:"),
+            ("select.kill", "Elige una sesión para terminar:"),
+            ("select.host", "Elige un host al que conectarte:"),
+            ("select.switch", "Elige una sesión a la que cambiar:"),
+            ("picker.hint", "(↑/↓ o clic, Enter para elegir, Esc para cancelar)"),
+            ("prompt.number", "Escribe un número (o pulsa Enter para 1): "),
+            ("prompt.choose", "Elige de 1 a {count}, Enter para 1 o q para cancelar: "),
+            ("prompt.not_a_choice", "'{answer}' no es ninguna de las opciones."),
+            ("confirm.yes_no", "[s/N]"),
+            ("confirm.yes_words", "s si sí y yes"),
+            ("confirm.type", "Escribe '{expected}' para confirmar: "),
+            ("label.warning", "AVISO:"),
+            ("label.error", "ERROR:"),
+            ("tmux.missing", "no se encontró tmux en el host remoto."),
+            ("tmux.install_unknown", "Instálalo con el gestor de paquetes del sistema (apt-get, dnf, apk, pacman, zypper, pkg o brew)"),
+            ("tmux.elsewhere", "¿Instalado en otro sitio? Añade la ruta a tmux_fallbacks en el archivo de configuración o usa --tmux RUTA"),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("cancelled", "annulé"),
+            ("select.attach", "Choisissez une session à rejoindre :"),
+            ("select.kill", "Choisissez une session à fermer :"),
+            ("select.host", "Choisissez un hôte auquel se connecter :"),
+            ("select.switch", "Choisissez une session vers laquelle basculer :"),
+            ("picker.hint", "(↑/↓ ou clic, Entrée pour choisir, Échap pour annuler)"),
+            ("prompt.number", "Saisissez un numéro (ou Entrée pour 1) : "),
+            ("prompt.choose", "Choisissez de 1 à {count}, Entrée pour 1 ou q pour annuler : "),
+            ("prompt.not_a_choice", "'{answer}' ne fait pas partie des choix."),
+            ("confirm.yes_no", "[o/N]"),
+            ("confirm.yes_words", "o oui y yes"),
+            ("confirm.type", "Tapez '{expected}' pour confirmer : "),
+            ("label.warning", "ATTENTION :"),
+            ("label.error", "ERREUR :"),
+            ("tmux.missing", "tmux est introuvable sur l'hôte distant."),
+            ("tmux.install_unknown", "Installez-le avec le gestionnaire de paquets du système (apt-get, dnf, apk, pacman, zypper, pkg ou brew)"),
+            ("tmux.elsewhere", "Installé ailleurs ? Ajoutez le chemin à tmux_fallbacks dans le fichier de configuration, ou passez --tmux CHEMIN"),
+        ],
+    ),
+];
+
+/// The message with id `id` in the user's language, with each `{name}` replaced
+pub fn tr(id: &str, args: &[(&str, &str)]) -> String {
+    let catalog = CATALOG.get_or_init(Catalog::load);
+    let mut text = catalog.lookup(id).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// Messages for the selected locale, most specific first
+struct Catalog {
+    /// Files from the messages directory, e.g. pt_BR.toml then pt.toml
+    overrides: Vec<BTreeMap<String, String>>,
+    /// Built-in translations for the language, if any
+    builtin: Option<&'static [(&'static str, &'static str)]>,
+}
+
+impl Catalog {
+    fn load() -> Self {
+        let tags = locale_tags(&locale());
+        let dir = util::config_dir().join("messages");
+        let overrides = tags
+            .iter()
+            .filter_map(|tag| std::fs::read_to_string(dir.join(format!("{}.toml", tag))).ok())
+            .filter_map(|text| toml::from_str(&text).ok())
+            .collect();
+        let builtin = tags
+            .iter()
+            .find_map(|tag| TRANSLATIONS.iter().find(|(lang, _)| lang == tag))
+            .map(|(_, messages)| *messages);
+        Catalog { overrides, builtin }
+    }
+
+    fn lookup(&self, id: &str) -> &str {
+        let find = |messages: &'static [(&'static str, &'static str)]| messages.iter().find(|(key, _)| *key == id).map(|(_, text)| *text);
+        self.overrides
+            .iter()
+            .find_map(|messages| messages.get(id).map(String::as_str))
+            .or_else(|| self.builtin.and_then(find))
+            .or_else(|| find(ENGLISH))
+            .unwrap_or("")
+    }
+}
+
+/// The locale messages are shown in, from LC_ALL, LC_MESSAGES, or LANG as
+/// POSIX orders them
+fn locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// Catalog names to try for a locale such as "pt_BR.UTF-8@euro": "pt_BR"
+/// then "pt"; none for C and POSIX
+fn locale_tags(locale: &str) -> Vec<String> {
+    let tag = locale.split(['.', '@']).next().unwrap_or("");
+    if tag.is_empty() || tag == "C" || tag == "POSIX" {
+        return Vec::new();
+    }
+    let mut tags = vec![tag.to_string()];
+    if let Some((lang, _)) = tag.split_once(['_', '-']) {
+        tags.push(lang.to_string());
+    }
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_map_to_catalog_names() {
+        assert_eq!(locale_tags("pt_BR.UTF-8@euro"), ["pt_BR", "pt"]);
+        assert_eq!(locale_tags("de"), ["de"]);
+        assert!(locale_tags("C.UTF-8").is_empty());
+        assert!(locale_tags("").is_empty());
+    }
+
+    #[test]
+    fn lookups_fall_back_to_english() {
+        let overrides = BTreeMap::from([("label.error".to_string(), "ERRO:".to_string())]);
+        let catalog = Catalog { overrides: vec![overrides], builtin: Some(TRANSLATIONS[0].1) };
+        assert_eq!(catalog.lookup("label.error"), "ERRO:");
+        assert_eq!(catalog.lookup("cancelled"), "abgebrochen");
+        let english = Catalog { overrides: Vec::new(), builtin: None };
+        assert_eq!(english.lookup("select.host"), "Select a host to connect to:");
+        // Every translated id exists in English
+        for (_, messages) in TRANSLATIONS {
+            assert!(messages.iter().all(|(id, _)| ENGLISH.iter().any(|(key, _)| key == id)));
+        }
+    }
+}
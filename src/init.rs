@@ -0,0 +1,329 @@
+use anyhow::{anyhow, Context, Result};
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::cli::Cli;
+use crate::config::FileConfig;
+use crate::ui;
+use crate::util;
+
+/// Shells `vigil init SHELL` emits integration for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InitShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl InitShell {
+    fn completion_shell(self) -> Shell {
+        match self {
+            InitShell::Bash => Shell::Bash,
+            InitShell::Zsh => Shell::Zsh,
+            InitShell::Fish => Shell::Fish,
+        }
+    }
+}
+
+/// Answers collected by the setup wizard
+struct Answers {
+    /// Bookmarks as (name, destination)
+    hosts: Vec<(String, String)>,
+    session: String,
+    session_user_suffix: bool,
+    control_master: bool,
+    keepalive: u32,
+}
+
+/// First-run setup: ask a few questions, write the config file, and offer
+/// to install shell completion
+pub fn run() -> Result<()> {
+    let path = FileConfig::path();
+    let replace = !path.exists()
+        || ui::confirm(&format!("{} already exists. Replace it (the old file is kept as .bak)?", path.display()))?;
+    if replace {
+        let answers = ask_all()?;
+        write_config(&path, &render(&answers))?;
+        ui::status(&format!("Wrote {}.", path.display()));
+        if let Some((name, _)) = answers.hosts.first() {
+            ui::status(&format!("Try `vigil {}` to open your default session there.", name));
+        }
+    } else {
+        ui::status("Keeping the existing config file.");
+    }
+
+    match detect_shell() {
+        Some(shell) => {
+            if ask_yes_no(&format!("Install {} completion for vigil?", shell), true)? {
+                install_completion(shell)?;
+            }
+        }
+        None => ui::status("Could not tell which shell you use; skipping completion."),
+    }
+    match detect_shell() {
+        Some(shell @ (Shell::Bash | Shell::Zsh)) => ui::status(&format!(
+            "For the `s HOST` shortcut and the prompt segment, add `eval \"$(vigil init {})\"` to ~/.{}rc.",
+            shell, shell
+        )),
+        Some(Shell::Fish) => ui::status(
+            "For the `s HOST` shortcut and the prompt segment, add `vigil init fish | source` to config.fish.",
+        ),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn ask_all() -> Result<Answers> {
+    let mut hosts: Vec<(String, String)> = Vec::new();
+    eprintln!("Bookmark the hosts you use most, so `vigil NAME` connects to them.");
+    loop {
+        let name = ui::ask("Bookmark name (empty when done):", "")?;
+        if name.is_empty() {
+            break;
+        }
+        if let Err(e) = check_bookmark_name(&name, &hosts) {
+            ui::warn(&e.to_string());
+            continue;
+        }
+        let destination = ui::ask("  SSH destination (user@host):", &name)?;
+        hosts.push((name, destination));
+    }
+
+    let session = ui::ask("Base name for the default session:", "default")?;
+    let session_user_suffix = ask_yes_no(
+        &format!("Append your user name to it (e.g. {}_{})?", session, util::get_local_username()),
+        true,
+    )?;
+    let control_master = ask_yes_no("Reuse one SSH connection per host (faster repeat commands)?", true)?;
+    let keepalive = loop {
+        let answer = ui::ask("Seconds between keepalive probes (0 disables):", "60")?;
+        match answer.parse::<u32>() {
+            Ok(secs) => break secs,
+            Err(_) => ui::warn("please enter a number of seconds"),
+        }
+    };
+
+    Ok(Answers { hosts, session, session_user_suffix, control_master, keepalive })
+}
+
+/// Ask until the answer is yes or no
+fn ask_yes_no(question: &str, default: bool) -> Result<bool> {
+    loop {
+        let answer = ui::ask(&format!("{} (y/n)", question), if default { "y" } else { "n" })?;
+        match answer.to_ascii_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => ui::warn("please answer y or n"),
+        }
+    }
+}
+
+/// Bookmark names share the command line with subcommands, so those are off limits
+fn check_bookmark_name(name: &str, taken: &[(String, String)]) -> Result<()> {
+    if name.contains(char::is_whitespace) || name.starts_with('-') {
+        return Err(anyhow!("'{}' cannot be used as a bookmark name", name));
+    }
+    if Cli::command().get_subcommands().any(|c| c.get_name() == name) {
+        return Err(anyhow!("'{}' is a vigil subcommand; pick another bookmark name", name));
+    }
+    if taken.iter().any(|(n, _)| n == name) {
+        return Err(anyhow!("'{}' is already bookmarked", name));
+    }
+    Ok(())
+}
+
+/// TOML string literal
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+/// TOML key, quoted unless it is a valid bare key
+fn key(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        s.to_string()
+    } else {
+        quote(s)
+    }
+}
+
+fn render(answers: &Answers) -> String {
+    let mut out = String::from("# vigil configuration, written by `vigil init`\n\n");
+    out += "# Default session: the base name, plus \"_<user>\" when session_user_suffix is on\n";
+    out += &format!("session = {}\n", quote(&answers.session));
+    out += &format!("session_user_suffix = {}\n\n", answers.session_user_suffix);
+    out += "# Share one ssh connection per host (ControlMaster)\n";
+    out += &format!("control_master = {}\n", answers.control_master);
+    out += "control_persist = \"10m\"\n\n";
+    out += "# Seconds between ssh keepalive probes (0 disables)\n";
+    out += &format!("keepalive = {}\n", answers.keepalive);
+    for (name, destination) in &answers.hosts {
+        out += &format!("\n[hosts.{}]\ndestination = {}\n", key(name), quote(destination));
+    }
+    out
+}
+
+fn write_config(path: &Path, text: &str) -> Result<()> {
+    // Never write a file vigil itself would reject
+    toml::from_str::<FileConfig>(text).context("generated config is invalid")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    if path.exists() {
+        let backup = path.with_extension("toml.bak");
+        fs::copy(path, &backup).with_context(|| format!("failed to back up to {}", backup.display()))?;
+    }
+    fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The user's login shell, from $SHELL
+fn detect_shell() -> Option<Shell> {
+    let shell = env::var_os("SHELL")?;
+    Shell::from_shell_path(shell)
+}
+
+/// $VAR, or the given directory under home when unset
+fn xdg_base(var: &str, fallback: &[&str]) -> PathBuf {
+    env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| fallback.iter().fold(util::home_dir(), |p, c| p.join(c)))
+}
+
+/// Write a completion script where the shell picks it up on its own
+fn install_completion(shell: Shell) -> Result<()> {
+    let data = xdg_base("XDG_DATA_HOME", &[".local", "share"]);
+    let path = match shell {
+        Shell::Bash => data.join("bash-completion").join("completions").join("vigil"),
+        Shell::Zsh => data.join("zsh").join("site-functions").join("_vigil"),
+        Shell::Fish => xdg_base("XDG_CONFIG_HOME", &[".config"]).join("fish").join("completions").join("vigil.fish"),
+        other => {
+            ui::status(&format!("Completion for {} is not installed automatically; skipping.", other));
+            return Ok(());
+        }
+    };
+
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "vigil", &mut script);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(&path, script).with_context(|| format!("failed to write {}", path.display()))?;
+    ui::status(&format!("Wrote {} completion to {}.", shell, path.display()));
+
+    if shell == Shell::Zsh {
+        let dir = path.parent().unwrap_or(&path).display().to_string();
+        ui::status(&format!(
+            "Make sure ~/.zshrc has `fpath=({} $fpath)` before `compinit`.",
+            dir
+        ));
+    }
+    Ok(())
+}
+
+const BASH_INTEGRATION: &str = r#"s() { command vigil "$@"; }
+
+__vigil_prompt() { VIGIL_PROMPT=$(command vigil prompt-segment 2>/dev/null); }
+case ";${PROMPT_COMMAND:-};" in
+    *";__vigil_prompt;"*) ;;
+    *) PROMPT_COMMAND="__vigil_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac
+case "$PS1" in
+    *VIGIL_PROMPT*) ;;
+    *) PS1='${VIGIL_PROMPT:+[$VIGIL_PROMPT] }'"$PS1" ;;
+esac
+"#;
+
+const BASH_COMPLETE: &str = r#"__vigil_complete() {
+    _vigil "$@"
+    local cur="${COMP_WORDS[COMP_CWORD]}" host
+    [[ $cur == -* ]] && return
+    for host in "${__vigil_hosts[@]}"; do
+        [[ $host == "$cur"* ]] && COMPREPLY+=("$host")
+    done
+}
+complete -F __vigil_complete -o bashdefault -o default vigil s
+"#;
+
+const ZSH_INTEGRATION: &str = r#"s() { command vigil "$@" }
+
+__vigil_prompt() { VIGIL_PROMPT=$(command vigil prompt-segment 2>/dev/null) }
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd __vigil_prompt
+setopt prompt_subst
+[[ $RPROMPT == *VIGIL_PROMPT* ]] || RPROMPT='${VIGIL_PROMPT}'"${RPROMPT:+ $RPROMPT}"
+"#;
+
+const ZSH_COMPLETE: &str = r#"__vigil_complete() {
+    _describe -t hosts 'bookmark' __vigil_hosts
+    _vigil "$@"
+}
+compdef __vigil_complete vigil s
+"#;
+
+const FISH_INTEGRATION: &str = r#"function s --wraps vigil
+    command vigil $argv
+end
+
+function __vigil_prompt_segment
+    command vigil prompt-segment 2>/dev/null
+end
+if not functions -q fish_right_prompt
+    function fish_right_prompt
+        __vigil_prompt_segment
+    end
+end
+"#;
+
+/// Shell code for `eval "$(vigil init SHELL)"`: an `s HOST` shortcut, the
+/// prompt segment in the prompt, and completion that also offers the
+/// bookmarks and aliases configured at the time the snippet is generated
+pub fn integration(shell: InitShell, file: &FileConfig) -> String {
+    // Completion words are split on whitespace, so such names cannot be offered
+    let hosts: Vec<&str> = file
+        .hosts
+        .keys()
+        .chain(file.aliases.keys())
+        .map(String::as_str)
+        .filter(|name| !name.contains(char::is_whitespace))
+        .collect();
+
+    let mut script = Vec::new();
+    clap_complete::generate(shell.completion_shell(), &mut Cli::command(), "vigil", &mut script);
+    let completion = String::from_utf8_lossy(&script);
+
+    let mut out = String::from("# vigil shell integration (regenerate after changing bookmarks or aliases)\n");
+    match shell {
+        InitShell::Bash => {
+            out += BASH_INTEGRATION;
+            out += "\n";
+            out += &completion;
+            out += &format!("\n__vigil_hosts=({})\n", shell_words::join(&hosts));
+            out += BASH_COMPLETE;
+        }
+        InitShell::Zsh => {
+            out += ZSH_INTEGRATION;
+            // compdef exists only once compinit has run
+            out += "\nif (( $+functions[compdef] )); then\n";
+            out += &completion;
+            out += &format!("\n__vigil_hosts=({})\n", shell_words::join(&hosts));
+            out += ZSH_COMPLETE;
+            out += "fi\n";
+        }
+        InitShell::Fish => {
+            out += FISH_INTEGRATION;
+            out += "\n";
+            out += &completion;
+            for name in hosts {
+                out += &format!("complete -c vigil -a {} -d bookmark\n", fish_quote(name));
+            }
+        }
+    }
+    out
+}
+
+/// Single-quote a word for fish, where only \ and ' are special inside quotes
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
@@ -0,0 +1,87 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{self, File};
+use std::path::PathBuf;
+use crate::config::Config;
+use crate::ui;
+use crate::util;
+
+/// Advisory lock on one host, held by destructive operations (killing
+/// sessions or the server) so two vigil invocations don't race. Released
+/// when dropped, or by the kernel if vigil dies.
+pub struct HostLock {
+    _file: File,
+}
+
+impl HostLock {
+    /// Take the host's lock; when another vigil holds it, fail or (with
+    /// `wait`) block until it is released
+    pub fn acquire(config: &Config, wait: bool) -> Result<Self> {
+        let host = config.host_alias.as_deref().or(config.destination()).unwrap_or("default");
+        let path = lock_path(host);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(&path).with_context(|| format!("failed to open lock file {}", path.display()))?;
+
+        if !try_lock(&file)? {
+            if !wait {
+                return Err(anyhow!(
+                    "another vigil operation is in progress on {}; pass --wait to wait for it",
+                    config.host_label()
+                ));
+            }
+            ui::status(&format!("Waiting for another vigil operation on {}...", ui::host(config.host_label())));
+            lock(&file)?;
+        }
+        Ok(HostLock { _file: file })
+    }
+}
+
+/// Lock file of a host, with characters unsafe in file names replaced
+fn lock_path(host: &str) -> PathBuf {
+    let safe: String = host
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@') { c } else { '_' })
+        .collect();
+    util::state_dir().join("locks").join(format!("{}.lock", safe))
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    // SAFETY: flock only operates on the descriptor we own
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        return Ok(false);
+    }
+    Err(anyhow!(err).context("failed to lock the host"))
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    loop {
+        // SAFETY: as in try_lock
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(anyhow!(err).context("failed to lock the host"));
+        }
+    }
+}
+
+// No advisory locks without flock; operations run unguarded as before
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn lock(_file: &File) -> Result<()> {
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::events;
+use crate::ssh;
+use crate::tmux;
+use crate::ui;
+use crate::util;
+
+/// Remote directory of the spool files, relative to the home directory
+const SPOOL_DIR: &str = ".cache/vigil/spool";
+
+/// Size past which a spool file is emptied, once everything in it was read
+const SPOOL_MAX: u64 = 64 << 20;
+
+/// How often the position is saved while output streams in
+const SAVE_EVERY: Duration = Duration::from_secs(1);
+
+/// Starts the line naming the spool file sent and the offset it is sent from
+const MARK: &str = "@@vigil-spool";
+
+/// How much of a pane's spool file has been printed locally
+#[derive(Debug, PartialEq)]
+struct Position {
+    /// Inode of the spool file, so a new file (new pane, new server) starts over
+    inode: String,
+    /// Bytes printed so far
+    offset: u64,
+}
+
+impl Position {
+    fn load(path: &Path) -> Option<Position> {
+        let text = std::fs::read_to_string(path).ok()?;
+        let (inode, offset) = text.trim().split_once(' ')?;
+        Some(Position { inode: inode.to_string(), offset: offset.parse().ok()? })
+    }
+
+    fn store(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, format!("{} {}\n", self.inode, self.offset))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Where the position in the spool of `target` on this host is kept
+fn position_path(config: &Config, target: &str) -> PathBuf {
+    let name = format!("{}_{}", config.host_label(), target).replace('/', "_");
+    util::state_dir().join("spool").join(name)
+}
+
+/// Print the output of `target` (a session's active pane, or
+/// session:window.pane) since the last call, from a spool file on the host
+/// that pipe-pane keeps appending to. Spooling starts on first use. With
+/// `follow`, keeps printing and resumes from the last byte received after a
+/// dropped connection; `all` starts over from the beginning of the spool.
+pub fn show(config: &Config, target: &str, follow: bool, all: bool) -> Result<()> {
+    let path = position_path(config, target);
+    let mut position = if all { None } else { Position::load(&path) };
+    let mut failures = 0;
+    loop {
+        let mut resumed = false;
+        let status = stream(config, target, &path, &mut position, follow, &mut resumed)?;
+        // ssh exits 255 on its own failures, e.g. a dropped connection
+        if !follow || status.code() != Some(255) {
+            if !status.success() {
+                events::exit(status.code().unwrap_or(1));
+            }
+            return Ok(());
+        }
+        failures = if resumed { 1 } else { failures + 1 };
+        let delay = (1u64 << failures.min(5)).min(30);
+        ui::warn(&format!(
+            "Connection to {} lost; resuming in {}s...",
+            ui::host(config.host_label()),
+            delay
+        ));
+        std::thread::sleep(Duration::from_secs(delay));
+    }
+}
+
+/// One connection's worth of spool output, saving `position` to disk every
+/// [`SAVE_EVERY`] and when the connection ends; `resumed` is set once the
+/// spool header arrives
+fn stream(
+    config: &Config,
+    target: &str,
+    path: &Path,
+    position: &mut Option<Position>,
+    follow: bool,
+    resumed: &mut bool,
+) -> Result<std::process::ExitStatus> {
+    let cmd = script(config, target, position.as_ref(), follow);
+    let mut header = Vec::new();
+    let mut stdout = std::io::stdout();
+    let mut saved = Instant::now();
+    let status = ssh::exec_remote_streaming(config, &cmd, |mut data| {
+        if !*resumed {
+            let Some(end) = data.iter().position(|b| *b == b'\n') else {
+                header.extend_from_slice(data);
+                return Ok(());
+            };
+            header.extend_from_slice(&data[..end]);
+            data = &data[end + 1..];
+            *position = Some(parse_header(&String::from_utf8_lossy(&header))?);
+            *resumed = true;
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        stdout.write_all(data)?;
+        stdout.flush()?;
+        // Counted only once written, so an interruption repeats output rather than losing it
+        if let Some(position) = position.as_mut() {
+            position.offset += data.len() as u64;
+            if saved.elapsed() >= SAVE_EVERY {
+                position.store(path)?;
+                saved = Instant::now();
+            }
+        }
+        Ok(())
+    });
+    if let Some(position) = position.as_ref() {
+        position.store(path)?;
+    }
+    status
+}
+
+/// The spool file's inode and the offset its output starts at
+fn parse_header(line: &str) -> Result<Position> {
+    let fields = line.strip_prefix(MARK).map(|rest| rest.split_whitespace().collect::<Vec<_>>());
+    match fields.as_deref() {
+        Some([inode, offset]) => Ok(Position { inode: inode.to_string(), offset: offset.parse()? }),
+        _ => Err(anyhow!("unexpected reply from the remote host: {}", line)),
+    }
+}
+
+/// Remote script: spool the pane unless it already is, then send a header
+/// line and the spool from the byte after `position`, or from the start when
+/// the file was replaced or has shrunk. A spool past [`SPOOL_MAX`] that has
+/// been read to the end is emptied first, and it is removed when the pane
+/// closes.
+fn script(config: &Config, target: &str, position: Option<&Position>, follow: bool) -> String {
+    let (pane, _) = tmux::pane_target(target);
+    let tmux = config.tmux_cmd();
+    let (inode, offset) = position.map(|p| (p.inode.as_str(), p.offset)).unwrap_or(("", 0));
+    format!(
+        "set -- $({tmux} display-message -p -t {pane} '#{{pane_id}} #{{pane_pipe}}'); \
+         [ -n \"$1\" ] || {{ echo {missing} >&2; exit 1; }}; \
+         f=\"$HOME/{dir}/${{1#%}}.log\"; \
+         if [ \"$2\" != 1 ]; then mkdir -p \"$HOME/{dir}\" && touch \"$f\" && {tmux} pipe-pane -t \"$1\" \"cat >> '$f'; rm -f '$f'\" || exit 1; \
+         elif [ ! -f \"$f\" ]; then echo '[vigil] the pane is already piped elsewhere (pipe-pane)' >&2; exit 1; fi; \
+         set -- $(ls -di \"$f\"); size=$(($(wc -c < \"$f\"))); start=0; \
+         if [ \"$1\" = {inode} ] && [ \"$size\" -ge {offset} ]; then start={offset}; fi; \
+         if [ \"$start\" = \"$size\" ] && [ \"$size\" -gt {max} ]; then : > \"$f\"; start=0; fi; \
+         echo \"{mark} $1 $start\"; exec tail -c +$((start + 1)){follow} \"$f\"",
+        tmux = tmux,
+        pane = util::shell_escape(&pane),
+        missing = util::shell_escape(&format!("[vigil] no session or pane '{}'", target)),
+        dir = SPOOL_DIR,
+        inode = util::shell_escape(inode),
+        offset = offset,
+        max = SPOOL_MAX,
+        mark = MARK,
+        follow = if follow { " -f" } else { "" }
+    )
+}
+
+/// Stop spooling `target` and delete its spool file and saved position
+pub fn stop(config: &Config, target: &str) -> Result<()> {
+    let (pane, _) = tmux::pane_target(target);
+    let tmux = config.tmux_cmd();
+    let cmd = format!(
+        "id=$({tmux} display-message -p -t {pane} '#{{pane_id}}'); [ -n \"$id\" ] || {{ echo {missing} >&2; exit 1; }}; \
+         f=\"$HOME/{dir}/${{id#%}}.log\"; [ -f \"$f\" ] || exit 0; {tmux} pipe-pane -t \"$id\" && rm -f \"$f\"",
+        tmux = tmux,
+        pane = util::shell_escape(&pane),
+        missing = util::shell_escape(&format!("[vigil] no session or pane '{}'", target)),
+        dir = SPOOL_DIR
+    );
+    ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    let path = position_path(config, target);
+    if path.exists() {
+        std::fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spool_header_gives_the_starting_position() {
+        let position = parse_header("@@vigil-spool 1835012 4096").unwrap();
+        assert_eq!(position, Position { inode: "1835012".into(), offset: 4096 });
+        assert!(parse_header("bash: tmux: command not found").is_err());
+    }
+}
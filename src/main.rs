@@ -1,37 +1,268 @@
+mod audit;
+mod cache;
 mod cli;
+mod clipboard;
+mod commands;
 mod config;
+mod daemon;
+mod docs;
+mod endpoint;
+mod events;
+mod health;
+mod hostkey;
+mod i18n;
+mod init;
+mod lock;
+mod logs;
+mod nix;
+mod notes;
+mod picker;
+mod pty;
+mod record;
+mod schedule;
+mod shellhist;
+mod slurm;
 mod ssh;
+mod term;
+mod timings;
 mod tmux;
 mod ui;
+mod update;
 mod util;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use serde_json::json;
 
 fn main() -> Result<()> {
+    // A panic must not leave the terminal in raw mode or without echo
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        term::reset_terminal();
+        default_hook(info);
+    }));
+
+    let result = run();
+    timings::report();
+    if let Err(e) = &result {
+        events::emit("error", json!({ "message": format!("{:#}", e) }));
+    }
+    events::emit("exited", json!({ "code": if result.is_ok() { 0 } else { 1 } }));
+    result
+}
+
+fn run() -> Result<()> {
     // Parse arguments with fallback flag hoisting
-    let cli_args = cli::Cli::parse_with_fallback()?;
-    
+    let mut cli_args = timings::phase("parse arguments", cli::Cli::parse_with_fallback)?;
+    if cli_args.timings {
+        timings::enable();
+    }
+    if let Some(format) = cli_args.events {
+        events::enable(format);
+    }
+    let file_config = timings::phase("load config", config::FileConfig::load)?;
+    ui::init(
+        cli_args.quiet,
+        cli_args.color,
+        file_config.theme.clone(),
+        cli_args.plain_prompts || file_config.plain_prompts,
+    );
+
+    // Subcommands that only touch local state run without SSH checks
+    let command = match cli_args.command.take() {
+        Some(command) if command.is_local() => return commands::run_local(command, &file_config),
+        command => command,
+    };
+
+    // Where an editor plugin finds the daemon; known without any host
+    if cli_args.print_endpoint {
+        println!("{}", endpoint::path().display());
+        return Ok(());
+    }
+
     // Extract mode flags before consuming cli_args
     let list_mode = cli_args.list;
+    let verbose = cli_args.verbose;
+    let mine_only = cli_args.mine;
     let kill_opt = cli_args.kill.clone();
+    let kill_server = cli_args.kill_server;
+    let assume_yes = cli_args.yes;
+    let wait_for_lock = cli_args.wait;
+    let exists_opt = cli_args.exists.clone();
+    let from_stdin = cli_args.stdin;
     let attach_opt = cli_args.attach.clone();
-    
+    let restore_if_missing = cli_args.restore_if_missing;
+    let observe = cli_args.observe;
+    let push_terminfo = cli_args.push_terminfo;
+    let term_check = !cli_args.no_term_check;
+    let check_agent = cli_args.check_agent || file_config.check_agent;
+    let require_healthy = cli_args.require_healthy || file_config.require_healthy;
+    let raw = cli_args.raw;
+    let no_tmux = cli_args.no_tmux || raw;
+
+    // `vigil switch` chooses a bookmarked host and session, then attaches like `vigil HOST --session NAME`
+    let command = match command {
+        Some(cli::Command::Switch { refresh }) => {
+            let base = cli_args.clone().into_config(&file_config)?;
+            let (host, session) = commands::switch_target(&base, &file_config, refresh)?;
+            cli_args.ssh_args.push(host);
+            cli_args.session = Some(session);
+            None
+        }
+        // `vigil grep` attaches to the chosen match the same way
+        Some(cli::Command::Grep { pattern, lines, ignore_case, max, no_attach, .. }) => {
+            let base = cli_args.clone().into_config(&file_config)?;
+            match commands::grep_target(&base, &pattern, lines, ignore_case, max, no_attach)? {
+                Some(session) => {
+                    cli_args.session = Some(session);
+                    None
+                }
+                None => return Ok(()),
+            }
+        }
+        // `vigil each` runs the command on every host it names, with @groups expanded
+        Some(cli::Command::Each { hosts, command, jobs }) => {
+            let mut configs = Vec::new();
+            for target in &hosts {
+                for host in file_config.expand_group(target)? {
+                    let mut args = cli_args.clone();
+                    args.ssh_args.push(host);
+                    let mut config = args.into_config(&file_config)?;
+                    config.ensure_writable("each")?;
+                    config.make_non_interactive();
+                    configs.push(config);
+                }
+            }
+            if configs.is_empty() {
+                return Err(anyhow!("no hosts given (usage: vigil each HOST... -- COMMAND...)"));
+            }
+            return commands::each(&configs, &command, jobs as usize);
+        }
+        command => command,
+    };
+
+    // An @group destination: --list, status, ping, and warm cover each of its hosts
+    let group_at = ssh::destination_index(&cli_args.ssh_args).filter(|&at| cli_args.ssh_args[at].starts_with('@'));
+    if let Some(at) = group_at {
+        let group = cli_args.ssh_args[at].clone();
+        let per_host = matches!(command, Some(cli::Command::Status { .. } | cli::Command::Ping { .. } | cli::Command::Warm { .. }));
+        if !(list_mode || per_host) {
+            return Err(anyhow!(
+                "{} is a host group, which works with --list, status, ping, warm, and each; name a single host for anything else",
+                group
+            ));
+        }
+        let mut configs = Vec::new();
+        for host in file_config.expand_group(&group)? {
+            let mut args = cli_args.clone();
+            args.ssh_args[at] = host;
+            let mut config = args.into_config(&file_config)?;
+            config.make_non_interactive();
+            configs.push(config);
+        }
+        let Some(command) = command else {
+            return commands::list_hosts(&configs, mine_only);
+        };
+        // One host failing does not keep the rest from running
+        let mut failed = 0;
+        for config in &mut configs {
+            let result = if command.drives_tmux() {
+                tmux::handshake(config, &file_config.min_tmux_version, &file_config.tmux_fallbacks)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result.and_then(|()| commands::run(command.clone(), config, &file_config)) {
+                ui::error(&format!("{}: {:#}", config.host_label(), e));
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!("{} failed on {} of {} host(s)", group, failed, configs.len()));
+        }
+        return Ok(());
+    }
+
+    // `vigil which` must not touch the network, not even to verify a pinned host key
+    cli_args.offline = matches!(command, Some(cli::Command::Which { .. }));
+    let offline = cli_args.offline;
+    // Only a plain attach falls back to the last host; subcommands without one cover all bookmarks
+    cli_args.default_to_recent = command.is_none() && !cli_args.list && !no_tmux && kill_opt.is_none() && !kill_server && exists_opt.is_none();
+
     // Convert to config
-    let config = cli_args.to_config()?;
+    let mut config = timings::phase("resolve config", || cli_args.into_config(&file_config))?;
+
+    if !offline {
+        events::emit("connecting", json!({ "host": config.host_label() }));
+    }
+
+    // Subcommands run on their own and exit
+    if let Some(command) = command {
+        if command.drives_tmux() && config.destination().is_some() {
+            timings::phase("tmux version check", || {
+                tmux::handshake(&mut config, &file_config.min_tmux_version, &file_config.tmux_fallbacks)
+            })?;
+        }
+        return commands::run(command, &config, &file_config);
+    }
+
+    // Plain ssh shell (or the user's own remote command with --raw): everything but tmux
+    if no_tmux {
+        // A remote command after the host runs unchecked, like `vigil exec`
+        let remote_command = ssh::destination_index(&config.ssh_args).is_some_and(|at| at + 1 < config.ssh_args.len());
+        if raw && remote_command {
+            config.ensure_writable("--raw with a remote command")?;
+        }
+        if check_agent && config.transport == ssh::Transport::Ssh {
+            ssh::check_agent_interactive()?;
+        }
+        if term_check {
+            timings::phase("terminfo check", || term::ensure_remote_term(&mut config, push_terminfo))?;
+        }
+        let started = util::unix_now();
+        let result = tmux::attach_plain_shell(&config);
+        audit::log(&config, "shell", "-", started, Some(util::unix_now() - started));
+        return result;
+    }
+
+    // Learn what the remote tmux supports before driving it
+    timings::phase("tmux version check", || {
+        tmux::handshake(&mut config, &file_config.min_tmux_version, &file_config.tmux_fallbacks)
+    })?;
+    events::emit(
+        "connected",
+        json!({ "host": config.host_label(), "tmux_version": config.tmux_version.map(|v| v.to_string()) }),
+    );
 
     // Handle list mode: print sessions and exit
     if config.debug {
         ui::status("List mode enabled");
     }
     if config.debug || list_mode {
-        match tmux::list_remote_sessions(&config) {
+        if verbose {
+            match ssh::resolve_endpoint(&config) {
+                Ok(endpoint) => ui::status(&format!("Sessions on {} ({}):", ui::host(config.host_label()), endpoint)),
+                Err(e) => ui::status(&format!("Sessions on {} ({:#}):", ui::host(config.host_label()), e)),
+            }
+        }
+        let listing = if verbose {
+            tmux::list_remote_sessions_with_health(&config).map(|(sessions, health)| {
+                if let Some(health) = health {
+                    ui::status(&health.to_string());
+                    for problem in health.problems(&file_config.health) {
+                        ui::warn(&format!("{} looks unhealthy: {}", config.host_label(), problem));
+                    }
+                }
+                sessions
+            })
+        } else {
+            tmux::list_remote_sessions(&config)
+        };
+        match listing {
             Ok(sessions) => {
                 if sessions.is_empty() {
                     ui::status("No tmux sessions found remotely.");
                 } else {
-                    for s in sessions {
-                        println!("{}", s);
-                    }
+                    commands::print_session_list(&config, &sessions, mine_only);
+                    commands::warn_stale_sessions(&config, None);
                 }
             }
             Err(e) => {
@@ -44,10 +275,115 @@ fn main() -> Result<()> {
         }
     }
 
+    // Session names for batch operations (--stdin)
+    let stdin_names = if from_stdin { Some(util::read_names_from_stdin()?) } else { None };
+
+    // Handle exists mode: exit status tells whether the session(s) exist
+    if let Some(exists_val) = exists_opt {
+        let names = match (stdin_names, exists_val) {
+            (Some(names), _) => names,
+            (None, Some(name)) => vec![name],
+            (None, None) => return Err(anyhow!("--exists needs a session name or --stdin")),
+        };
+        let results = tmux::sessions_exist(&config, &names)?;
+        for (name, exists) in &results {
+            if *exists {
+                println!("{}", name);
+            }
+        }
+        if results.iter().any(|(_, exists)| !exists) {
+            events::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Batch kill: every name from stdin in one SSH connection
+    if let (Some(names), Some(_)) = (&stdin_names, &kill_opt) {
+        config.ensure_writable("--kill")?;
+        let _lock = lock::HostLock::acquire(&config, wait_for_lock)?;
+        let foreign: Vec<&String> = names.iter().filter(|n| config.foreign_owner(n).is_some()).collect();
+        if !foreign.is_empty() && !assume_yes {
+            ui::warn(&format!("{} of these sessions belong to other users:", foreign.len()));
+            for name in &foreign {
+                eprintln!("  {}", ui::session(name));
+            }
+            if !ui::confirm("Kill them anyway?")? {
+                ui::status("Aborted.");
+                return Ok(());
+            }
+        }
+        // Protected sessions each need their name typed; the rest go without
+        let mut names = names.clone();
+        for name in names.clone().iter().filter(|n| config.session_protected(n)) {
+            let question = format!("'{}' on {} is protected.", name, config.host_label());
+            if !ui::confirm_by_typing(&question, name)? {
+                ui::status(&format!("Skipping protected session '{}'.", ui::session(name)));
+                names.retain(|n| n != name);
+            }
+        }
+        if names.is_empty() {
+            return Ok(());
+        }
+        let started = util::unix_now();
+        let results = tmux::kill_sessions_batch(&config, &names)?;
+        let mut failed = 0;
+        for (name, killed) in &results {
+            if *killed {
+                audit::log(&config, "kill", name, started, None);
+                ui::status(&format!("Killed session '{}'.", ui::session(name)));
+            } else {
+                failed += 1;
+                ui::error(&format!("Failed to kill session '{}'.", name));
+            }
+        }
+        if failed > 0 {
+            return Err(anyhow!("{} of {} session(s) could not be killed", failed, results.len()));
+        }
+        return Ok(());
+    }
+
+    // Kill the whole server after showing what will go with it
+    if kill_server {
+        config.ensure_writable("--kill-server")?;
+        let _lock = lock::HostLock::acquire(&config, wait_for_lock)?;
+        let sessions = tmux::list_remote_sessions(&config)?;
+        if sessions.is_empty() {
+            ui::status("No tmux sessions found remotely; nothing to kill.");
+            return Ok(());
+        }
+        ui::warn(&format!(
+            "this kills the tmux server on {} and all {} session(s):",
+            ui::host(config.host_label()),
+            sessions.len()
+        ));
+        for name in &sessions {
+            eprintln!("  {}", ui::session(name));
+        }
+        let confirmed = if config.host_protected() || sessions.iter().any(|name| config.session_protected(name)) {
+            ui::confirm_by_typing("This host or one of its sessions is protected.", config.host_label())?
+        } else {
+            assume_yes || ui::confirm("Kill the tmux server?")?
+        };
+        if !confirmed {
+            ui::status("Aborted.");
+            return Ok(());
+        }
+        let started = util::unix_now();
+        tmux::kill_remote_server(&config)?;
+        for name in &sessions {
+            audit::log(&config, "kill", name, started, None);
+        }
+        ui::status(&format!("Killed the tmux server on {}.", ui::host(config.host_label())));
+        return Ok(());
+    }
+
     // Handle kill mode: kill a named session or interactively select
     if let Some(kill_opt_val) = kill_opt {
+        config.ensure_writable("--kill")?;
+        let _lock = lock::HostLock::acquire(&config, wait_for_lock)?;
         let target = match kill_opt_val {
             Some(name) => name,
+            None if config.target_user.is_some() => config.default_session_name(),
             None => {
                 match tmux::list_remote_sessions(&config) {
                     Ok(sessions) => {
@@ -55,7 +391,7 @@ fn main() -> Result<()> {
                             ui::status("No tmux sessions found remotely to kill.");
                             return Ok(());
                         }
-                        ui::prompt_user_to_select_session("kill", &sessions)?
+                        ui::prompt_user_to_select_session("kill", &sessions, &notes::Notes::load().for_host(config.host_label()))?
                     }
                     Err(e) => {
                         ui::error(&format!("Failed to list sessions: {}", e));
@@ -64,30 +400,80 @@ fn main() -> Result<()> {
                 }
             }
         };
+        if config.session_protected(&target) {
+            let question = format!("'{}' on {} is protected.", target, config.host_label());
+            if !ui::confirm_by_typing(&question, &target)? {
+                ui::status("Aborted.");
+                return Ok(());
+            }
+        } else if let Some(owner) = config.foreign_owner(&target) {
+            let question = format!("'{}' belongs to {}, not you. Kill it anyway?", target, owner);
+            if !assume_yes && !ui::confirm(&question)? {
+                ui::status("Aborted.");
+                return Ok(());
+            }
+        }
+        let started = util::unix_now();
         tmux::kill_remote_session(&config, &target)?;
-        ui::status(&format!("Killed session '{}'.", target));
+        audit::log(&config, "kill", &target, started, None);
+        ui::status(&format!(
+            "Killed session '{}' on {}.",
+            ui::session(&target),
+            ui::host(config.host_label())
+        ));
         return Ok(());
     }
 
     // Handle attach mode: attach to named, interactively selected, or default session
+    if check_agent && config.transport == ssh::Transport::Ssh {
+        ssh::check_agent_interactive()?;
+    }
+
+    // Keep away from a host that is already struggling
+    if require_healthy {
+        let health = timings::phase("health check", || health::fetch(&config))?;
+        let problems = health.problems(&file_config.health);
+        if !problems.is_empty() {
+            for problem in &problems {
+                ui::error(&format!("{}: {}", config.host_label(), problem));
+            }
+            return Err(anyhow!("{} is unhealthy; not attaching (--require-healthy)", config.host_label()));
+        }
+    }
+
+    // Single-shot picker: list, choose, and attach over one SSH connection
+    if matches!(attach_opt, Some(None)) && config.single_shot && config.target_user.is_none() {
+        if term_check {
+            timings::phase("terminfo check", || term::ensure_remote_term(&mut config, push_terminfo))?;
+        }
+        let default_name = config.default_session_name();
+        let started = util::unix_now();
+        let result = tmux::attach_with_remote_picker(&config, &default_name);
+        audit::log(&config, "attach", "(remote picker)", started, Some(util::unix_now() - started));
+        return result;
+    }
+
+    let mut action = "attach";
     let final_session_name = match attach_opt {
         Some(Some(name)) => {
             // Explicit session name provided
             name
         }
+        Some(None) if config.target_user.is_some() => config.default_session_name(),
         Some(None) => {
             // Interactive selection
             match tmux::list_remote_sessions(&config) {
                 Ok(sessions) => {
                     if sessions.is_empty() {
-                        let default_name = format!("{}_{}", config.session, config.local_user);
+                        let default_name = config.default_session_name();
                         ui::status(&format!(
                             "No tmux sessions found remotely; will create/attach to '{}'.",
-                            default_name
+                            ui::session(&default_name)
                         ));
+                        action = "create";
                         default_name
                     } else {
-                        ui::prompt_user_to_select_session("attach", &sessions)?
+                        ui::prompt_user_to_select_session("attach", &sessions, &notes::Notes::load().for_host(config.host_label()))?
                     }
                 }
                 Err(e) => {
@@ -99,17 +485,49 @@ fn main() -> Result<()> {
         None => {
             // Default behavior: create/attach to user-scoped session. If the
             // user explicitly provided a session name, use it verbatim; otherwise
-            // append the local user to the default base session.
-            if config.session_provided {
-                config.session.clone()
-            } else {
-                format!("{}_{}", config.session, config.local_user)
-            }
+            // derive it from the default base session (see default_session_name).
+            config.target_session()
         }
     };
 
+    // Never create a session in someone else's name, only join an existing one
+    if let Some(user) = &config.target_user {
+        let exists = tmux::sessions_exist(&config, std::slice::from_ref(&final_session_name))?;
+        if !exists.iter().any(|(_, found)| *found) {
+            return Err(anyhow!("{} has no session '{}' on {}", user, final_session_name, config.host_label()));
+        }
+    }
+
+    // Make sure the remote side understands our terminal type
+    if term_check {
+        timings::phase("terminfo check", || term::ensure_remote_term(&mut config, push_terminfo))?;
+    }
+
+    // Watch from a local pane instead: the session must exist, nothing is created
+    if observe {
+        let exists = tmux::sessions_exist(&config, std::slice::from_ref(&final_session_name))?;
+        if !exists.iter().any(|(_, found)| *found) {
+            return Err(anyhow!("no session '{}' on {} to observe", final_session_name, config.host_label()));
+        }
+        tmux::observe_in_local_pane(&config, &final_session_name)?;
+        audit::log(&config, "observe", &final_session_name, util::unix_now(), None);
+        ui::status(&format!("Observing '{}' read-only in a new pane below.", ui::session(&final_session_name)));
+        return Ok(());
+    }
+
+    if restore_if_missing && tmux::restore_if_missing(&config, &final_session_name)? {
+        ui::status(&format!("Restored '{}' from its last autosave.", ui::session(&final_session_name)));
+        action = "restore";
+    }
+
+    // Nudge about forgotten sessions; the warnings reappear once tmux gives the screen back
+    commands::warn_stale_sessions(&config, Some(&final_session_name));
+
     // Attach to the session
-    tmux::attach_session(&config, &final_session_name)?;
+    let started = util::unix_now();
+    let result = tmux::attach_session(&config, &final_session_name);
+    audit::log(&config, action, &final_session_name, started, Some(util::unix_now() - started));
+    result?;
 
     Ok(())
 }
\ No newline at end of file
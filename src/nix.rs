@@ -0,0 +1,39 @@
+use serde::Deserialize;
+use crate::tmux;
+use crate::util;
+
+/// Nix development shell the session's first window runs in, from a
+/// `[hosts.<name>.nix]` table and --nix-develop
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DevShell {
+    /// Project directory holding flake.nix or shell.nix (e.g. "~/src/app"); the home directory if unset
+    pub dir: Option<String>,
+    /// Further arguments for nix develop or nix-shell, e.g. [".#ci"] or ["--pure"]
+    pub args: Vec<String>,
+}
+
+/// Sourced first when present: tmux's shell is not a login shell, so
+/// multi-user installs outside NixOS would otherwise leave nix off PATH
+const NIX_PROFILE: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+
+impl DevShell {
+    /// Shell command for tmux new-session: `nix develop` in the project
+    /// directory when it has a flake.nix, `nix-shell` otherwise. A failure
+    /// stays on screen until Enter instead of closing the window at once.
+    pub fn shell_command(&self) -> String {
+        let cd = match &self.dir {
+            Some(dir) => format!("cd {} || exit; ", tmux::remote_path(dir)),
+            None => String::new(),
+        };
+        let args: String = self.args.iter().map(|arg| format!(" {}", util::shell_escape(arg))).collect();
+        format!(
+            "[ -e {profile} ] && . {profile}; {cd}echo '[vigil] Entering the nix development shell...'; \
+             if [ -f flake.nix ]; then nix develop{args}; else nix-shell{args}; fi \
+             || {{ echo \"[vigil] nix exited with status $?; press Enter to close.\"; read -r _; }}",
+            profile = NIX_PROFILE,
+            cd = cd,
+            args = args
+        )
+    }
+}
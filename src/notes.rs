@@ -0,0 +1,56 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use crate::util;
+
+/// A note left on a session with `vigil note`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub text: String,
+    /// When the note was written (Unix time)
+    pub written: u64,
+}
+
+/// Notes by host (the bookmark or destination, as in `vigil history`) and
+/// session; kept locally, the host never sees them
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Notes {
+    pub hosts: BTreeMap<String, BTreeMap<String, Note>>,
+}
+
+impl Notes {
+    pub fn path() -> PathBuf {
+        util::state_dir().join("notes.json")
+    }
+
+    /// Load the notes; a missing or unreadable file counts as none
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the notes atomically (write to a temp file, then rename)
+    pub fn store(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension(format!("json.{}", std::process::id()));
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Note texts of one host's sessions, by session name
+    pub fn for_host(&self, host: &str) -> BTreeMap<String, String> {
+        self.hosts
+            .get(host)
+            .map(|notes| notes.iter().map(|(session, note)| (session.clone(), note.text.clone())).collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEventKind,
+};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use crate::ui;
+
+/// Puts the terminal into raw mode with mouse reporting for its lifetime
+struct RawGuard;
+
+impl RawGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnableMouseCapture, cursor::Hide)?;
+        Ok(RawGuard)
+    }
+}
+
+impl Drop for RawGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), DisableMouseCapture, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Whether the inline picker fits: it needs a terminal tall enough to show
+/// the header and every item at once.
+pub fn fits(count: usize) -> bool {
+    matches!(terminal::size(), Ok((_, rows)) if count + 2 <= rows as usize)
+}
+
+/// Let the user choose one of `items` with the arrow keys (or j/k), a digit,
+/// a mouse click, or the scroll wheel. Enter or a click confirms; Esc, q, or
+/// Ctrl-C cancel and return `None`. Drawn inline on stderr below `header`.
+pub fn select(header: &str, items: &[String]) -> Result<Option<usize>> {
+    let mut stderr = io::stderr();
+    eprintln!("{}", header);
+    for _ in items {
+        eprintln!();
+    }
+
+    let _raw = RawGuard::enter()?;
+    // The reserved lines sit directly above the cursor, even if the screen scrolled
+    let (_, row) = cursor::position()?;
+    let top = row.saturating_sub(items.len() as u16);
+
+    let mut selected = 0;
+    let choice = loop {
+        for (i, item) in items.iter().enumerate() {
+            let line = if i == selected {
+                format!("> {}. {}", i + 1, ui::session(item))
+            } else {
+                format!("  {}. {}", i + 1, item)
+            };
+            queue!(stderr, MoveTo(0, top + i as u16), Clear(ClearType::CurrentLine), Print(line))?;
+        }
+        stderr.flush()?;
+
+        match event::read()? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => selected = selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => selected = (selected + 1).min(items.len() - 1),
+                KeyCode::Home => selected = 0,
+                KeyCode::End => selected = items.len() - 1,
+                KeyCode::Enter => break Some(selected),
+                KeyCode::Char(c @ '1'..='9') => {
+                    let idx = c as usize - '1' as usize;
+                    if idx < items.len() {
+                        break Some(idx);
+                    }
+                }
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break None,
+                KeyCode::Esc | KeyCode::Char('q') => break None,
+                _ => {}
+            },
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollUp => selected = selected.saturating_sub(1),
+                MouseEventKind::ScrollDown => selected = (selected + 1).min(items.len() - 1),
+                MouseEventKind::Down(MouseButton::Left) => {
+                    let clicked = mouse.row.checked_sub(top).map(usize::from);
+                    if let Some(idx) = clicked.filter(|&i| i < items.len()) {
+                        break Some(idx);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    };
+
+    execute!(stderr, MoveTo(0, top + items.len() as u16))?;
+    Ok(choice)
+}
@@ -0,0 +1,515 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::collections::VecDeque;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::ui;
+use crate::util;
+
+/// How --log-output writes the session's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Exactly the bytes the terminal received, escape sequences included
+    #[default]
+    Raw,
+    /// Each line prefixed with the wall-clock time (UTC) it started
+    Timestamped,
+}
+
+/// Append-only copy of everything shown on the terminal during an attach
+pub struct OutputLog {
+    out: BufWriter<File>,
+    format: LogFormat,
+    /// Whether the next byte starts a new line (and so needs a timestamp)
+    line_start: bool,
+}
+
+impl OutputLog {
+    /// Open `path` for appending, so several sessions can share one log
+    pub fn open(path: &Path, format: LogFormat) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open output log {}", path.display()))?;
+        Ok(OutputLog { out: BufWriter::new(file), format, line_start: true })
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.format == LogFormat::Raw {
+            self.out.write_all(data)?;
+            return Ok(());
+        }
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            if self.line_start {
+                write!(self.out, "[{}] ", util::format_timestamp(util::unix_now()))?;
+            }
+            self.out.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if self.format == LogFormat::Timestamped && !self.line_start {
+            writeln!(self.out)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Longest OSC sequence held back while looking for its end
+const MAX_OSC_LEN: usize = 4096;
+
+/// Rewrites window titles set by remote programs (OSC 0 and 2) through the
+/// title template, with the remote title as `{title}`
+struct TitleRewriter {
+    template: String,
+    /// An escape sequence that may be a title, not yet complete
+    pending: Vec<u8>,
+}
+
+impl TitleRewriter {
+    fn rewrite(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &b in data {
+            if self.pending.is_empty() {
+                if b == 0x1b {
+                    self.pending.push(b);
+                } else {
+                    out.push(b);
+                }
+                continue;
+            }
+            if self.pending.len() == 1 && b != b']' {
+                // Not an OSC; a second ESC may start one
+                out.append(&mut self.pending);
+                if b == 0x1b {
+                    self.pending.push(b);
+                } else {
+                    out.push(b);
+                }
+                continue;
+            }
+            self.pending.push(b);
+            if b == 0x07 || self.pending.ends_with(b"\x1b\\") {
+                out.extend(self.finish_osc());
+            } else if self.pending.len() > MAX_OSC_LEN {
+                out.append(&mut self.pending);
+            }
+        }
+        out
+    }
+
+    /// The complete OSC in `pending`, rewritten if it sets the title
+    fn finish_osc(&mut self) -> Vec<u8> {
+        let osc = std::mem::take(&mut self.pending);
+        let terminator = if osc.ends_with(b"\x07") { 1 } else { 2 };
+        let body = String::from_utf8_lossy(&osc[2..osc.len() - terminator]);
+        match body.split_once(';') {
+            Some((code @ ("0" | "2"), title)) => {
+                format!("\x1b]{};{}\x07", code, self.template.replace("{title}", title)).into_bytes()
+            }
+            _ => osc,
+        }
+    }
+}
+
+/// Parse a --detach-key chord such as "ctrl-\\", "ctrl-]" or "C-q" into the
+/// byte the terminal sends for it
+pub fn parse_chord(chord: &str) -> Result<u8> {
+    let key = ["ctrl-", "ctrl+", "C-", "^"]
+        .iter()
+        .find_map(|prefix| chord.strip_prefix(prefix))
+        .ok_or_else(|| anyhow!("invalid detach key '{}': expected a control chord like ctrl-\\ or ctrl-]", chord))?;
+    match key.as_bytes() {
+        [c @ (b'@'..=b'_' | b'a'..=b'z')] => Ok(c.to_ascii_uppercase() & 0x1f),
+        _ if key.eq_ignore_ascii_case("space") => Ok(0),
+        _ => Err(anyhow!("invalid detach key '{}': ctrl- must be followed by a letter or one of @[\\]^_", chord)),
+    }
+}
+
+/// How fast --paced-paste feeds a large paste to the session
+#[derive(Debug, Clone, Copy)]
+pub struct PasteRate {
+    /// Bytes sent at a time
+    pub chunk: usize,
+    /// Pause after each chunk
+    pub delay: Duration,
+}
+
+/// Start of a bracketed paste, as sent by terminals when the remote side asks for it
+const PASTE_START: &[u8] = b"\x1b[200~";
+
+/// Holds back large pastes and releases them a chunk at a time, so a slow
+/// link or a busy remote program does not drop or reorder keys
+struct PastePacer {
+    rate: PasteRate,
+    queue: VecDeque<u8>,
+    next_at: Instant,
+}
+
+impl PastePacer {
+    /// Keys to send right away; the rest waits in the queue. Typing behind a
+    /// paste queues too, so it keeps its place.
+    fn input(&mut self, data: Vec<u8>) -> Vec<u8> {
+        let pasted = data.len() > self.rate.chunk || data.windows(PASTE_START.len()).any(|w| w == PASTE_START);
+        if self.queue.is_empty() && !pasted {
+            return data;
+        }
+        self.queue.extend(data);
+        Vec::new()
+    }
+
+    /// Time until the next chunk is due, if anything is queued
+    fn wait(&self) -> Option<Duration> {
+        (!self.queue.is_empty()).then(|| self.next_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// The next chunk, once its pause is over
+    fn due(&mut self) -> Option<Vec<u8>> {
+        let now = Instant::now();
+        if self.queue.is_empty() || now < self.next_at {
+            return None;
+        }
+        self.next_at = now + self.rate.delay;
+        let n = self.rate.chunk.min(self.queue.len());
+        Some(self.queue.drain(..n).collect())
+    }
+}
+
+/// Desktop notification when a session prints again after a quiet spell,
+/// unless the output just echoes recent typing
+struct ActivityWatch {
+    quiet: Duration,
+    last_output: Instant,
+    label: String,
+}
+
+impl ActivityWatch {
+    fn output(&mut self, last_input: Instant) {
+        let now = Instant::now();
+        if now.duration_since(self.last_output) >= self.quiet && now.duration_since(last_input) >= self.quiet {
+            ui::desktop_notify(&format!("New output in {}", self.label));
+        }
+        self.last_output = now;
+    }
+}
+
+/// Local pseudo-terminal between the user's terminal and ssh, for features
+/// that need to see the byte stream: output logging, title rewriting,
+/// activity notifications, detaching (on a local key or when idle), and
+/// paced pasting
+pub struct Proxy {
+    log: Option<OutputLog>,
+    titles: Option<TitleRewriter>,
+    activity: Option<ActivityWatch>,
+    /// Local key that detaches
+    detach_key: Option<u8>,
+    /// Detach after this long without keyboard input
+    idle_detach: Option<Duration>,
+    /// Keys that make the remote tmux client detach, when anything may detach
+    detach_keys: Option<Vec<u8>>,
+    paste: Option<PastePacer>,
+    last_input: Instant,
+    /// Whether the idle timeout has sent the detach keys
+    idled: bool,
+}
+
+impl Proxy {
+    /// The proxy an attach to `session` needs, or None when nothing asks for one
+    pub fn for_session(config: &Config, session: &str) -> Result<Option<Self>> {
+        let log = match &config.log_output {
+            Some(path) => {
+                ui::status(&format!("Logging output to {}", path.display()));
+                Some(OutputLog::open(path, config.log_format)?)
+            }
+            None => None,
+        };
+        let titles = config.title_template.contains("{title}").then(|| TitleRewriter {
+            template: crate::tmux::render_title(config, session),
+            pending: Vec::new(),
+        });
+        let activity = config.notify_activity.map(|secs| ActivityWatch {
+            quiet: Duration::from_secs(secs),
+            last_output: Instant::now(),
+            label: format!("{} on {}", session, config.host_label()),
+        });
+        let detach_keys = (config.detach_key.is_some() || config.idle_detach.is_some())
+            .then(|| crate::tmux::detach_keys(config));
+        let paste = config.paced_paste.map(|rate| PastePacer { rate, queue: VecDeque::new(), next_at: Instant::now() });
+
+        if log.is_none() && titles.is_none() && activity.is_none() && detach_keys.is_none() && paste.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Proxy {
+            log,
+            titles,
+            activity,
+            detach_key: config.detach_key,
+            idle_detach: config.idle_detach,
+            detach_keys,
+            paste,
+            last_input: Instant::now(),
+            idled: false,
+        }))
+    }
+
+    /// Keys typed locally, as they should reach the session now. The detach
+    /// key becomes the remote prefix + d; anything typed after it is dropped.
+    /// Large pastes are held back for paste_due().
+    fn input(&mut self, data: &[u8]) -> Vec<u8> {
+        self.last_input = Instant::now();
+        let keys = match (self.detach_key, &self.detach_keys) {
+            (Some(key), Some(keys)) if data.contains(&key) => {
+                let at = data.iter().position(|&b| b == key).unwrap_or(data.len());
+                [&data[..at], keys.as_slice()].concat()
+            }
+            _ => data.to_vec(),
+        };
+        match &mut self.paste {
+            Some(paste) => paste.input(keys),
+            None => keys,
+        }
+    }
+
+    /// How long the pump may wait for input before due() has something
+    fn wait(&self) -> Option<Duration> {
+        let idle = match self.idle_detach {
+            Some(after) if !self.idled => Some(
+                self.last_input.checked_add(after).map_or(Duration::MAX, |at| at.saturating_duration_since(Instant::now())),
+            ),
+            _ => None,
+        };
+        let paste = self.paste.as_ref().and_then(PastePacer::wait);
+        [idle, paste].into_iter().flatten().min()
+    }
+
+    /// Keys the proxy sends on its own: the next chunk of a held-back paste,
+    /// or the detach keys once the idle timeout has passed
+    fn due(&mut self) -> Option<Vec<u8>> {
+        if let (Some(after), Some(keys)) = (self.idle_detach, &self.detach_keys) {
+            if !self.idled && self.last_input.elapsed() >= after {
+                self.idled = true;
+                return Some(keys.clone());
+            }
+        }
+        self.paste.as_mut().and_then(PastePacer::due)
+    }
+
+    /// Session output, as it should appear on the terminal
+    fn output(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let shown = match &mut self.titles {
+            Some(titles) => titles.rewrite(data),
+            None => data.to_vec(),
+        };
+        if let Some(log) = &mut self.log {
+            log.write(&shown)?;
+        }
+        if let Some(activity) = &mut self.activity {
+            activity.output(self.last_input);
+        }
+        Ok(shown)
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.idled {
+            ui::status(&format!(
+                "Detached after {} without input.",
+                util::format_duration(self.idle_detach.unwrap_or_default().as_secs())
+            ));
+        }
+        match self.log {
+            Some(log) => log.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Run an interactive command on a local pseudo-terminal, passing the
+    /// terminal's keys and the command's output through this proxy
+    pub fn run(self, cmd: Command) -> Result<ExitStatus> {
+        #[cfg(unix)]
+        {
+            unix::run(cmd, self)
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = cmd;
+            Err(anyhow::anyhow!("--log-output, --notify-activity, --detach-key, --idle-detach, --paced-paste, and {{title}} titles need a Unix pseudo-terminal"))
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use anyhow::{anyhow, Context, Result};
+    use std::io::{self, Read, Write};
+    use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command, ExitStatus, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use super::Proxy;
+
+    /// How often the pump checks whether ssh is gone while the pty stays open
+    const CHILD_CHECK_MS: libc::c_int = 500;
+
+    static RESIZED: AtomicBool = AtomicBool::new(false);
+
+    extern "C" fn on_winch(_: libc::c_int) {
+        RESIZED.store(true, Ordering::Relaxed);
+    }
+
+    /// Puts the local terminal into raw mode, so keys reach the remote side
+    /// untouched, and restores it (and the SIGWINCH disposition) when dropped
+    struct RawMode {
+        saved: Option<libc::termios>,
+        previous_winch: libc::sighandler_t,
+    }
+
+    impl RawMode {
+        fn enter() -> Self {
+            let handler = on_winch as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only stores to an atomic
+            let previous_winch = unsafe { libc::signal(libc::SIGWINCH, handler) };
+
+            // SAFETY: tcgetattr/tcsetattr only read and write the struct we pass
+            let mut attrs: libc::termios = unsafe { std::mem::zeroed() };
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut attrs) } != 0 {
+                return RawMode { saved: None, previous_winch };
+            }
+            let saved = attrs;
+            unsafe {
+                libc::cfmakeraw(&mut attrs);
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &attrs);
+            }
+            RawMode { saved: Some(saved), previous_winch }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: reinstates the attributes and handler saved by enter()
+            unsafe {
+                if let Some(saved) = &self.saved {
+                    libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+                }
+                libc::signal(libc::SIGWINCH, self.previous_winch);
+            }
+        }
+    }
+
+    /// Size of the local terminal, or 80x24 when there is none
+    fn window_size() -> libc::winsize {
+        let (cols, rows) = crate::util::terminal_size().unwrap_or((80, 24));
+        libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 }
+    }
+
+    pub fn run(mut cmd: Command, mut proxy: Proxy) -> Result<ExitStatus> {
+        let (mut master_fd, mut slave_fd) = (-1, -1);
+        let mut size = window_size();
+        // SAFETY: openpty fills in two new descriptors, which are owned below
+        let rc = unsafe {
+            libc::openpty(
+                &mut master_fd,
+                &mut slave_fd,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::addr_of_mut!(size),
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error()).context("failed to open a pseudo-terminal");
+        }
+        let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master_fd), OwnedFd::from_raw_fd(slave_fd)) };
+
+        cmd.stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: only async-signal-safe calls between fork and exec; the
+        // child gets its own session with the pty as controlling terminal
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().context("failed to start ssh")?;
+        // Our copies of the slave end must close, or reading the master never ends
+        drop(cmd);
+
+        let result = pump(&master, &mut proxy, &mut child);
+        let status = child.wait()?;
+        proxy.finish()?;
+        result?;
+        Ok(status)
+    }
+
+    /// Copy keys to the pty and its output to the terminal until the child
+    /// exits or its side closes, both through the proxy. A process started
+    /// by ssh (such as a tmux server on a local test host) may keep the pty
+    /// open after ssh itself has gone.
+    fn pump(master: &OwnedFd, proxy: &mut Proxy, child: &mut Child) -> Result<()> {
+        let _raw = RawMode::enter();
+        let mut master_file = std::fs::File::from(master.try_clone()?);
+        // Unbuffered, so poll() sees every key that has not been forwarded yet
+        let mut stdin = std::fs::File::from(io::stdin().as_fd().try_clone_to_owned()?);
+        let mut stdout = io::stdout().lock();
+        let mut stdin_open = true;
+        let mut buf = [0u8; 8192];
+
+        loop {
+            if RESIZED.swap(false, Ordering::Relaxed) {
+                let size = window_size();
+                // SAFETY: TIOCSWINSZ reads the struct; the kernel signals the child
+                unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+            }
+
+            let mut fds = [
+                libc::pollfd { fd: master.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+                libc::pollfd { fd: if stdin_open { libc::STDIN_FILENO } else { -1 }, events: libc::POLLIN, revents: 0 },
+            ];
+            // Wake up in time for a paced paste's next chunk or the idle timeout
+            let timeout = proxy.wait().map_or(CHILD_CHECK_MS, |wait| wait.as_millis().min(CHILD_CHECK_MS as u128) as libc::c_int);
+            // SAFETY: poll reads and updates the array we own
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(anyhow!(err).context("failed to wait for terminal input"));
+            }
+
+            if fds[1].revents != 0 {
+                match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => stdin_open = false,
+                    Ok(n) => {
+                        master_file.write_all(&proxy.input(&buf[..n]))?;
+                    }
+                }
+            }
+            if fds[0].revents != 0 {
+                match master_file.read(&mut buf) {
+                    // EIO once the child has closed its end
+                    Ok(0) | Err(_) => return Ok(()),
+                    Ok(n) => {
+                        stdout.write_all(&proxy.output(&buf[..n])?)?;
+                        stdout.flush()?;
+                    }
+                }
+            }
+            if let Some(keys) = proxy.due() {
+                master_file.write_all(&keys)?;
+            }
+            // Everything ssh printed was read above
+            if fds[0].revents == 0 && child.try_wait()?.is_some() {
+                return Ok(());
+            }
+        }
+    }
+}
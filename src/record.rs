@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use crate::util;
+
+/// Writer for asciicast v2 recordings (https://docs.asciinema.org/manual/asciicast/v2/)
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Instant,
+    /// Trailing bytes of an incomplete UTF-8 sequence from the previous chunk
+    pending: Vec<u8>,
+}
+
+impl Recorder {
+    /// Create the recording file and write the asciicast header
+    pub fn create(path: &Path, title: &str) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("failed to create recording {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+
+        let (width, height) = util::terminal_size().unwrap_or((80, 24));
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": timestamp,
+            "title": title,
+            "env": {
+                "TERM": std::env::var("TERM").unwrap_or_default(),
+                "SHELL": std::env::var("SHELL").unwrap_or_default(),
+            },
+        });
+        writeln!(out, "{}", header)?;
+
+        Ok(Recorder { out, start: Instant::now(), pending: Vec::new() })
+    }
+
+    /// Record a chunk of terminal output
+    pub fn output(&mut self, data: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(data);
+
+        // Keep an incomplete multi-byte sequence at the end for the next chunk
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+        if valid == 0 {
+            return Ok(());
+        }
+        let chunk: Vec<u8> = self.pending.drain(..valid).collect();
+        let text = String::from_utf8_lossy(&chunk);
+
+        let event = json!([self.start.elapsed().as_secs_f64(), "o", text]);
+        writeln!(self.out, "{}", event)?;
+        Ok(())
+    }
+
+    /// Flush remaining output to disk
+    pub fn finish(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            let rest = std::mem::take(&mut self.pending);
+            let text = String::from_utf8_lossy(&rest).into_owned();
+            let event = json!([self.start.elapsed().as_secs_f64(), "o", text]);
+            writeln!(self.out, "{}", event)?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Run an interactive command with its output copied to a recording. ssh
+/// still reads the terminal (raw mode, window size) from the inherited stdin,
+/// so only stdout needs to pass through vigil.
+pub fn run_recorded(mut cmd: Command, path: &Path, title: &str) -> Result<ExitStatus> {
+    let mut recorder = Recorder::create(path, title)?;
+    let mut child = cmd
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .context("failed to start ssh")?;
+
+    let mut child_out = child.stdout.take().context("ssh stdout unavailable")?;
+    let mut stdout = io::stdout();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match child_out.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("failed to read ssh output"),
+        };
+        stdout.write_all(&buf[..n])?;
+        stdout.flush()?;
+        recorder.output(&buf[..n])?;
+    }
+
+    let status = child.wait()?;
+    recorder.finish()?;
+    Ok(status)
+}
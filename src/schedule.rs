@@ -0,0 +1,172 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use crate::config::Config;
+use crate::ssh;
+use crate::tmux;
+use crate::util;
+
+/// A session recreated after the host reboots, from a
+/// `[[hosts.<name>.sessions]]` entry of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionTemplate {
+    /// Session name, used verbatim
+    pub name: String,
+    /// Directory the windows start in (e.g. "~/src/app")
+    pub cwd: Option<String>,
+    /// Window names, in order; one unnamed window when empty
+    pub windows: Vec<String>,
+}
+
+/// Where the generated script lives on the remote host
+const SCRIPT: &str = ".local/share/vigil/sessions.sh";
+/// systemd user unit that runs the script at boot
+const UNIT: &str = ".config/systemd/user/vigil-sessions.service";
+/// Marks vigil's line in the crontab so it can be replaced or removed
+const CRON_MARK: &str = "# vigil schedule";
+/// Remote script saving session snapshots for `--restore-if-missing`
+const AUTOSAVE_SCRIPT: &str = ".local/share/vigil/autosave.sh";
+/// Marks the autosave line in the crontab
+const AUTOSAVE_MARK: &str = "# vigil autosave";
+/// Directories where a tmux missing from cron's PATH is usually installed
+const EXTRA_PATH: &str = "PATH=\"$PATH:/usr/local/bin:$HOME/bin:$HOME/.local/bin\"\n";
+
+/// How the script is started at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runner {
+    /// An `@reboot` crontab entry
+    Cron,
+    /// A systemd user service (needs lingering to start before login)
+    Systemd,
+}
+
+/// Shell script that creates each missing session with its windows; existing
+/// sessions are left alone. Boot environments have a short PATH, so the
+/// usual places for a self-built tmux are added.
+fn script(config: &Config, sessions: &[SessionTemplate]) -> String {
+    let tmux = config.tmux_cmd();
+    let mut out = String::from("#!/bin/sh\n# Written by `vigil schedule`; recreates tmux sessions after a reboot\n");
+    out += EXTRA_PATH;
+    for session in sessions {
+        let name = util::shell_escape(&session.name);
+        let cwd = session.cwd.as_deref().map(|cwd| format!(" -c {}", tmux::remote_path(cwd))).unwrap_or_default();
+        let mut windows = session.windows.iter();
+        let mut create = format!("{} new-session -d -s {}{}", tmux, name, cwd);
+        if let Some(first) = windows.next() {
+            create += &format!(" -n {}", util::shell_escape(first));
+        }
+        for window in windows {
+            create += &format!(
+                " && {} new-window -d -t {}{} -n {}",
+                tmux,
+                util::shell_escape(&format!("={}:", session.name)),
+                cwd,
+                util::shell_escape(window)
+            );
+        }
+        out += &format!("{} has-session -t {} 2>/dev/null || {{ {}; }}\n", tmux, util::shell_escape(&format!("={}", session.name)), create);
+    }
+    out
+}
+
+fn unit() -> String {
+    format!(
+        "[Unit]\nDescription=Recreate tmux sessions (vigil schedule)\n\n\
+         [Service]\nType=oneshot\nRemainAfterExit=yes\nExecStart=%h/{}\n\n\
+         [Install]\nWantedBy=default.target\n",
+        SCRIPT
+    )
+}
+
+/// Remote command writing `content` to `path` (relative to the home directory)
+fn write_file(path: &str, content: &str) -> String {
+    let dir = path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or(".");
+    format!(
+        "mkdir -p \"$HOME\"/{} && printf '%s' {} > \"$HOME\"/{}",
+        util::shell_escape(dir),
+        util::shell_escape(content),
+        util::shell_escape(path)
+    )
+}
+
+/// Crontab without the line carrying `mark`, for adding a fresh one or removing it
+fn crontab_without(mark: &str) -> String {
+    format!("{{ crontab -l 2>/dev/null | grep -vF {}; }}", util::shell_escape(mark))
+}
+
+/// Remote command replacing the crontab line carrying `mark` with `schedule` running `script`.
+/// cron runs the line through sh, which expands $HOME there.
+fn crontab_entry(schedule: &str, script: &str, mark: &str) -> String {
+    let entry = format!("{} \"$HOME\"/{} {}", schedule, script, mark);
+    format!("{{ {}; echo {}; }} | crontab -", crontab_without(mark), util::shell_escape(&entry))
+}
+
+/// Install the script and have `runner` start it at boot, replacing an earlier installation
+pub fn install(config: &Config, sessions: &[SessionTemplate], runner: Runner) -> Result<()> {
+    if sessions.iter().any(|s| s.name.is_empty()) {
+        return Err(anyhow!("every [[hosts.<name>.sessions]] entry needs a name"));
+    }
+    let mut steps = vec![write_file(SCRIPT, &script(config, sessions)), format!("chmod +x \"$HOME\"/{}", SCRIPT)];
+    match runner {
+        Runner::Cron => steps.push(crontab_entry("@reboot", SCRIPT, CRON_MARK)),
+        Runner::Systemd => {
+            steps.push(write_file(UNIT, &unit()));
+            steps.push("systemctl --user daemon-reload && systemctl --user enable vigil-sessions.service".into());
+        }
+    }
+    let output = ssh::exec_remote_capture(config, &steps.join(" && "))?;
+    output.into_stdout().map(drop)
+}
+
+/// Remove the script, the crontab entry, and the systemd unit, whichever exist
+pub fn remove(config: &Config) -> Result<()> {
+    let steps = [
+        format!("{} | crontab -", crontab_without(CRON_MARK)),
+        "{ systemctl --user disable vigil-sessions.service 2>/dev/null; true; }".into(),
+        format!("rm -f \"$HOME\"/{} \"$HOME\"/{}", SCRIPT, UNIT),
+    ];
+    ssh::exec_remote_capture(config, &steps.join("; "))?.into_stdout().map(drop)
+}
+
+/// Script writing a snapshot of every session's windows, panes, and
+/// directories to the autosave directory, replacing each file atomically
+fn autosave_script(config: &Config) -> String {
+    let tmux = config.tmux_cmd();
+    let mut out = String::from("#!/bin/sh\n# Written by `vigil autosave`; snapshots tmux sessions for `vigil --restore-if-missing`\n");
+    out += EXTRA_PATH;
+    out += &format!("dir=\"$HOME\"/{}\nmkdir -p \"$dir\" || exit 1\n", tmux::AUTOSAVE_DIR);
+    // Same file names as tmux::autosave_file
+    out += &format!(
+        "{} list-sessions -F '#{{session_name}}' 2>/dev/null | while IFS= read -r s; do\n    \
+         f=\"$dir/$(printf '%s' \"$s\" | tr / _).panes\"\n    \
+         {} list-panes -s -t \"=$s\" -F {} > \"$f.tmp\" && mv \"$f.tmp\" \"$f\"\n\
+         done\n",
+        tmux,
+        tmux,
+        util::shell_escape(tmux::PANE_FORMAT)
+    );
+    out
+}
+
+/// Install the autosave script, run it every `interval` minutes from cron, and take a first snapshot now
+pub fn install_autosave(config: &Config, interval: u32) -> Result<()> {
+    if !(1..=59).contains(&interval) {
+        return Err(anyhow!("--interval must be between 1 and 59 minutes"));
+    }
+    let steps = [
+        write_file(AUTOSAVE_SCRIPT, &autosave_script(config)),
+        format!("chmod +x \"$HOME\"/{}", AUTOSAVE_SCRIPT),
+        crontab_entry(&format!("*/{} * * * *", interval), AUTOSAVE_SCRIPT, AUTOSAVE_MARK),
+        format!("\"$HOME\"/{}", AUTOSAVE_SCRIPT),
+    ];
+    ssh::exec_remote_capture(config, &steps.join(" && "))?.into_stdout().map(drop)
+}
+
+/// Stop autosaving; existing snapshots are kept
+pub fn remove_autosave(config: &Config) -> Result<()> {
+    let steps = [
+        format!("{} | crontab -", crontab_without(AUTOSAVE_MARK)),
+        format!("rm -f \"$HOME\"/{}", AUTOSAVE_SCRIPT),
+    ];
+    ssh::exec_remote_capture(config, &steps.join("; "))?.into_stdout().map(drop)
+}
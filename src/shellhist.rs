@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use crate::config::Config;
+use crate::ssh;
+use crate::util;
+
+/// Marks the start of one pane's scrollback or one history file in the remote output
+const MARK: &str = "@@vigil ";
+
+/// A command found in a pane or a history file, with its time when recorded
+#[derive(Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub time: Option<u64>,
+    pub command: String,
+}
+
+/// Commands of one pane or history file
+pub struct Source {
+    /// "pane 0.1 (bash)" or the history file's path
+    pub label: String,
+    pub entries: Vec<Entry>,
+}
+
+/// Commands typed in the session's panes, recovered from their scrollback by
+/// spotting shell prompts
+pub fn from_scrollback(config: &Config, session: &str) -> Result<Vec<Source>> {
+    let tmux = config.tmux_cmd();
+    let cmd = format!(
+        "{tmux} list-panes -s -t {target} -F '#{{window_index}}.#{{pane_index}} #{{pane_id}} #{{pane_current_command}}' \
+         | while read -r idx id cmd; do echo \"{mark}pane $idx ($cmd)\"; {tmux} capture-pane -p -J -S - -t \"$id\"; done",
+        tmux = tmux,
+        target = util::shell_escape(&format!("={}", session)),
+        mark = MARK
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    let sources = split_sources(&output, |lines| {
+        let commands = lines.iter().filter_map(|line| prompt_command(line));
+        commands.map(|command| Entry { time: None, command: command.to_string() }).collect()
+    });
+    if sources.is_empty() {
+        return Err(anyhow!("session '{}' not found on the remote host", session));
+    }
+    Ok(sources)
+}
+
+/// The last `limit` lines of the history files of the shells running in the
+/// session's panes: $HISTFILE from the shell's environment when exported,
+/// otherwise the usual file of bash, zsh, or fish
+pub fn from_files(config: &Config, session: &str, limit: usize) -> Result<Vec<Source>> {
+    let cmd = format!(
+        "{tmux} list-panes -s -t {target} -F '#{{pane_pid}}' | while read -r pid; do \
+             f=$(tr '\\0' '\\n' < /proc/\"$pid\"/environ 2>/dev/null | sed -n 's/^HISTFILE=//p'); \
+             if [ -z \"$f\" ]; then case $(ps -o comm= -p \"$pid\") in \
+                 *bash) f=\"$HOME/.bash_history\" ;; \
+                 *zsh) f=\"$HOME/.zsh_history\" ;; \
+                 *fish) f=\"$HOME/.local/share/fish/fish_history\" ;; \
+             esac; fi; \
+             [ -n \"$f\" ] && echo \"$f\"; \
+         done | sort -u | while read -r f; do [ -r \"$f\" ] && echo \"{mark}$f\" && tail -n {limit} \"$f\"; done",
+        tmux = config.tmux_cmd(),
+        target = util::shell_escape(&format!("={}", session)),
+        mark = MARK,
+        // fish spends two or three lines on each command
+        limit = limit.saturating_mul(3)
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    Ok(split_sources(&output, |lines| {
+        let entries = parse_history(lines);
+        let skip = entries.len().saturating_sub(limit);
+        entries.into_iter().skip(skip).collect()
+    }))
+}
+
+/// Split remote output at the marker lines, parsing each part with `parse`
+fn split_sources(output: &str, parse: impl Fn(&[&str]) -> Vec<Entry>) -> Vec<Source> {
+    let mut sources = Vec::new();
+    let mut current: Option<(String, Vec<&str>)> = None;
+    for line in output.lines() {
+        if let Some(label) = line.strip_prefix(MARK) {
+            sources.extend(current.take().map(|(label, lines)| Source { label, entries: parse(&lines) }));
+            current = Some((label.to_string(), Vec::new()));
+        } else if let Some((_, lines)) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    sources.extend(current.map(|(label, lines)| Source { label, entries: parse(&lines) }));
+    sources
+}
+
+/// The command on a line that looks like a shell prompt followed by input,
+/// e.g. "me@box:~/src$ make" or "❯ git status". Prompts ending in $, #, or %
+/// need a user, host, or path right before the sign so ordinary output
+/// rarely matches.
+pub fn prompt_command(line: &str) -> Option<&str> {
+    const SIGNS: [&str; 5] = ["$ ", "# ", "% ", "❯ ", "» "];
+    let (at, sign) = SIGNS.iter().filter_map(|sign| line.find(sign).map(|at| (at, *sign))).min()?;
+    let prompt = line[..at].trim_end();
+    // The word before the sign: "me@box:~/src", "~]", "(main)"...
+    let last_word = prompt.rsplit(' ').next().unwrap_or("");
+    let looks_like_prompt = prompt.chars().count() <= 60
+        && (matches!(sign, "❯ " | "» ") || last_word.contains(['@', '~', '/', ']', ':', ')']));
+    let command = line[at + sign.len()..].trim();
+    (looks_like_prompt && !command.is_empty()).then_some(command)
+}
+
+/// Entries of a bash (with "#<time>" lines from HISTTIMEFORMAT), zsh
+/// (plain or ": <time>:<duration>;<command>"), or fish history file
+pub fn parse_history(lines: &[&str]) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut time = None;
+    let fish = lines.iter().any(|l| l.starts_with("- cmd: "));
+    for line in lines {
+        if let Some(stamp) = line.strip_prefix('#').filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())) {
+            time = stamp.parse().ok();
+        } else if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push(Entry { time: None, command: command.to_string() });
+        } else if let Some(stamp) = line.trim_start().strip_prefix("when: ") {
+            if let Some(last) = entries.last_mut() {
+                last.time = stamp.trim().parse().ok();
+            }
+        } else if fish && line.starts_with("  ") {
+            // Further fish fields (paths:) belong to the previous command
+        } else if let Some((stamp, command)) = line.strip_prefix(": ").and_then(|rest| rest.split_once(';')) {
+            let stamp = stamp.split(':').next().and_then(|s| s.trim().parse().ok());
+            entries.push(Entry { time: stamp, command: command.to_string() });
+        } else if !line.trim().is_empty() {
+            entries.push(Entry { time: time.take(), command: line.to_string() });
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompt_lines_yield_their_command() {
+        assert_eq!(prompt_command("me@box:~/src$ make test"), Some("make test"));
+        assert_eq!(prompt_command("[root@db1 ~]# systemctl restart pg"), Some("systemctl restart pg"));
+        assert_eq!(prompt_command("❯ git status"), Some("git status"));
+        assert_eq!(prompt_command("me@box:~$ "), None);
+        assert_eq!(prompt_command("Total: 5 items, 20% done"), None);
+        assert_eq!(prompt_command("price is 5$ today"), None);
+    }
+
+    #[test]
+    fn history_formats_are_parsed_with_times() {
+        let bash = ["#1700000000", "ls -la", "make"];
+        assert_eq!(
+            parse_history(&bash),
+            [
+                Entry { time: Some(1_700_000_000), command: "ls -la".into() },
+                Entry { time: None, command: "make".into() }
+            ]
+        );
+        let zsh = [": 1700000100:0;cargo build", "echo plain"];
+        assert_eq!(parse_history(&zsh)[0], Entry { time: Some(1_700_000_100), command: "cargo build".into() });
+        assert_eq!(parse_history(&zsh)[1].command, "echo plain");
+        let fish = ["- cmd: cd /srv", "  when: 1700000200", "  paths:", "    - /srv"];
+        assert_eq!(parse_history(&fish), [Entry { time: Some(1_700_000_200), command: "cd /srv".into() }]);
+    }
+}
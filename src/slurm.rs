@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use crate::util;
+
+/// Slurm allocation the session's first window runs in, from a
+/// `[hosts.<name>.slurm]` table and the --partition/--time/--cpus flags
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Allocation {
+    /// Partition (queue) to allocate from (srun --partition)
+    pub partition: Option<String>,
+    /// Time limit, e.g. "8:00:00" or "2-00:00:00" (srun --time)
+    pub time: Option<String>,
+    /// CPUs for the job (srun --cpus-per-task)
+    pub cpus: Option<u32>,
+    /// Further srun arguments, e.g. ["--account=lab", "--gres=gpu:1"]
+    pub args: Vec<String>,
+}
+
+impl Allocation {
+    /// Shell command for tmux new-session: an interactive login shell on the
+    /// allocated compute node. tmux itself stays on the login node, so the
+    /// job keeps running while nobody is attached. A failed request stays on
+    /// screen until Enter instead of closing the window at once.
+    pub fn shell_command(&self) -> String {
+        let mut command = vec!["srun".to_string(), "--pty".to_string()];
+        if let Some(partition) = &self.partition {
+            command.push(format!("--partition={}", util::shell_escape(partition)));
+        }
+        if let Some(time) = &self.time {
+            command.push(format!("--time={}", util::shell_escape(time)));
+        }
+        if let Some(cpus) = self.cpus {
+            command.push(format!("--cpus-per-task={}", cpus));
+        }
+        command.extend(self.args.iter().map(|arg| util::shell_escape(arg)));
+        command.push("\"${SHELL:-/bin/sh}\" -l".into());
+        let notice = format!("[vigil] Requesting a Slurm allocation ({})...", self.describe());
+        format!(
+            "echo {}; {} || {{ echo \"[vigil] srun exited with status $?; press Enter to close.\"; read -r _; }}",
+            util::shell_escape(&notice),
+            command.join(" ")
+        )
+    }
+
+    /// Short description, e.g. "partition gpu, 8:00:00, 4 CPUs"
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(partition) = &self.partition {
+            parts.push(format!("partition {}", partition));
+        }
+        if let Some(time) = &self.time {
+            parts.push(time.clone());
+        }
+        if let Some(cpus) = self.cpus {
+            parts.push(format!("{} CPU{}", cpus, if cpus == 1 { "" } else { "s" }));
+        }
+        if parts.is_empty() {
+            "default partition and limits".into()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
@@ -1,26 +1,441 @@
 use anyhow::{anyhow, Context, Result};
+use std::fmt;
+use std::io::{Read, Write};
 use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
 use crate::config::Config;
 use crate::ui;
+use crate::util;
+
+/// The OpenSSH client binary (OpenSSH for Windows ships it as ssh.exe)
+pub const SSH_PROG: &str = if cfg!(windows) { "ssh.exe" } else { "ssh" };
+
+/// The Windows OpenSSH client as reachable from WSL through interop
+pub const WINDOWS_SSH_PROG: &str = "ssh.exe";
+
+/// Flag forcing TTY allocation. Under Windows consoles (conpty) ssh.exe often
+/// fails to detect a terminal on stdin and ignores a single -t, so force it.
+pub const TTY_FLAG: &str = if cfg!(windows) { "-tt" } else { "-t" };
+
+/// PuTTY's command-line client
+pub const PLINK_PROG: &str = if cfg!(windows) { "plink.exe" } else { "plink" };
+
+/// Program used to reach remote hosts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// OpenSSH client
+    #[default]
+    Ssh,
+    /// PuTTY's plink (keys from Pageant, sessions saved in PuTTY)
+    Plink,
+}
+
+/// Translate OpenSSH-style arguments for plink: -p becomes -P, -tt becomes
+/// -t, and BatchMode=yes becomes -batch. Other -o options have no plink
+/// equivalent and are dropped with a warning. Plink's anti-spoofing prompt
+/// would get in the way of tmux, so it is turned off.
+pub fn plink_args(ssh_args: &[String]) -> Vec<String> {
+    let mut out = vec!["-ssh".to_string(), "-no-antispoof".to_string()];
+    out.extend(plink_options(ssh_args));
+    out
+}
+
+/// [`plink_args`] without the leading options, for arguments added to ones
+/// already translated
+pub fn plink_options(ssh_args: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut args = ssh_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" => out.push("-P".into()),
+            "-tt" => out.push("-t".into()),
+            "-o" => match args.next().map(String::as_str) {
+                Some("BatchMode=yes") => out.push("-batch".into()),
+                Some(opt) => ui::warn(&format!("plink has no equivalent for ssh option '-o {}'; ignoring it", opt)),
+                None => {}
+            },
+            _ => match arg.strip_prefix("-p").filter(|port| port.chars().all(|c| c.is_ascii_digit())) {
+                Some(port) if !port.is_empty() => out.extend(["-P".into(), port.to_string()]),
+                _ => out.push(arg.clone()),
+            },
+        }
+    }
+    out
+}
+
+/// Undo [`plink_args`], for a bookmark that goes back to OpenSSH; the -o
+/// options plink had no equivalent for stay dropped
+pub fn openssh_args(plink_args: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    for arg in plink_args {
+        match arg.as_str() {
+            "-ssh" | "-no-antispoof" => {}
+            "-P" => out.push("-p".into()),
+            "-batch" => out.extend(["-o".into(), "BatchMode=yes".into()]),
+            _ => out.push(arg.clone()),
+        }
+    }
+    out
+}
 
 /// Infer SSH program and normalize arguments
 pub fn infer_ssh_prog(ssh_args: &[String]) -> Result<(String, Vec<String>)> {
-    let prog = "ssh".to_string();
+    let prog = SSH_PROG.to_string();
     Ok((prog, ssh_args.to_vec()))
 }
 
+/// ssh options that consume the following argument
+const SSH_OPTS_WITH_VALUE: &str = "BbcDEeFIiJLlmOoPpQRSWw";
+
+/// Find the destination (e.g. user@host) among ssh arguments: the first
+/// argument that is neither an option nor an option's value.
+pub fn destination(ssh_args: &[String]) -> Option<&str> {
+    destination_index(ssh_args).map(|i| ssh_args[i].as_str())
+}
+
+/// Index of the destination within ssh arguments (see [`destination`])
+pub fn destination_index(ssh_args: &[String]) -> Option<usize> {
+    let mut i = 0;
+    while i < ssh_args.len() {
+        let arg = &ssh_args[i];
+        if arg == "--" {
+            return (i + 1 < ssh_args.len()).then_some(i + 1);
+        }
+        if let Some(flags) = arg.strip_prefix('-') {
+            // A value-taking flag at the end of a cluster (e.g. "-p" or "-4p")
+            // consumes the next argument unless the value is attached.
+            if let Some(pos) = flags.find(|c| SSH_OPTS_WITH_VALUE.contains(c)) {
+                if pos == flags.len() - 1 {
+                    i += 1;
+                }
+            }
+            i += 1;
+            continue;
+        }
+        return Some(i);
+    }
+    None
+}
+
+/// Move ssh options that follow the destination (e.g. `host -p 2222`) to
+/// just before it, stopping at the first argument that is not an option
+pub fn hoist_trailing_options(ssh_args: &mut [String]) {
+    let Some(dest) = destination_index(ssh_args) else { return };
+    let mut end = dest + 1;
+    while let Some(flags) = ssh_args.get(end).and_then(|arg| arg.strip_prefix('-')).filter(|f| !f.is_empty() && *f != "-") {
+        // As in destination_index, a value-taking flag ending the cluster takes the next argument
+        let takes_value = flags.find(|c| SSH_OPTS_WITH_VALUE.contains(c)) == Some(flags.len() - 1);
+        end = (end + 1 + takes_value as usize).min(ssh_args.len());
+    }
+    ssh_args[dest..end].rotate_left(1);
+}
+
+/// Where ssh really connects once ssh_config is applied (aliases, User,
+/// Port, ProxyJump), as reported by `ssh -G`
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    pub user: String,
+    pub hostname: String,
+    pub port: String,
+    pub proxy_jump: Option<String>,
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}:{}", self.user, self.hostname, self.port)?;
+        if let Some(jump) = &self.proxy_jump {
+            write!(f, " via {}", jump)?;
+        }
+        Ok(())
+    }
+}
+
+/// Resolve the config's destination with `ssh -G`, which only evaluates
+/// ssh_config and never connects. Not available with plink.
+pub fn resolve_endpoint(config: &Config) -> Result<Endpoint> {
+    if config.transport == Transport::Plink {
+        return Err(anyhow!("plink cannot resolve hosts like ssh -G"));
+    }
+    let end = destination_index(&config.ssh_args)
+        .ok_or_else(|| anyhow!("no destination given"))?;
+    let mut args = vec!["-G".to_string()];
+    args.extend(config.ssh_args[..=end].iter().filter(|a| *a != "-t" && *a != "-tt").cloned());
+    let output = config.ssh_command()
+        .args(&args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run {} -G", config.ssh_prog))?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let value = |key: &str| {
+        text.lines()
+            .find_map(|l| l.strip_prefix(key).and_then(|v| v.strip_prefix(' ')))
+            .map(str::to_string)
+    };
+    match (value("user"), value("hostname"), value("port")) {
+        (Some(user), Some(hostname), Some(port)) => Ok(Endpoint {
+            user,
+            hostname,
+            port,
+            proxy_jump: value("proxyjump").filter(|j| j != "none"),
+        }),
+        _ => Err(anyhow!("could not resolve the address of {}", config.host_label())),
+    }
+}
+
+/// ssh options sharing one master connection per host through a socket in
+/// vigil's cache directory
+pub fn control_args(persist: &str) -> Vec<String> {
+    let path = util::cache_dir().join("cm-%C");
+    vec![
+        "-o".into(),
+        "ControlMaster=auto".into(),
+        "-o".into(),
+        format!("ControlPath={}", path.display()),
+        "-o".into(),
+        format!("ControlPersist={}", persist),
+    ]
+}
+
+/// ssh options probing an idle connection every `interval` seconds, so
+/// NAT timeouts do not drop it and a dead link is noticed after three misses
+pub fn keepalive_args(interval: u32) -> Vec<String> {
+    vec![
+        "-o".into(),
+        format!("ServerAliveInterval={}", interval),
+        "-o".into(),
+        "ServerAliveCountMax=3".into(),
+    ]
+}
+
+/// ssh options hiding the pre-login banner and informational chatter such as
+/// "Warning: Permanently added ...", while connection errors still show
+pub fn quiet_args() -> Vec<String> {
+    vec!["-o".into(), "LogLevel=ERROR".into()]
+}
+
+/// ssh arguments without TTY flags, for control (-O) and background commands
+fn plain_args(config: &Config) -> Vec<String> {
+    let mut ssh_args = config.ssh_args.clone();
+    ssh_args.retain(|a| a != "-t" && a != "-tt");
+    ssh_args
+}
+
+/// Whether a master connection for the host is running
+pub fn check_master(config: &Config) -> bool {
+    let mut args = plain_args(config);
+    args.splice(0..0, ["-O".to_string(), "check".to_string()]);
+    config.ssh_command()
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
+
+/// Start a background master connection for the host (ssh -f -N)
+pub fn start_master(config: &Config) -> Result<()> {
+    let mut args = plain_args(config);
+    args.splice(0..0, ["-f".to_string(), "-N".to_string()]);
+    let output = config.ssh_command()
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "could not open master connection: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Time one SSH round trip running `true` on the remote host
+pub fn ping(config: &Config) -> Result<Duration> {
+    let mut args = plain_args(config);
+    args.push("true".into());
+    let started = Instant::now();
+    let output = config.ssh_command()
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))?;
+    let elapsed = started.elapsed();
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ssh exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(elapsed)
+}
+
+/// Details of a "REMOTE HOST IDENTIFICATION HAS CHANGED" failure
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostKeyChange {
+    /// Host as written in known_hosts (e.g. "example.com" or "[example.com]:2222")
+    pub host: String,
+    /// Fingerprint of the key the host presents now
+    pub fingerprint: Option<String>,
+    /// known_hosts file and line holding the old key, e.g. "~/.ssh/known_hosts:12"
+    pub offending: Option<String>,
+}
+
+/// Pick the relevant bits out of ssh's host key change warning
+pub fn parse_host_key_change(stderr: &str) -> Option<HostKeyChange> {
+    if !stderr.contains("REMOTE HOST IDENTIFICATION HAS CHANGED") {
+        return None;
+    }
+    let mut change = HostKeyChange::default();
+    let mut lines = stderr.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line.starts_with("The fingerprint for the") {
+            // The fingerprint follows on the next line, with a trailing "."
+            change.fingerprint = lines.next().map(|f| f.trim_end_matches('.').to_string());
+        } else if let Some(rest) = line.strip_prefix("Offending ") {
+            change.offending = rest.split_once(" key in ").map(|(_, loc)| loc.to_string());
+        } else if let Some(pos) = line.to_ascii_lowercase().find("host key for ") {
+            // "Host key for H has changed" or "ED25519 host key for H has changed"
+            let rest = &line[pos + "host key for ".len()..];
+            if let Some((host, _)) = rest.split_once(" has changed") {
+                change.host = host.to_string();
+            }
+        }
+    }
+    (!change.host.is_empty()).then_some(change)
+}
+
+/// After a failed connection, check whether the host key changed. If so,
+/// explain it briefly and offer `ssh-keygen -R` after explicit confirmation;
+/// the new key still has to be accepted on the next connection as usual.
+pub fn diagnose_host_key(config: &Config) -> Result<()> {
+    if config.transport != Transport::Ssh {
+        return Ok(());
+    }
+    let mut args = plain_args(config);
+    let at = destination_index(&args).unwrap_or(args.len());
+    args.splice(at..at, ["-o".to_string(), "BatchMode=yes".to_string()]);
+    args.push("true".into());
+    let output = config.ssh_command()
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))?;
+    let Some(change) = parse_host_key_change(&String::from_utf8_lossy(&output.stderr)) else {
+        return Ok(());
+    };
+    if let Some(pinned) = &config.host_fingerprint {
+        return Err(anyhow!(
+            "{} no longer presents the pinned host key {} (now {}). Refusing to connect.",
+            config.host_label(),
+            pinned,
+            change.fingerprint.as_deref().unwrap_or("unknown")
+        ));
+    }
+
+    ui::error(&format!(
+        "the host key for {} has changed since you last connected.",
+        ui::host(&change.host)
+    ));
+    eprintln!("  This can be a reinstalled server, or someone intercepting the connection.");
+    if let Some(fingerprint) = &change.fingerprint {
+        eprintln!("  Key offered now:  {}", fingerprint);
+    }
+    if let Some(offending) = &change.offending {
+        eprintln!("  Old key stored at: {}", offending);
+    }
+    eprintln!("  Verify the new fingerprint with the host's administrator before continuing.");
+
+    if !ui::confirm(&format!("Remove the old key for {} from known_hosts?", change.host))? {
+        return Ok(());
+    }
+    let mut keygen = Command::new("ssh-keygen");
+    keygen.args(["-R", &change.host]);
+    if let Some((file, _)) = change.offending.as_deref().and_then(|o| o.rsplit_once(':')) {
+        keygen.args(["-f", file]);
+    }
+    let status = keygen.status().context("failed to run ssh-keygen")?;
+    if !status.success() {
+        return Err(anyhow!("ssh-keygen -R exited with status: {}", status));
+    }
+    ui::status("Old key removed; ssh will ask you to accept the new one on the next connection.");
+    Ok(())
+}
+
+/// What the local ssh-agent currently offers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentState {
+    NoAgent,
+    NoKeys,
+    Keys(usize),
+}
+
+/// Query the ssh-agent with `ssh-add -l`
+pub fn agent_state() -> AgentState {
+    let output = Command::new("ssh-add")
+        .arg("-l")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(out) if out.status.success() => {
+            AgentState::Keys(String::from_utf8_lossy(&out.stdout).lines().count())
+        }
+        // ssh-add exits 1 when the agent has no identities, 2 when unreachable
+        Ok(out) if out.status.code() == Some(1) => AgentState::NoKeys,
+        _ => AgentState::NoAgent,
+    }
+}
+
+/// Catch a missing or empty agent before connecting, instead of a silent
+/// publickey failure followed by a password prompt. With an empty agent the
+/// user is offered an interactive `ssh-add`.
+pub fn check_agent_interactive() -> Result<()> {
+    match agent_state() {
+        AgentState::Keys(_) => Ok(()),
+        AgentState::NoAgent => {
+            ui::warn("no ssh-agent reachable (SSH_AUTH_SOCK unset or stale); key authentication may fail.");
+            Ok(())
+        }
+        AgentState::NoKeys => {
+            if !ui::confirm("ssh-agent holds no keys. Run `ssh-add` now?")? {
+                return Ok(());
+            }
+            let status = Command::new("ssh-add")
+                .status()
+                .context("failed to run ssh-add")?;
+            if !status.success() {
+                ui::warn("ssh-add did not add any keys; continuing anyway.");
+            }
+            Ok(())
+        }
+    }
+}
+
 /// Execute a command over SSH on the remote host
 pub fn exec_remote_command(
     config: &Config,
     command: &str,
 ) -> Result<()> {
     let mut ssh_args = config.ssh_args.clone();
-    ssh_args.push(command.to_string());
+    ssh_args.push(config.remote_command(command));
 
-    config.debug_print(&format!("ssh prog: {}", config.ssh_prog));
+    config.debug_print(&format!("ssh prog: {}", config.ssh_argv().join(" ")));
     config.debug_print(&format!("ssh args (final): {:?}", ssh_args));
 
-    let status = Command::new(&config.ssh_prog)
+    let status = config.ssh_command()
         .args(&ssh_args)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
@@ -30,12 +445,9 @@ pub fn exec_remote_command(
 
     if !status.success() {
         if let Some(127) = status.code() {
-            ui::error(
-                "Remote reported 'command not found' — tmux may not be installed.\n  \
-                 - Debian/Ubuntu: sudo apt-get install tmux\n  \
-                 - RHEL/CentOS/Fedora: sudo yum install tmux (or dnf)\n  \
-                 - macOS (Homebrew): brew install tmux"
-            );
+            // One more connection to learn the system, for its install command
+            let os_probe = exec_remote_capture(config, util::OS_PROBE).map(|output| output.stdout);
+            ui::error(&util::tmux_install_hint(&os_probe.unwrap_or_default()));
         }
         return Err(anyhow!("remote command exited with status: {}", status));
     }
@@ -43,26 +455,235 @@ pub fn exec_remote_command(
     Ok(())
 }
 
-/// Execute SSH command and capture output
+/// Run a command on the remote host with stdio passed through, returning
+/// ssh's exit status (the remote command's status, or 255 on ssh errors)
+pub fn exec_remote_passthrough(
+    config: &Config,
+    command: &[String],
+    tty: bool,
+) -> Result<std::process::ExitStatus> {
+    let mut ssh_args = plain_args(config);
+    if tty {
+        ssh_args.insert(0, config.tty_flag().into());
+    }
+    if config.login_shell.is_some() {
+        ssh_args.push(config.remote_command(&command.join(" ")));
+    } else {
+        ssh_args.extend(command.iter().cloned());
+    }
+
+    config.debug_print(&format!("ssh args (exec): {:?}", ssh_args));
+
+    config.ssh_command()
+        .args(&ssh_args)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))
+}
+
+/// Run a command on the remote host, handing its output to `on_output` as
+/// it arrives; stderr stays on the terminal. Returns ssh's exit status.
+pub fn exec_remote_streaming(
+    config: &Config,
+    command: &str,
+    mut on_output: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<std::process::ExitStatus> {
+    let mut ssh_args = plain_args(config);
+    ssh_args.push(config.remote_command(command));
+
+    config.debug_print(&format!("executing remote (streaming): {}", command));
+
+    let mut child = config.ssh_command()
+        .args(&ssh_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))?;
+
+    let mut stdout = child.stdout.take().context("no output from ssh")?;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match stdout.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("failed to read from ssh"),
+        };
+        if let Err(e) = on_output(&buf[..n]) {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(e);
+        }
+    }
+    Ok(child.wait()?)
+}
+
+/// Backoff before retry number `attempt` (1-based): base * 2^(attempt-1),
+/// plus up to 50% jitter so parallel invocations don't retry in lockstep
+fn retry_delay(base_ms: u64, attempt: u32) -> Duration {
+    let backoff = base_ms.saturating_mul(1 << (attempt - 1).min(10));
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter = if backoff > 0 { nanos % (backoff / 2 + 1) } else { 0 };
+    Duration::from_millis(backoff + jitter)
+}
+
+/// Captured result of a remote command
+#[derive(Debug, Clone)]
+pub struct RemoteOutput {
+    /// Exit status of ssh: the remote command's status, or 255 if ssh failed
+    pub status: std::process::ExitStatus,
+    pub stdout: String,
+    /// Empty with --password-auth, where stderr stays on the terminal
+    pub stderr: String,
+}
+
+impl RemoteOutput {
+    pub fn success(&self) -> bool {
+        self.status.success()
+    }
+
+    /// Whether ssh itself failed (connection, authentication, host key)
+    pub fn ssh_failed(&self) -> bool {
+        self.status.code() == Some(255)
+    }
+
+    /// The remote error text, or the exit status when there is none
+    pub fn error_text(&self) -> String {
+        match self.stderr.trim() {
+            "" => format!("exited with {}", self.status),
+            text => text.to_string(),
+        }
+    }
+
+    /// stdout of a successful command; otherwise an error carrying stderr
+    pub fn into_stdout(self) -> Result<String> {
+        if !self.success() {
+            return Err(anyhow!("remote command failed: {}", self.error_text()));
+        }
+        Ok(self.stdout)
+    }
+}
+
+/// Execute SSH command capturing stdout, stderr, and the exit status
 pub fn exec_remote_capture(
     config: &Config,
     command: &str,
-) -> Result<String> {
+) -> Result<RemoteOutput> {
     let mut ssh_args = config.ssh_args.clone();
     
     // Remove TTY flags for non-interactive commands
     ssh_args.retain(|a| a != "-t" && a != "-tt");
-    
-    ssh_args.push(command.to_string());
+    if config.password_auth {
+        // ssh asks for passwords and keyboard-interactive answers on /dev/tty;
+        // the forced remote pty merges the command's stderr into stdout
+        let mut opts = vec![if config.transport == Transport::Plink { "-t" } else { "-tt" }.to_string()];
+        if config.transport == Transport::Ssh {
+            opts.extend(["-o".to_string(), "BatchMode=no".to_string()]);
+        }
+        // Before the destination, where ssh still reads options
+        let at = destination_index(&ssh_args).unwrap_or(ssh_args.len());
+        ssh_args.splice(at..at, opts);
+    }
+
+    ssh_args.push(config.remote_command(command));
 
     config.debug_print(&format!("executing remote (capture): {}", command));
 
-    let output = Command::new(&config.ssh_prog)
-        .args(&ssh_args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+    // ssh exits 255 on its own failures (dropped or timed-out connections);
+    // those are retried with jittered exponential backoff, except where each
+    // attempt would ask for the password again
+    let retries = if config.password_auth { 0 } else { config.retries };
+    let mut attempt = 0;
+    let output = loop {
+        let stdin = if config.password_auth { Stdio::inherit() } else { Stdio::null() };
+        let output = config.ssh_command()
+            .args(&ssh_args)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| format!("failed to execute {} for remote command", config.ssh_prog))?;
+        if output.status.code() != Some(255) || attempt >= retries {
+            break output;
+        }
+        attempt += 1;
+        let delay = retry_delay(config.retry_backoff_ms, attempt);
+        config.debug_print(&format!(
+            "ssh failed ({}); retry {}/{} in {}ms",
+            String::from_utf8_lossy(&output.stderr).trim(),
+            attempt,
+            retries,
+            delay.as_millis()
+        ));
+        std::thread::sleep(delay);
+    };
+
+    let mut stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if config.password_auth {
+        // The pty turns newlines into CRLF
+        stdout = stdout.replace("\r\n", "\n");
+    }
+    Ok(RemoteOutput {
+        status: output.status,
+        stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+/// Feed passwords through sshpass: the password is read from the output of
+/// the `VIGIL_ASKPASS` command and handed over in the SSHPASS variable.
+pub fn wrap_with_sshpass(config: &mut Config, askpass: &str) -> Result<()> {
+    let output = Command::new("sh")
+        .args(["-c", askpass])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
         .output()
-        .with_context(|| format!("failed to execute {} for remote command", config.ssh_prog))?;
+        .context("failed to run VIGIL_ASKPASS command")?;
+    if !output.status.success() {
+        return Err(anyhow!("VIGIL_ASKPASS command exited with status: {}", output.status));
+    }
+    let password = String::from_utf8_lossy(&output.stdout);
+    std::env::set_var("SSHPASS", password.trim_end_matches(['\r', '\n']));
+
+    if !util::check_ssh_available("sshpass") {
+        return Err(anyhow!("VIGIL_ASKPASS is set but `sshpass` was not found in PATH"));
+    }
+    config.ssh_wrapper = vec!["sshpass".into(), "-e".into()];
+    Ok(())
+}
+
+/// Execute SSH command feeding `input` on its stdin
+pub fn exec_remote_with_input(
+    config: &Config,
+    command: &str,
+    input: &[u8],
+) -> Result<()> {
+    let mut ssh_args = config.ssh_args.clone();
+    ssh_args.retain(|a| a != "-t" && a != "-tt");
+    ssh_args.push(config.remote_command(command));
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    config.debug_print(&format!("executing remote (with input): {}", command));
+
+    let mut child = config.ssh_command()
+        .args(&ssh_args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to execute {}", config.ssh_prog))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).context("failed to write to remote command")?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("remote command exited with status: {}", status));
+    }
+    Ok(())
 }
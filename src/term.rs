@@ -0,0 +1,118 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use crate::config::Config;
+use crate::ssh;
+use crate::ui;
+use crate::util;
+
+/// TERM used when the local terminal type is unknown to the remote host
+pub const FALLBACK_TERM: &str = "xterm-256color";
+
+/// Terminal types assumed to exist in every terminfo database
+const COMMON_TERMS: &[&str] = &[
+    "xterm", "xterm-256color", "screen", "screen-256color", "tmux", "tmux-256color",
+    "vt100", "vt220", "linux", "dumb", "ansi",
+];
+
+/// Terminal attributes of stdin captured by [`save_termios`]
+#[cfg(unix)]
+static SAVED_TERMIOS: std::sync::OnceLock<libc::termios> = std::sync::OnceLock::new();
+
+/// Remember the current terminal attributes of stdin (first call wins) so
+/// they can be put back by [`restore_termios`].
+#[cfg(unix)]
+pub fn save_termios() {
+    // SAFETY: tcgetattr only writes into the termios struct we pass in
+    let mut attrs: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut attrs) } == 0 {
+        let _ = SAVED_TERMIOS.set(attrs);
+    }
+}
+
+/// Put back the attributes saved by [`save_termios`]. Only async-signal-safe
+/// calls are made, so this may run inside a signal handler.
+#[cfg(unix)]
+pub fn restore_termios() {
+    if let Some(attrs) = SAVED_TERMIOS.get() {
+        // SAFETY: tcsetattr reads the saved struct and touches nothing else
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, attrs) };
+    }
+}
+
+/// Undo what a remote program that died mid-session may have left behind:
+/// raw mode and disabled echo, mouse reporting, and a hidden cursor.
+pub fn reset_terminal() {
+    restore_termios();
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let _ = write!(stderr, "\x1b[?1000l\x1b[?1002l\x1b[?1006l\x1b[?25h");
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(not(unix))]
+pub fn save_termios() {}
+
+#[cfg(not(unix))]
+pub fn restore_termios() {}
+
+/// Make sure the local $TERM will be understood on the remote host. Unusual
+/// terminal types (xterm-kitty, wezterm, ...) are checked with infocmp
+/// remotely; if missing, the entry is pushed with tic (when `push` is set) or
+/// TERM is overridden with a safe fallback for the ssh invocation.
+pub fn ensure_remote_term(config: &mut Config, push: bool) -> Result<()> {
+    let term = match std::env::var("TERM") {
+        Ok(t) if !t.is_empty() => t,
+        _ => return Ok(()),
+    };
+    if COMMON_TERMS.contains(&term.as_str()) {
+        return Ok(());
+    }
+
+    let check = format!(
+        "infocmp {} >/dev/null 2>&1 && echo present",
+        util::shell_escape(&term)
+    );
+    let output = ssh::exec_remote_capture(config, &check)?;
+    if output.ssh_failed() {
+        // Leave reporting the connection problem to the attach itself
+        config.debug_print(&format!("terminfo check failed: {}", output.error_text()));
+        return Ok(());
+    }
+    if output.stdout.trim() == "present" {
+        config.debug_print(&format!("remote terminfo has {}", term));
+        return Ok(());
+    }
+
+    if push {
+        push_terminfo(config, &term)?;
+        ui::status(&format!("Installed terminfo entry for '{}' on the remote host.", term));
+        return Ok(());
+    }
+
+    ui::warn(&format!(
+        "remote host has no terminfo entry for '{}'; using TERM={} (use --push-terminfo to install it)",
+        term, FALLBACK_TERM
+    ));
+    config.term = Some(FALLBACK_TERM.to_string());
+    Ok(())
+}
+
+/// Compile the local terminfo entry for `term` into ~/.terminfo on the remote host
+fn push_terminfo(config: &Config, term: &str) -> Result<()> {
+    let output = Command::new("infocmp")
+        .args(["-x", term])
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to run infocmp locally")?;
+    if !output.status.success() || output.stdout.is_empty() {
+        return Err(anyhow!("no local terminfo entry for '{}'", term));
+    }
+
+    ssh::exec_remote_with_input(
+        config,
+        "mkdir -p ~/.terminfo && tic -x -o ~/.terminfo /dev/stdin",
+        &output.stdout,
+    )
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PHASES: Mutex<Vec<(String, Duration)>> = Mutex::new(Vec::new());
+
+/// Print the collected phase timings on exit (--timings)
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, recording how long it took under `name`
+pub fn phase<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    if let Ok(mut phases) = PHASES.lock() {
+        phases.push((name.to_string(), started.elapsed()));
+    }
+    result
+}
+
+/// Print recorded phases to stderr if timings are enabled
+pub fn report() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Ok(phases) = PHASES.lock() else { return };
+    let width = phases.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    eprintln!("[vigil] timings:");
+    for (name, elapsed) in phases.iter() {
+        eprintln!("  {:<width$}  {:>9.1} ms", name, elapsed.as_secs_f64() * 1000.0, width = width);
+    }
+    let total: Duration = phases.iter().map(|(_, d)| *d).sum();
+    eprintln!("  {:<width$}  {:>9.1} ms", "total", total.as_secs_f64() * 1000.0, width = width);
+}
@@ -1,104 +1,1454 @@
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
+use crate::cache;
 use crate::config::Config;
+use crate::events;
+use crate::health::{self, HostHealth};
+use crate::pty;
+use crate::record;
 use crate::ssh;
+use crate::term;
+use crate::timings;
+use crate::ui;
 use crate::util;
 
+/// A tmux release as (major, minor); letter suffixes as in "3.3a" are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TmuxVersion(pub u32, pub u32);
+
+impl TmuxVersion {
+    /// First release with `new-session -A` and `-F` formats
+    pub const FORMATS: TmuxVersion = TmuxVersion(1, 8);
+    /// First release where window-size can follow the most recently active client
+    pub const WINDOW_SIZE_LATEST: TmuxVersion = TmuxVersion(3, 1);
+    /// First release where attach-session takes client flags (-f read-only,ignore-size)
+    pub const CLIENT_FLAGS: TmuxVersion = TmuxVersion(3, 2);
+    /// First release with the terminal-features option
+    pub const TERMINAL_FEATURES: TmuxVersion = TmuxVersion(3, 2);
+
+    /// Parse "3.3a", "next-3.4", or the whole `tmux -V` output ("tmux 3.3a").
+    /// Builds like "master" or "openbsd-7.4" give `None`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let word = text.split_whitespace().last()?;
+        let word = word.strip_prefix("next-").unwrap_or(word);
+        let (major, rest) = word.split_once('.')?;
+        let minor: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        Some(TmuxVersion(major.parse().ok()?, minor.parse().ok()?))
+    }
+}
+
+impl fmt::Display for TmuxVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.0, self.1)
+    }
+}
+
+/// Remote shell word for a path, keeping a leading "~/" expandable
+pub fn remote_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("\"$HOME\"/{}", util::shell_escape(rest)),
+        None => util::shell_escape(path),
+    }
+}
+
+/// `tmux -V` with the configured binary or, when that is not found (exit
+/// 127), with the first of `fallbacks` that exists, printed as "path:<path>"
+fn probe_command(config: &Config, fallbacks: &[String]) -> String {
+    let check = format!("{} -V", config.tmux_bin);
+    if fallbacks.is_empty() {
+        return check;
+    }
+    let paths: Vec<String> = fallbacks.iter().map(|p| remote_path(p)).collect();
+    format!(
+        "{} && exit; [ $? -eq 127 ] || exit; \
+         for t in {}; do if [ -x \"$t\" ]; then echo \"path:$t\"; exec \"$t\" -V; fi; done; exit 127",
+        check,
+        paths.join(" ")
+    )
+}
+
+fn parse_probe(stdout: &str) -> cache::TmuxProbe {
+    let mut lines = stdout.lines();
+    let first = lines.next().unwrap_or("").trim();
+    match first.strip_prefix("path:") {
+        Some(path) => cache::TmuxProbe {
+            version: lines.next().unwrap_or("").trim().to_string(),
+            path: Some(path.to_string()),
+        },
+        None => cache::TmuxProbe { version: first.to_string(), path: None },
+    }
+}
+
+/// Learn the remote tmux version (remembered per host for a day), warn once
+/// if it is older than `minimum` (when set), and leave out what it does not
+/// support.
+/// When tmux is not on the non-interactive PATH, the first of `fallbacks`
+/// that exists is used instead (unless --tmux named the binary). Connection
+/// problems and a missing tmux are left for the actual command to report.
+pub fn handshake(config: &mut Config, minimum: &str, fallbacks: &[String]) -> Result<()> {
+    let fallbacks = if config.tmux_bin_provided { &[] } else { fallbacks };
+    let minimum = match minimum.trim() {
+        "" => None,
+        text => Some(
+            TmuxVersion::parse(text)
+                .ok_or_else(|| anyhow!("invalid min_tmux_version '{}' in the config file", text))?,
+        ),
+    };
+
+    let key = format!("{} {}", config.host_label(), config.tmux_bin);
+    let probe = match cache::tmux_version(&key) {
+        Some(probe) => probe,
+        None => {
+            let output = ssh::exec_remote_capture(config, &probe_command(config, fallbacks))?;
+            if !output.success() {
+                config.debug_print(&format!("tmux version check failed: {}", output.error_text()));
+                return Ok(());
+            }
+            let probe = parse_probe(&output.stdout);
+            if let Err(e) = cache::store_tmux_version(&key, &probe) {
+                config.debug_print(&format!("could not cache tmux version: {:#}", e));
+            }
+            probe
+        }
+    };
+    if let Some(path) = &probe.path {
+        config.debug_print(&format!("{} is not on the remote PATH; using {}", config.tmux_bin, path));
+        config.tmux_bin = util::shell_escape(path);
+    }
+    let Some(version) = TmuxVersion::parse(&probe.version) else {
+        config.debug_print(&format!("unrecognized tmux version '{}'", probe.version));
+        return Ok(());
+    };
+    config.tmux_version = Some(version);
+
+    if let Some(minimum) = minimum.filter(|&minimum| version < minimum) {
+        ui::warn(&format!(
+            "{} runs tmux {}, older than the minimum {} (min_tmux_version); some features are \
+             turned off and others may fail. Please upgrade tmux there.",
+            ui::host(config.host_label()),
+            version,
+            minimum
+        ));
+    }
+    if version < TmuxVersion::TERMINAL_FEATURES {
+        config.session_setup.retain(|cmd| !cmd.iter().any(|arg| arg == "terminal-features"));
+    }
+    if !config.tmux_supports_formats() && config.single_shot {
+        config.debug_print("tmux lacks -F; not using the remote picker");
+        config.single_shot = false;
+    }
+    Ok(())
+}
+
 /// Build a tmux new-session command
 pub fn build_session_command(config: &Config, session_name: &str) -> Vec<String> {
-    let mut tmux_cmd: Vec<String> = vec![
-        config.tmux_bin.clone(),
-        "new-session".into(),
-        "-A".into(),
-        "-s".into(),
-        session_name.to_string(),
-    ];
-    
+    let mut tmux_cmd: Vec<String> = vec![config.tty_tmux_cmd()];
+    if config.utf8 {
+        tmux_cmd.push("-u".into());
+    }
+    tmux_cmd.push("new-session".into());
+    if config.tmux_supports_formats() {
+        tmux_cmd.push("-A".into());
+    }
+    tmux_cmd.extend(["-s".into(), session_name.to_string()]);
+
+    // Order: the deprecated --tmuxargs words, then each --tmux-arg in the
+    // order given, then vigil's own chained setup commands
     if !config.tmux_args.trim().is_empty() {
         if let Ok(mut extra) = shell_words::split(&config.tmux_args) {
             tmux_cmd.append(&mut extra);
         }
     }
-    
+    tmux_cmd.extend(config.tmux_extra_args.iter().map(|arg| util::shell_escape(arg)));
+    let window_command = match (&config.slurm, &config.nix) {
+        (Some(allocation), _) => Some(allocation.shell_command()),
+        (None, Some(shell)) => Some(shell.shell_command()),
+        (None, None) => None,
+    };
+    // Loaded modules and an activated environment reach srun and nix through the environment
+    let mut prepare = Vec::new();
+    if !config.modules.is_empty() {
+        prepare.push(module_load_command(&config.modules));
+    }
+    if let Some(env) = &config.activate {
+        prepare.push(activate_command(env));
+    }
+    match (window_command, prepare.is_empty()) {
+        (Some(command), true) => tmux_cmd.push(util::shell_escape(&command)),
+        (command, false) => {
+            prepare.push(command.unwrap_or_else(|| LOGIN_SHELL.into()));
+            tmux_cmd.push(util::shell_escape(&prepare.join("; ")));
+        }
+        (None, true) => {}
+    }
+
+    // Chain setup commands after new-session; the escaped ";" reaches tmux as
+    // a bare command separator once the remote shell has parsed it.
+    for cmd in &config.session_setup {
+        tmux_cmd.push("\\;".into());
+        tmux_cmd.extend(cmd.iter().map(|arg| util::shell_escape(arg)));
+    }
+    // Later windows and panes of the session activate the environment too
+    if let Some(env) = &config.activate {
+        tmux_cmd.push("\\;".into());
+        let command = format!("{}; {}", activate_command(env), LOGIN_SHELL);
+        tmux_cmd.extend(["set-option".into(), "default-command".into(), util::shell_escape(&command)]);
+    }
+    if config.force_size {
+        for cmd in force_size_setup(config.tmux_version) {
+            tmux_cmd.push("\\;".into());
+            tmux_cmd.extend(cmd.iter().map(|arg| util::shell_escape(arg)));
+        }
+    }
+
     tmux_cmd
 }
 
-/// Build the full SSH command with embedded tmux session creation
-pub fn build_attach_command(config: &Config, session_name: &str) -> Vec<String> {
-    let tmux_cmd = build_session_command(config, session_name);
+/// The user's shell as tmux starts it by default
+const LOGIN_SHELL: &str = "exec \"${SHELL:-/bin/sh}\" -l";
+
+/// Where conda usually lives when it is not on the non-interactive PATH
+const CONDA_PATHS: [&str; 6] = [
+    "conda",
+    "\"$HOME\"/miniconda3/bin/conda",
+    "\"$HOME\"/anaconda3/bin/conda",
+    "\"$HOME\"/miniforge3/bin/conda",
+    "\"$HOME\"/mambaforge/bin/conda",
+    "/opt/conda/bin/conda",
+];
+
+/// Shell commands activating `env`: a virtualenv when it is a directory with
+/// bin/activate, otherwise a conda environment by name or prefix. The shell
+/// started afterwards inherits it; conda is kept from stacking base on top.
+/// A failure stays on screen until Enter, then the shell starts anyway.
+fn activate_command(env: &str) -> String {
+    let venv = remote_path(env);
+    format!(
+        "if [ -r {venv}/bin/activate ]; then . {venv}/bin/activate; \
+         else for c in {conda}; do command -v \"$c\" >/dev/null 2>&1 && break; done; \
+         eval \"$(\"$c\" shell.posix hook 2>/dev/null)\" && conda activate {name} && export CONDA_AUTO_ACTIVATE_BASE=false; fi \
+         || {{ echo {failed}; read -r _; }}",
+        venv = venv,
+        conda = CONDA_PATHS.join(" "),
+        name = util::shell_escape(env),
+        failed = util::shell_escape(&format!("[vigil] could not activate {}; press Enter to continue.", env))
+    )
+}
+
+/// Where Lmod and Environment Modules define the `module` function, which
+/// tmux's non-login shell has not run
+const MODULE_INIT: [&str; 4] = [
+    "/etc/profile.d/lmod.sh",
+    "/etc/profile.d/modules.sh",
+    "/usr/share/lmod/lmod/init/sh",
+    "/usr/share/Modules/init/sh",
+];
+
+/// Shell commands loading `modules` before the window's shell starts; a
+/// failure stays on screen until Enter, then the shell starts anyway
+fn module_load_command(modules: &[String]) -> String {
+    let names: Vec<String> = modules.iter().map(|m| util::shell_escape(m)).collect();
+    format!(
+        "for f in {}; do command -v module >/dev/null 2>&1 && break; [ -r \"$f\" ] && . \"$f\"; done; \
+         module load {} || {{ echo '[vigil] module load failed; press Enter to continue.'; read -r _; }}",
+        MODULE_INIT.join(" "),
+        names.join(" ")
+    )
+}
+
+/// tmux commands sizing this session's windows to the client that attached
+/// or resized last, instead of the smallest one (an idle 80x24 client left
+/// attached elsewhere). Only this session is touched: the current window is
+/// set now, and session hooks set each window that is created or switched
+/// to. Before 3.1 the closest is aggressive-resize, which only counts
+/// clients looking at the window.
+fn force_size_setup(version: Option<TmuxVersion>) -> Vec<Vec<String>> {
+    let option = match version {
+        Some(version) if version < TmuxVersion::WINDOW_SIZE_LATEST => "set-option -w aggressive-resize on",
+        _ => "set-option -w window-size latest",
+    };
+    let mut commands = vec![option.split(' ').map(String::from).collect::<Vec<_>>()];
+    for hook in ["after-new-window", "session-window-changed"] {
+        commands.push(vec!["set-hook".into(), hook.into(), option.into()]);
+    }
+    commands
+}
+
+/// tmux command redrawing the status line once a minute instead of every
+/// few seconds, to save bandwidth on slow links
+pub fn slow_link_setup() -> Vec<String> {
+    vec!["set-option".into(), "status-interval".into(), "60".into()]
+}
+
+/// tmux commands enabling 24-bit color for the session's clients and panes
+pub fn truecolor_setup() -> Vec<Vec<String>> {
+    vec![
+        vec!["set-option".into(), "-as".into(), "terminal-overrides".into(), ",*:Tc".into()],
+        vec!["set-option".into(), "-g".into(), "default-terminal".into(), "screen-256color".into()],
+    ]
+}
+
+/// tmux commands enabling OSC 52 clipboard passthrough to the local terminal
+pub fn clipboard_setup() -> Vec<Vec<String>> {
+    vec![
+        vec!["set-option".into(), "-s".into(), "set-clipboard".into(), "on".into()],
+        vec!["set-option".into(), "-as".into(), "terminal-features".into(), ",*:clipboard".into()],
+    ]
+}
+
+/// Shell snippet that warns (before tmux takes over the screen) when a new
+/// session would be killed at logout because logind has KillUserProcesses=yes
+/// and lingering is disabled for the remote user.
+fn linger_probe(config: &Config, session_name: &str) -> String {
+    format!(
+        "if ! {tmux} has-session -t {session} 2>/dev/null && command -v loginctl >/dev/null 2>&1 \
+           && [ \"$(loginctl show-user \"$(id -un)\" -p Linger 2>/dev/null)\" != Linger=yes ] \
+           && busctl get-property org.freedesktop.login1 /org/freedesktop/login1 \
+              org.freedesktop.login1.Manager KillUserProcesses 2>/dev/null | grep -q true; then \
+         echo '[vigil] WARNING: systemd KillUserProcesses=yes and lingering is disabled; \
+this session will be killed at logout. Run `loginctl enable-linger` or use vigil --linger.' >&2; \
+         sleep 3; \
+         fi;",
+        tmux = config.tty_tmux_cmd(),
+        session = util::shell_escape(session_name),
+    )
+}
+
+/// tmux command locking the session after `mins` minutes without input
+pub fn auto_lock_setup(mins: u32) -> Vec<String> {
+    vec!["set-option".into(), "lock-after-time".into(), (mins * 60).to_string()]
+}
+
+/// ssh arguments for an interactive session, with TTY allocation ensured
+fn tty_ssh_args(config: &Config) -> Vec<String> {
     let mut ssh_args = config.ssh_args.clone();
-    
+
     // Ensure TTY allocation
     if !ssh_args.iter().any(|a| a == "-t" || a == "-tt") {
-        ssh_args.insert(0, "-t".into());
+        ssh_args.insert(0, config.tty_flag().into());
+    }
+    ssh_args
+}
+
+/// tmux new-session argv, wrapped in a systemd user scope with --linger
+fn session_argv(config: &Config, session_name: &str) -> Vec<String> {
+    let mut tmux_cmd = build_session_command(config, session_name);
+    if config.linger {
+        // A user scope outlives the login session once lingering is enabled
+        let wrapper = ["systemd-run", "--user", "--scope", "--quiet"];
+        tmux_cmd.splice(0..0, wrapper.iter().map(|s| s.to_string()));
     }
-    
+    tmux_cmd
+}
+
+/// Build the full SSH command with embedded tmux session creation
+pub fn build_attach_command(config: &Config, session_name: &str) -> Vec<String> {
+    let tmux_cmd = session_argv(config, session_name);
+    let mut ssh_args = tty_ssh_args(config);
+
     config.debug_print(&format!("ssh args (pre-tmux): {:?}", ssh_args));
     config.debug_print(&format!("tmux argv: {:?}", tmux_cmd));
-    
-    ssh_args.extend(tmux_cmd);
+
+    // Without new-session -A, attach to an existing session explicitly
+    let attach_existing = if config.tmux_supports_formats() {
+        String::new()
+    } else {
+        format!(
+            "{tmux} has-session -t {session} 2>/dev/null && exec {tmux} attach-session -t {session}; ",
+            tmux = config.tty_tmux_cmd(),
+            session = util::shell_escape(session_name)
+        )
+    };
+
+    if config.linger {
+        ssh_args.push(config.remote_command(&format!(
+            "loginctl enable-linger 2>/dev/null; {}exec {}",
+            attach_existing,
+            tmux_cmd.join(" ")
+        )));
+    } else if config.linger_check {
+        ssh_args.push(config.remote_command(&format!(
+            "{} {}exec {}",
+            linger_probe(config, session_name),
+            attach_existing,
+            tmux_cmd.join(" ")
+        )));
+    } else if !attach_existing.is_empty() || config.login_shell.is_some() {
+        ssh_args.push(config.remote_command(&format!("{}exec {}", attach_existing, tmux_cmd.join(" "))));
+    } else {
+        ssh_args.extend(tmux_cmd);
+    }
+    ssh_args
+}
+
+/// Build an SSH command attaching read-only to an existing session. Where
+/// tmux supports it the observer is also ignored when sizing windows, so a
+/// small pane does not shrink the session for everyone else.
+pub fn build_observe_command(config: &Config, session_name: &str) -> Vec<String> {
+    let mut ssh_args = tty_ssh_args(config);
+    let flags = match config.tmux_version {
+        Some(version) if version >= TmuxVersion::CLIENT_FLAGS => "-f read-only,ignore-size",
+        _ => "-r",
+    };
+    ssh_args.push(config.remote_command(&format!(
+        "exec {} attach-session {} -t {}",
+        config.tty_tmux_cmd(),
+        flags,
+        util::shell_escape(&format!("={}", session_name))
+    )));
+    ssh_args
+}
+
+/// Split the local tmux window and show `session_name` read-only in the new
+/// pane below, leaving the current pane focused
+pub fn observe_in_local_pane(config: &Config, session_name: &str) -> Result<()> {
+    if std::env::var_os("TMUX").is_none() {
+        return Err(anyhow!("--observe opens a pane in the local tmux; run vigil inside tmux"));
+    }
+    let mut argv = config.ssh_argv();
+    argv.extend(build_observe_command(config, session_name));
+    config.debug_print(&format!("observer pane: {:?}", argv));
+    let status = std::process::Command::new("tmux")
+        .args(["split-window", "-d", "-v", "-p", "30"])
+        .arg(shell_words::join(&argv))
+        .status()
+        .map_err(|e| anyhow!("failed to run the local tmux: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!("local tmux split-window failed ({})", status));
+    }
+    Ok(())
+}
+
+/// Build an SSH command that lists sessions, prompts for a choice on the
+/// remote TTY, and attaches, all in one connection (--single-shot). With no
+/// sessions, `default_name` is created.
+pub fn build_picker_command(config: &Config, default_name: &str) -> Vec<String> {
+    // The session name is expanded by the remote shell from $name
+    let tmux_cmd = session_argv(config, "\"$name\"");
+    let mut ssh_args = tty_ssh_args(config);
+
+    let script = format!(
+        "sessions=$({tmux} list-sessions -F '#{{session_name}}' 2>/dev/null); \
+         if [ -z \"$sessions\" ]; then \
+           name={default}; \
+           echo \"[vigil] No tmux sessions found remotely; will create/attach to '$name'.\" >&2; \
+         else \
+           echo '[vigil] Select a session to attach:' >&2; \
+           echo \"$sessions\" | awk '{{ printf \"  %d. %s\\n\", NR, $0 }}' >&2; \
+           printf 'Enter number (or press Enter for 1): ' >&2; \
+           read -r n; n=${{n:-1}}; \
+           case $n in *[!0-9]*|0) n=; esac; \
+           name=${{n:+$(echo \"$sessions\" | sed -n \"${{n}}p\")}}; \
+           [ -n \"$name\" ] || {{ echo '[vigil] ERROR: invalid selection' >&2; exit 1; }}; \
+         fi; \
+         {loginctl}exec {tmux_cmd}",
+        tmux = config.tty_tmux_cmd(),
+        default = util::shell_escape(default_name),
+        loginctl = if config.linger { "loginctl enable-linger 2>/dev/null; " } else { "" },
+        tmux_cmd = tmux_cmd.join(" "),
+    );
+    config.debug_print(&format!("picker script: {}", script));
+    ssh_args.push(config.remote_command(&script));
     ssh_args
 }
 
 /// List all remote tmux sessions
 pub fn list_remote_sessions(config: &Config) -> Result<Vec<String>> {
-    let list_cmd = format!(
-        "{} list-sessions -F {}",
-        config.tmux_bin,
-        util::shell_escape("#{session_name}")
-    );
-
-    match ssh::exec_remote_capture(config, &list_cmd) {
-        Ok(output) => {
-            let sessions: Vec<String> = output
-                .lines()
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-                .map(|s| s.to_string())
-                .collect();
-            Ok(sessions)
-        }
-        Err(e) => {
-            // Check if it's a "command not found" (127) error
-            let stderr = format!("{}", e);
-            if stderr.contains("127") || stderr.contains("not found") {
-                eprintln!("[vigil] {}", util::tmux_install_hint());
-                Err(anyhow!("remote tmux not found"))
-            } else {
-                // Non-zero from tmux when no server exists is fine; treat as no sessions
-                Ok(Vec::new())
+    timings::phase("list sessions", || list_remote_sessions_inner(config, false)).map(|(sessions, _)| sessions)
+}
+
+/// List all remote tmux sessions along with a summary of the host's uptime,
+/// load, and disk, gathered over the same connection
+pub fn list_remote_sessions_with_health(config: &Config) -> Result<(Vec<String>, Option<HostHealth>)> {
+    timings::phase("list sessions", || list_remote_sessions_inner(config, true))
+}
+
+fn list_remote_sessions_inner(config: &Config, with_health: bool) -> Result<(Vec<String>, Option<HostHealth>)> {
+    // Before -F existed, lines read "name: 2 windows (created ...)"
+    let formats = config.tmux_supports_formats();
+    let mut list_cmd = if with_health { health::command() } else { String::new() };
+    if formats {
+        list_cmd += &format!("{} list-sessions -F {}", config.tmux_cmd(), util::shell_escape("#{session_name}"));
+    } else {
+        list_cmd += &format!("{} list-sessions", config.tmux_cmd());
+    }
+    // Without tmux, say which system this is so the hint names its install command
+    list_cmd = format!("{}; s=$?; if [ $s -eq 127 ]; then echo {}; {}; fi; exit $s", list_cmd, OS_MARK, util::OS_PROBE);
+
+    let output = ssh::exec_remote_capture(config, &list_cmd)?;
+    let (stdout, health) = health::split(&output.stdout);
+    if output.success() {
+        let sessions = stdout
+            .lines()
+            .map(|s| if formats { s } else { s.split(':').next().unwrap_or("") })
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        return Ok((sessions, health));
+    }
+
+    // With --password-auth the pty merges tmux's stderr into stdout
+    let messages = format!("{}{}", output.stderr, stdout);
+    if is_no_server(&messages) {
+        return Ok((Vec::new(), health));
+    }
+    if config.sudo && messages.contains("a password is required") {
+        return Err(anyhow!(
+            "sudo on {} asks for a password, which cannot be typed while listing; name the session \
+             to attach to, or allow `sudo {}` without a password (NOPASSWD in sudoers)",
+            config.host_label(),
+            config.tmux_bin
+        ));
+    }
+    if output.status.code() == Some(127) || messages.contains("command not found") {
+        ui::error(&util::tmux_install_hint(os_probe(&stdout)));
+        return Err(anyhow!("remote tmux not found"));
+    }
+    let detail = messages.trim();
+    Err(anyhow!(
+        "listing sessions on {} failed ({}){}{}",
+        config.host_label(),
+        output.status,
+        if detail.is_empty() { "" } else { ": " },
+        detail
+    ))
+}
+
+/// Precedes the output of [`util::OS_PROBE`] when listing finds no tmux
+const OS_MARK: &str = "@@vigil-os";
+
+/// The [`util::OS_PROBE`] output after the marker line, starting with `uname -s`
+fn os_probe(stdout: &str) -> &str {
+    stdout.split_once(OS_MARK).map_or("", |(_, probe)| probe.trim_start_matches('\n'))
+}
+
+/// Whether tmux output says no server is running, which just means there
+/// are no sessions yet ("no server running on ..." or, with older tmux, an
+/// "error connecting to" a socket that does not exist).
+fn is_no_server(messages: &str) -> bool {
+    messages.contains("no server running")
+        || (messages.contains("error connecting to") && messages.contains("No such file or directory"))
+}
+
+/// Details of a remote tmux session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub name: String,
+    /// Number of clients attached to the session
+    pub attached: u32,
+    /// Last activity as a Unix timestamp (remote clock)
+    pub activity: u64,
+    pub windows: u32,
+}
+
+/// A client attached to a remote tmux session
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub session: String,
+    pub tty: String,
+    /// When the client attached, as a Unix timestamp (remote clock)
+    pub created: u64,
+}
+
+/// List remote sessions together with their attached clients, in one round trip
+pub fn session_details(config: &Config) -> Result<(Vec<SessionInfo>, Vec<ClientInfo>)> {
+    let cmd = format!(
+        "{tmux} list-sessions -F {sfmt} 2>/dev/null; {tmux} list-clients -F {cfmt} 2>/dev/null",
+        tmux = config.tmux_cmd(),
+        sfmt = util::shell_escape("S\t#{session_name}\t#{session_attached}\t#{session_activity}\t#{session_windows}"),
+        cfmt = util::shell_escape("C\t#{client_session}\t#{client_tty}\t#{client_created}"),
+    );
+    // tmux failing (no server) just means no sessions; ssh failing is an error
+    let output = ssh::exec_remote_capture(config, &cmd)?;
+    if output.ssh_failed() {
+        return Err(anyhow!("{}", output.error_text()));
+    }
+
+    let mut sessions = Vec::new();
+    let mut clients = Vec::new();
+    for line in output.stdout.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields.as_slice() {
+            ["S", name, attached, activity, windows] => sessions.push(SessionInfo {
+                name: name.to_string(),
+                attached: attached.parse().unwrap_or(0),
+                activity: activity.parse().unwrap_or(0),
+                windows: windows.parse().unwrap_or(0),
+            }),
+            ["C", session, tty, created] => clients.push(ClientInfo {
+                session: session.to_string(),
+                tty: tty.to_string(),
+                created: created.parse().unwrap_or(0),
+            }),
+            _ => {}
+        }
+    }
+    Ok((sessions, clients))
+}
+
+/// Where a pane of a remote session is, for `vigil pwd`
+#[derive(Debug, Clone)]
+pub struct PanePath {
+    /// "window.pane", e.g. "1.0"
+    pub pane: String,
+    pub window_name: String,
+    pub command: String,
+    /// Current directory, with the remote home directory shown as ~
+    pub path: String,
+}
+
+/// Current directory of each pane of `session`
+pub fn pane_paths(config: &Config, session: &str) -> Result<Vec<PanePath>> {
+    let cmd = format!(
+        "printf 'H\\t%s\\n' \"$HOME\"; {} list-panes -s -t {} -F {}",
+        config.tmux_cmd(),
+        // With the colon, = matches the session name exactly rather than as a prefix
+        util::shell_escape(&format!("={}:", session)),
+        util::shell_escape("P\t#{window_index}.#{pane_index}\t#{window_name}\t#{pane_current_command}\t#{pane_current_path}")
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    let mut home = None;
+    let mut panes = Vec::new();
+    for line in output.lines() {
+        match line.split('\t').collect::<Vec<_>>().as_slice() {
+            ["H", dir] => home = Some(dir.to_string()).filter(|dir| !dir.is_empty() && dir != "/"),
+            ["P", pane, window_name, command, path] => {
+                let path = match home.as_deref().and_then(|home| path.strip_prefix(home)) {
+                    Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("~{}", rest),
+                    _ => path.to_string(),
+                };
+                panes.push(PanePath {
+                    pane: pane.to_string(),
+                    window_name: window_name.to_string(),
+                    command: command.to_string(),
+                    path,
+                });
             }
+            _ => {}
+        }
+    }
+    Ok(panes)
+}
+
+/// A scrollback line matching `vigil grep`'s pattern
+#[derive(Debug, Clone)]
+pub struct ScrollbackMatch {
+    pub session: String,
+    /// "window.pane", e.g. "1.0"
+    pub pane: String,
+    pub line: String,
+}
+
+/// Lines matching `pattern` (grep -E) in the last `lines` lines of every
+/// pane of every session, at most `max` per pane, most recent last. One
+/// round trip: the panes are captured and filtered on the remote host.
+pub fn search_scrollback(config: &Config, pattern: &str, lines: usize, ignore_case: bool, max: usize) -> Result<Vec<ScrollbackMatch>> {
+    const MARK: &str = "@@vigil-pane ";
+    let tmux = config.tmux_cmd();
+    let cmd = format!(
+        "{tmux} list-panes -a -F '#{{pane_id}} #{{window_index}}.#{{pane_index}} #{{session_name}}' | while read -r id pane s; do \
+             m=$({tmux} capture-pane -p -J -S -{lines} -t \"$id\" | grep -E{i} -e {pattern} | tail -n {max}); \
+             [ -n \"$m\" ] && printf '{mark}%s %s\\n%s\\n' \"$pane\" \"$s\" \"$m\"; \
+         done; true",
+        tmux = tmux,
+        lines = lines,
+        i = if ignore_case { "i" } else { "" },
+        pattern = util::shell_escape(pattern),
+        max = max,
+        mark = MARK
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    let mut matches = Vec::new();
+    let mut current: Option<(String, String)> = None;
+    for line in output.lines() {
+        if let Some((pane, session)) = line.strip_prefix(MARK).and_then(|rest| rest.split_once(' ')) {
+            current = Some((session.to_string(), pane.to_string()));
+        } else if let Some((session, pane)) = &current {
+            matches.push(ScrollbackMatch { session: session.clone(), pane: pane.clone(), line: line.trim_end().to_string() });
+        }
+    }
+    Ok(matches)
+}
+
+/// The whole scrollback of one pane, from `vigil export`
+#[derive(Debug, Clone)]
+pub struct PaneCapture {
+    pub session: String,
+    /// "window.pane", e.g. "1.0"
+    pub pane: String,
+    pub text: String,
+}
+
+/// Scrollback and visible contents of every pane of every session, in one
+/// round trip, with attributes as escape sequences when `escapes` is set
+pub fn capture_all(config: &Config, escapes: bool) -> Result<Vec<PaneCapture>> {
+    // Unique per run so no captured line can pass for a marker
+    let mark = format!("@@vigil-export-{}-{} ", std::process::id(), util::unix_now());
+    let tmux = config.tmux_cmd();
+    let cmd = format!(
+        "{tmux} list-panes -a -F '#{{pane_id}} #{{window_index}}.#{{pane_index}} #{{session_name}}' | while read -r id pane s; do \
+             printf '{mark}%s %s\\n' \"$pane\" \"$s\"; {tmux} capture-pane -p -J{e} -S - -t \"$id\"; \
+         done",
+        tmux = tmux,
+        mark = mark,
+        e = if escapes { " -e" } else { "" }
+    );
+    // Without a server the loop reads nothing and still succeeds
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    let mut panes: Vec<PaneCapture> = Vec::new();
+    for line in output.lines() {
+        if let Some((pane, session)) = line.strip_prefix(&mark).and_then(|rest| rest.split_once(' ')) {
+            panes.push(PaneCapture { session: session.to_string(), pane: pane.to_string(), text: String::new() });
+        } else if let Some(capture) = panes.last_mut() {
+            capture.text.push_str(line);
+            capture.text.push('\n');
         }
     }
+    // The empty rows below the cursor are not part of the output
+    for capture in &mut panes {
+        let end = capture.text.trim_end_matches('\n').len();
+        capture.text.truncate(end);
+        if end > 0 {
+            capture.text.push('\n');
+        }
+    }
+    Ok(panes)
 }
 
 /// Kill a remote tmux session
 pub fn kill_remote_session(config: &Config, target: &str) -> Result<()> {
-    let kill_cmd = format!(
-        "{} kill-session -t {}",
-        config.tmux_bin,
-        util::shell_escape(target)
+    ssh::exec_remote_command(config, &kill_session_command(config, target))
+}
+
+/// tmux command killing exactly the session named `target`; a bare name
+/// would also match a longer name starting with it
+fn kill_session_command(config: &Config, target: &str) -> String {
+    format!("{} kill-session -t {}", config.tty_tmux_cmd(), util::shell_escape(&format!("={}", target)))
+}
+
+/// Keys that make the remote tmux client detach: its prefix (or prefix2
+/// when prefix is None), then `d`. Falls back to tmux's default C-b when the
+/// prefix cannot be read, e.g. because no server is running yet.
+pub fn detach_keys(config: &Config) -> Vec<u8> {
+    let query = format!(
+        "{t} show-options -gqv prefix; {t} show-options -gqv prefix2",
+        t = config.tmux_cmd()
     );
+    let prefix = ssh::exec_remote_capture(config, &query)
+        .and_then(|output| output.into_stdout())
+        .ok()
+        .and_then(|stdout| stdout.lines().find_map(|line| key_bytes(line.trim())))
+        .unwrap_or_else(|| vec![0x02]);
+    if config.debug {
+        ui::status(&format!("Detach key sends tmux prefix {:?} + d", prefix));
+    }
+    [prefix, b"d".to_vec()].concat()
+}
+
+/// Bytes a terminal sends for a tmux key name such as "C-a", "M-x" or "`"
+fn key_bytes(name: &str) -> Option<Vec<u8>> {
+    if let Some(rest) = name.strip_prefix("M-") {
+        return key_bytes(rest).map(|key| [vec![0x1b], key].concat());
+    }
+    if let Some(rest) = name.strip_prefix("C-").or_else(|| name.strip_prefix('^')) {
+        return match rest {
+            "Space" | "@" => Some(vec![0]),
+            "?" => Some(vec![0x7f]),
+            _ => match rest.as_bytes() {
+                [c @ (b'@'..=b'_' | b'a'..=b'z')] => Some(vec![c.to_ascii_uppercase() & 0x1f]),
+                _ => None,
+            },
+        };
+    }
+    match name {
+        "Escape" => Some(vec![0x1b]),
+        "Space" => Some(vec![b' ']),
+        "None" | "" => None,
+        _ if name.chars().count() == 1 => Some(name.as_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Render the terminal title template for a session
+pub fn render_title(config: &Config, session_name: &str) -> String {
+    config
+        .title_template
+        .replace("{session}", session_name)
+        .replace("{host}", config.host_label())
+        .replace("{user}", &config.local_user)
+}
+
+/// Kill the remote tmux server, taking every session with it
+pub fn kill_remote_server(config: &Config) -> Result<()> {
+    ssh::exec_remote_command(config, &format!("{} kill-server", config.tty_tmux_cmd()))
+}
+
+/// Run one tmux operation per session name in a single compound remote
+/// command; returns whether it succeeded for each name, in input order.
+fn run_batch(config: &Config, names: &[String], op: &str) -> Result<Vec<(String, bool)>> {
+    let script: Vec<String> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            // "=" makes tmux match the name exactly instead of by prefix
+            format!(
+                "{} {} -t {} 2>/dev/null && echo ok:{} || echo fail:{}",
+                config.tmux_cmd(),
+                op,
+                util::shell_escape(&format!("={}", name)),
+                i,
+                i
+            )
+        })
+        .collect();
+    let output = ssh::exec_remote_capture(config, &script.join("; "))?.into_stdout()?;
+
+    let mut results: Vec<(String, bool)> = names.iter().map(|n| (n.clone(), false)).collect();
+    for line in output.lines() {
+        if let Some(i) = line.trim().strip_prefix("ok:").and_then(|i| i.parse::<usize>().ok()) {
+            if let Some(entry) = results.get_mut(i) {
+                entry.1 = true;
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// Kill many sessions over one SSH connection
+pub fn kill_sessions_batch(config: &Config, names: &[String]) -> Result<Vec<(String, bool)>> {
+    run_batch(config, names, "kill-session")
+}
+
+/// Check for many sessions over one SSH connection
+pub fn sessions_exist(config: &Config, names: &[String]) -> Result<Vec<(String, bool)>> {
+    run_batch(config, names, "has-session")
+}
+
+/// Whether a tmux command line only reads state, so it stays allowed in
+/// read-only mode: every command chained with `;` must be one that does
+pub fn is_read_only_command(args: &[String]) -> bool {
+    command_names(args).into_iter().all(|command| {
+        command.starts_with("list-")
+            || command.starts_with("show-")
+            || matches!(
+                command,
+                "ls" | "lsw" | "lsp" | "lsc" | "lsb" | "lsk" | "lscm" | "show" | "showw" | "showb" | "showmsgs"
+                    | "has-session" | "has" | "capture-pane" | "capturep" | "info" | "server-info"
+                    | "start-server" | "start"
+            )
+    })
+}
+
+/// tmux's own options that take a value (`tmux -L NAME ...`)
+const TMUX_OPTS_WITH_VALUE: &str = "cfLST";
+
+/// Names of the commands in a tmux command line, split where tmux splits
+/// it: at a `;` argument or one ending in an unescaped `;`. tmux's own
+/// options before the first command (e.g. `-u`, `-L NAME`) are skipped.
+pub fn command_names(args: &[String]) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut args = args.iter().peekable();
+    while let Some(flags) = args.peek().filter(|arg| names.is_empty() && arg.len() > 1).and_then(|arg| arg.strip_prefix('-')) {
+        let takes_value = flags.find(|c| TMUX_OPTS_WITH_VALUE.contains(c)) == Some(flags.len() - 1);
+        args.next();
+        if takes_value {
+            args.next();
+        }
+        if flags == "-" {
+            break;
+        }
+    }
+    let mut first = true;
+    for arg in args {
+        if first && arg != ";" {
+            names.push(arg.trim_end_matches(';'));
+            first = false;
+        }
+        if arg.ends_with(';') && !arg.ends_with("\\;") {
+            first = true;
+        }
+    }
+    names
+}
+
+/// Session names in the -t targets of a tmux command line; None when a
+/// target names no session (e.g. a pane id), there is no -t at all, or a
+/// command acts on all the others (-a, as in `kill-session -a -t keep`)
+pub fn target_sessions(args: &[String]) -> Option<Vec<String>> {
+    let mut sessions = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = match arg.strip_prefix("-t") {
+            Some("") => args.next()?,
+            Some(target) => target,
+            None if arg.starts_with('-') && !arg.starts_with("--") && arg.contains('a') => return None,
+            None => continue,
+        };
+        let session = target.trim_start_matches('=').split(':').next().unwrap_or("");
+        if session.is_empty() || session.starts_with(['$', '@', '%']) {
+            return None;
+        }
+        sessions.push(session.to_string());
+    }
+    (!sessions.is_empty()).then_some(sessions)
+}
+
+/// Run an arbitrary tmux command remotely with stdio passed through. Each
+/// argument is quoted so the remote shell hands it to tmux unchanged.
+pub fn passthrough(config: &Config, args: &[String]) -> Result<std::process::ExitStatus> {
+    let mut cmd = vec![config.tmux_cmd()];
+    cmd.extend(args.iter().map(|a| util::shell_escape(a)));
+    ssh::exec_remote_passthrough(config, &cmd, false)
+}
+
+/// Print the windows of a remote session
+pub fn list_windows(config: &Config, session: &str) -> Result<()> {
+    let cmd = format!(
+        "{} list-windows -t {} -F '#{{window_index}}: #{{window_name}} (#{{window_panes}} panes)#{{?window_active, *,}}'",
+        config.tty_tmux_cmd(),
+        util::shell_escape(&format!("={}", session))
+    );
+    ssh::exec_remote_command(config, &cmd)
+}
+
+/// Add a window to an existing remote session without switching to it
+pub fn new_window(config: &Config, session: &str, name: Option<&str>, cwd: Option<&str>) -> Result<()> {
+    let mut cmd = format!(
+        "{} new-window -d -t {}",
+        config.tty_tmux_cmd(),
+        util::shell_escape(&format!("{}:", session))
+    );
+    if let Some(name) = name {
+        cmd.push_str(&format!(" -n {}", util::shell_escape(name)));
+    }
+    if let Some(cwd) = cwd {
+        cmd.push_str(&format!(" -c {}", util::shell_escape(cwd)));
+    }
+    if let Some(env) = &config.activate {
+        cmd.push_str(&format!(" {}", util::shell_escape(&format!("{}; {}", activate_command(env), LOGIN_SHELL))));
+    }
+    ssh::exec_remote_command(config, &cmd)
+}
+
+/// Kill a single window of a remote session
+pub fn kill_window(config: &Config, session: &str, window: &str) -> Result<()> {
+    let cmd = format!(
+        "{} kill-window -t {}",
+        config.tty_tmux_cmd(),
+        util::shell_escape(&format!("={}:{}", session, window))
+    );
+    ssh::exec_remote_command(config, &cmd)
+}
+
+/// `list-panes -s` format of a session snapshot, one line per pane; read
+/// back by [`parse_panes`] for cloning and for restoring autosaves
+pub const PANE_FORMAT: &str =
+    "#{window_index}\t#{window_name}\t#{window_layout}\t#{window_width}\t#{window_height}\t#{pane_current_path}";
+
+/// Remote directory of the autosaved snapshots, relative to the home directory
+pub const AUTOSAVE_DIR: &str = ".cache/vigil/autosave";
+
+/// One pane of a session as reported by `list-panes -s`
+struct PaneLayout {
+    window: String,
+    name: String,
+    layout: String,
+    width: String,
+    height: String,
+    path: String,
+}
+
+/// Create `dest` as a detached copy of `source`: same windows (names and
+/// order), same number of panes with their working directories, and the
+/// same layouts. Running programs are not copied.
+pub fn clone_session(config: &Config, source: &str, dest: &str) -> Result<()> {
+    let query = format!(
+        "{} list-panes -s -t {} -F {}",
+        config.tmux_cmd(),
+        util::shell_escape(&format!("={}", source)),
+        util::shell_escape(PANE_FORMAT)
+    );
+    let output = ssh::exec_remote_capture(config, &query)?.into_stdout()?;
+    let panes = parse_panes(&output);
+    if panes.is_empty() {
+        return Err(anyhow!("session '{}' not found on the remote host", source));
+    }
+    ssh::exec_remote_command(config, &rebuild_script(config, dest, &panes))
+}
+
+/// Rebuild a missing session from its last autosave (see `vigil autosave`).
+/// Returns whether it was restored: false when the session exists or there
+/// is no snapshot of it.
+pub fn restore_if_missing(config: &Config, session: &str) -> Result<bool> {
+    let cmd = format!(
+        "if {} has-session -t {} 2>/dev/null; then echo present; else cat \"$HOME\"/{}/{} 2>/dev/null || true; fi",
+        config.tmux_cmd(),
+        util::shell_escape(&format!("={}", session)),
+        AUTOSAVE_DIR,
+        util::shell_escape(&autosave_file(session))
+    );
+    // No snapshot is not an error, but a failed connection is
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    if output.trim() == "present" {
+        return Ok(false);
+    }
+    let panes = parse_panes(&output);
+    if panes.is_empty() {
+        ui::status(&format!("No autosave of '{}' found; starting it fresh.", ui::session(session)));
+        return Ok(false);
+    }
+    ssh::exec_remote_command(config, &rebuild_script(config, session, &panes))?;
+    Ok(true)
+}
+
+/// Snapshot file name of a session within [`AUTOSAVE_DIR`]
+pub fn autosave_file(session: &str) -> String {
+    format!("{}.panes", session.replace('/', "_"))
+}
+
+/// Panes of [`PANE_FORMAT`] lines, skipping lines that do not fit
+fn parse_panes(output: &str) -> Vec<PaneLayout> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut f = line.splitn(6, '\t');
+            Some(PaneLayout {
+                window: f.next()?.to_string(),
+                name: f.next()?.to_string(),
+                layout: f.next()?.to_string(),
+                width: f.next()?.to_string(),
+                height: f.next()?.to_string(),
+                path: f.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// tmux commands creating `dest` detached with the windows, panes, and layouts of `panes`
+fn rebuild_script(config: &Config, dest: &str, panes: &[PaneLayout]) -> String {
+    let first = &panes[0];
+    // Each new window becomes current, so "dest:" targets the window being built
+    let target = format!("={}:", dest);
+    let mut cmds: Vec<Vec<String>> = vec![vec![
+        "new-session".into(), "-d".into(), "-s".into(), dest.into(),
+        "-x".into(), first.width.clone(), "-y".into(), first.height.clone(),
+        "-n".into(), first.name.clone(), "-c".into(), first.path.clone(),
+    ]];
+    for (i, pane) in panes.iter().enumerate() {
+        let new_window = i > 0 && pane.window != panes[i - 1].window;
+        if new_window {
+            cmds.push(vec![
+                "new-window".into(), "-t".into(), target.clone(),
+                "-n".into(), pane.name.clone(), "-c".into(), pane.path.clone(),
+            ]);
+        } else if i > 0 {
+            cmds.push(vec!["split-window".into(), "-t".into(), target.clone(), "-c".into(), pane.path.clone()]);
+        }
+        let last_in_window = match panes.get(i + 1) {
+            Some(next) => next.window != pane.window,
+            None => true,
+        };
+        if last_in_window {
+            cmds.push(vec!["select-layout".into(), "-t".into(), target.clone(), pane.layout.clone()]);
+        }
+    }
+    cmds.push(vec!["select-window".into(), "-t".into(), format!("={}:^", dest)]);
+
+    cmds
+        .iter()
+        .map(|cmd| {
+            let args: Vec<String> = cmd.iter().map(|a| util::shell_escape(a)).collect();
+            format!("{} {}", config.tty_tmux_cmd(), args.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
 
-    ssh::exec_remote_command(config, &kill_cmd)
+/// Create `session` detached unless it exists; returns whether it was created
+pub fn ensure_session(config: &Config, session: &str) -> Result<bool> {
+    let tmux = config.tmux_cmd();
+    let cmd = format!(
+        "if {} has-session -t {} 2>/dev/null; then echo exists; else {} new-session -d -s {}; fi",
+        tmux,
+        util::shell_escape(&format!("={}", session)),
+        tmux,
+        util::shell_escape(session)
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    Ok(output.trim() != "exists")
+}
+
+/// When `vigil keep` starts a command again after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RestartPolicy {
+    /// After every exit
+    Always,
+    /// Only after a non-zero exit status
+    OnFailure,
+    /// Never; the session stays open with the last output
+    Never,
+}
+
+/// Shell loop that runs `command` (joined with spaces, as ssh does) and
+/// restarts it according to `policy`. Once it stops restarting, an
+/// interactive shell keeps the output visible.
+fn keep_script(command: &[String], policy: RestartPolicy, delay: u64) -> String {
+    let stop = match policy {
+        RestartPolicy::Always => "",
+        RestartPolicy::OnFailure => "[ \"$s\" -eq 0 ] && break; ",
+        RestartPolicy::Never => "break; ",
+    };
+    format!(
+        "while :; do ( {cmd} ); s=$?; printf '\\n[vigil keep] exited with status %s at %s\\n' \"$s\" \"$(date)\"; \
+         {stop}echo '[vigil keep] restarting in {delay}s'; sleep {delay}; done; \
+         echo '[vigil keep] not restarting'; exec \"${{SHELL:-sh}}\"",
+        cmd = command.join(" "),
+        stop = stop,
+        delay = delay
+    )
+}
+
+/// Start `command` in a detached session that restarts it according to
+/// `policy`. Returns false without touching it when the session already exists.
+pub fn keep_running(
+    config: &Config,
+    session: &str,
+    command: &[String],
+    cwd: Option<&str>,
+    policy: RestartPolicy,
+    delay: u64,
+) -> Result<bool> {
+    let tmux = config.tmux_cmd();
+    let mut new_session = format!("{} new-session -d -s {}", tmux, util::shell_escape(session));
+    if let Some(cwd) = cwd {
+        new_session.push_str(&format!(" -c {}", remote_path(cwd)));
+    }
+    // Through sh explicitly: the user's default-shell may not speak POSIX
+    let shell_command = format!("sh -c {}", util::shell_escape(&keep_script(command, policy, delay)));
+    let cmd = format!(
+        "if {} has-session -t {} 2>/dev/null; then echo running; else {} {}; fi",
+        tmux,
+        util::shell_escape(&format!("={}", session)),
+        new_session,
+        util::shell_escape(&shell_command)
+    );
+    let output = ssh::exec_remote_capture(config, &cmd)?.into_stdout()?;
+    Ok(output.trim() != "running")
+}
+
+/// POSIX shells `vigil exec --in --keys` types commands into, as a case
+/// pattern; fish gets a syntax of its own
+const PANE_SHELLS: &str = "bash|zsh|sh|dash|ksh|mksh|ash";
+
+/// tmux target for `session` or `session:window.pane`, and the session part
+pub fn pane_target(target: &str) -> (String, &str) {
+    match target.split_once(':') {
+        Some((session, _)) => (format!("={}", target), session),
+        None => (format!("={}:", target), target),
+    }
+}
+
+/// Remote script for `vigil exec --in`: run `command` (joined with spaces, as
+/// ssh does) in the working directory of the target pane with the session's
+/// tmux environment (what update-environment and `new-session -e` set),
+/// streaming its output and exiting with its status
+pub fn exec_in_script(config: &Config, target: &str, command: &[String]) -> String {
+    let (pane, session) = pane_target(target);
+    let tmux = config.tmux_cmd();
+    format!(
+        "dir=$({tmux} display-message -p -t {pane} '#{{pane_current_path}}'); [ -n \"$dir\" ] || {{ echo {missing} >&2; exit 1; }}; \
+         eval \"$({tmux} show-environment -s -t {session})\"; cd \"$dir\" || exit 1; {command}",
+        tmux = tmux,
+        pane = util::shell_escape(&pane),
+        session = util::shell_escape(&format!("={}", session)),
+        missing = util::shell_escape(&format!("[vigil] no session or pane '{}'", target)),
+        command = command.join(" ")
+    )
+}
+
+/// Remote script for `vigil exec --in --keys`: type `command` into the shell
+/// of the target pane, its output redirected to files in a private directory,
+/// then wait up to `timeout` seconds for the status file and print what it
+/// wrote. The line starts with a space so shells ignoring such lines keep it
+/// out of their history.
+pub fn exec_keys_script(config: &Config, target: &str, command: &[String], timeout: u64) -> String {
+    let (pane, _) = pane_target(target);
+    let tmux = config.tmux_cmd();
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let dir = format!("/tmp/vigil-exec-{}-{}", std::process::id(), nanos);
+    let command = command.join(" ");
+    // The status goes through a rename so it is never read half-written
+    let posix = format!(" {{ {cmd}; }} >{d}/out 2>{d}/err; echo $? >{d}/s; mv {d}/s {d}/rc", cmd = command, d = dir);
+    let fish = format!(" begin; {cmd}; end >{d}/out 2>{d}/err; echo $status >{d}/s; mv {d}/s {d}/rc", cmd = command, d = dir);
+    format!(
+        "t={pane}; c=$({tmux} display-message -p -t \"$t\" '#{{pane_current_command}}'); [ -n \"$c\" ] || {{ echo {missing} >&2; exit 1; }}; \
+         case $c in fish) line={fish} ;; {shells}) line={posix} ;; \
+         *) echo \"[vigil] the pane is running $c, not a shell; leave out --keys or pick another pane\" >&2; exit 1 ;; esac; \
+         mkdir -m 700 {d} || exit 1; \
+         {tmux} send-keys -t \"$t\" -l \"$line\" && {tmux} send-keys -t \"$t\" Enter || {{ rm -rf {d}; exit 1; }}; \
+         i=0; while [ ! -f {d}/rc ]; do \
+             if [ $i -ge {ticks} ]; then echo '[vigil] no result after {timeout}s; the command is still running in the pane' >&2; rm -rf {d}; exit 124; fi; \
+             i=$((i+1)); sleep 0.2; \
+         done; \
+         cat {d}/out; cat {d}/err >&2; rc=$(cat {d}/rc); rm -rf {d}; exit $rc",
+        pane = util::shell_escape(&pane),
+        tmux = tmux,
+        missing = util::shell_escape(&format!("[vigil] no session or pane '{}'", target)),
+        fish = util::shell_escape(&fish),
+        posix = util::shell_escape(&posix),
+        shells = PANE_SHELLS,
+        d = dir,
+        ticks = timeout * 5,
+        timeout = timeout
+    )
+}
+
+/// Contents of the paste buffer `buffer` (the most recent one when `None`) of
+/// the tmux server running `session`
+pub fn save_buffer(config: &Config, session: &str, buffer: Option<&str>) -> Result<String> {
+    let mut cmd = format!(
+        "{} has-session -t {} && {} save-buffer",
+        config.tmux_cmd(),
+        util::shell_escape(&format!("={}", session)),
+        config.tmux_cmd()
+    );
+    if let Some(buffer) = buffer {
+        cmd.push_str(&format!(" -b {}", util::shell_escape(buffer)));
+    }
+    cmd.push_str(" -");
+    ssh::exec_remote_capture(config, &cmd)?.into_stdout()
+}
+
+/// Load `data` into the paste buffer `buffer` (a new one when `None`) of the
+/// tmux server running `session`
+pub fn load_buffer(config: &Config, session: &str, buffer: Option<&str>, data: &[u8]) -> Result<()> {
+    let mut cmd = format!(
+        "{} has-session -t {} && {} load-buffer",
+        config.tmux_cmd(),
+        util::shell_escape(&format!("={}", session)),
+        config.tmux_cmd()
+    );
+    if let Some(buffer) = buffer {
+        cmd.push_str(&format!(" -b {}", util::shell_escape(buffer)));
+    }
+    cmd.push_str(" -");
+    ssh::exec_remote_with_input(config, &cmd, data)
+}
+
+/// Lock all clients attached to a remote tmux session
+pub fn lock_remote_session(config: &Config, target: &str) -> Result<()> {
+    let lock_cmd = format!(
+        "{} lock-session -t {}",
+        config.tty_tmux_cmd(),
+        util::shell_escape(&format!("={}", target))
+    );
+
+    ssh::exec_remote_command(config, &lock_cmd)
 }
 
 /// Attach to a remote tmux session (creates if not exists)
 pub fn attach_session(config: &Config, session_name: &str) -> Result<()> {
     let ssh_args = build_attach_command(config, session_name);
-    
-    let status = std::process::Command::new(&config.ssh_prog)
-        .args(&ssh_args)
-        .stdin(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .status()?;
+    with_events(config, Some(session_name), || run_attach(config, &ssh_args, session_name))
+}
+
+/// Open a plain interactive shell on the host, without tmux (--no-tmux)
+pub fn attach_plain_shell(config: &Config) -> Result<()> {
+    let mut ssh_args = tty_ssh_args(config);
+    // sshd prints the MOTD and last login only for sessions without a command;
+    // $SHELL -l reads as the same login shell in sh, bash, zsh, and fish
+    if config.quiet_login && ssh::destination_index(&ssh_args).is_some_and(|i| i + 1 == ssh_args.len()) {
+        ssh_args.push("exec $SHELL -l".into());
+    }
+    with_events(config, None, || run_attach(config, &ssh_args, "shell"))
+}
+
+/// Pick and attach to a session in a single SSH connection (see [`build_picker_command`])
+pub fn attach_with_remote_picker(config: &Config, default_name: &str) -> Result<()> {
+    let ssh_args = build_picker_command(config, default_name);
+    with_events(config, None, || run_attach(config, &ssh_args, "…"))
+}
+
+/// Run an attach between "attached" and (when it ends cleanly) "detached"
+/// events; the session is null for plain shells and the remote picker
+fn with_events(config: &Config, session: Option<&str>, attach: impl FnOnce() -> Result<()>) -> Result<()> {
+    let host = config.host_label();
+    events::emit("attached", json!({ "host": host, "session": session }));
+    let started = util::unix_now();
+    attach()?;
+    events::emit(
+        "detached",
+        json!({ "host": host, "session": session, "seconds": util::unix_now() - started }),
+    );
+    Ok(())
+}
+
+/// Run an interactive ssh command with title, recording, and error reporting
+fn run_attach(config: &Config, ssh_args: &[String], session: &str) -> Result<()> {
+    // Until the remote side sets a title, {title} shows the session
+    let title = render_title(config, session).replace("{title}", session);
+    let _title = ui::TitleGuard::set(&title);
+    term::save_termios();
+
+    let mut cmd = config.ssh_command();
+    if let Some(term) = &config.term {
+        cmd.env("TERM", term);
+    }
+    cmd.args(ssh_args);
+
+    let status = match (&config.record, pty::Proxy::for_session(config, session)?) {
+        (Some(path), _) => {
+            ui::status(&format!("Recording to {}", path.display()));
+            record::run_recorded(cmd, path, &title)?
+        }
+        (None, Some(proxy)) => timings::phase("ssh + tmux session (until exit)", || proxy.run(cmd))?,
+        (None, None) => timings::phase("ssh + tmux session (until exit)", || {
+            cmd.stdin(std::process::Stdio::inherit())
+                .stdout(std::process::Stdio::inherit())
+                .stderr(std::process::Stdio::inherit())
+                .status()
+        })?,
+    };
 
     if !status.success() {
-        if let Some(127) = status.code() {
-            eprintln!("[vigil] {}", util::tmux_install_hint());
+        // ssh or tmux may have died before putting the terminal back
+        term::reset_terminal();
+        match status.code() {
+            Some(127) => {
+                // One more connection to learn the system, for its install command
+                let os_probe = ssh::exec_remote_capture(config, util::OS_PROBE).map(|output| output.stdout);
+                ui::error(&util::tmux_install_hint(&os_probe.unwrap_or_default()));
+            }
+            // ssh's own failures; a changed host key deserves a clearer story
+            Some(255) => ssh::diagnose_host_key(config)?,
+            _ => {}
         }
         return Err(anyhow!("remote command exited with status: {}", status));
     }
 
     Ok(())
 }
+
+/// Remote path of the tmate socket used to share a session
+fn share_socket(session_name: &str) -> String {
+    let safe: String = session_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("\"/tmp/vigil-share-$(id -u)-{}.sock\"", safe)
+}
+
+/// Join URLs for a shared session
+#[derive(Debug, Default)]
+pub struct ShareInfo {
+    pub ssh_rw: String,
+    pub ssh_ro: String,
+    pub web_rw: String,
+    pub web_ro: String,
+}
+
+/// Start a tmate server on the remote host whose only window attaches to
+/// `session_name`, and return its join URLs. Reuses a running share.
+pub fn share_session(config: &Config, session_name: &str) -> Result<ShareInfo> {
+    let sock = share_socket(session_name);
+    let inner = format!(
+        "unset TMUX; exec {} attach-session -t {}",
+        config.tmux_cmd(),
+        util::shell_escape(session_name)
+    );
+    let script = format!(
+        "command -v tmate >/dev/null 2>&1 || {{ echo 'tmate not found' >&2; exit 127; }}; \
+         {tmux} has-session -t {session} 2>/dev/null || {{ echo 'no such session' >&2; exit 1; }}; \
+         tmate -S {sock} has-session 2>/dev/null || tmate -S {sock} new-session -d {inner} || exit 1; \
+         tmate -S {sock} wait tmate-ready && \
+         tmate -S {sock} display -p 'ssh_rw=#{{tmate_ssh}}' && \
+         tmate -S {sock} display -p 'ssh_ro=#{{tmate_ssh_ro}}' && \
+         tmate -S {sock} display -p 'web_rw=#{{tmate_web}}' && \
+         tmate -S {sock} display -p 'web_ro=#{{tmate_web_ro}}'",
+        tmux = config.tmux_cmd(),
+        session = util::shell_escape(session_name),
+        sock = sock,
+        inner = util::shell_escape(&inner),
+    );
+
+    let output = ssh::exec_remote_capture(config, &script)?.into_stdout()?;
+    let mut info = ShareInfo::default();
+    for line in output.lines() {
+        if let Some((key, value)) = line.trim().split_once('=') {
+            let value = value.to_string();
+            match key {
+                "ssh_rw" => info.ssh_rw = value,
+                "ssh_ro" => info.ssh_ro = value,
+                "web_rw" => info.web_rw = value,
+                "web_ro" => info.web_ro = value,
+                _ => {}
+            }
+        }
+    }
+    if info.ssh_rw.is_empty() {
+        return Err(anyhow!(
+            "failed to share session '{}' (is tmate installed on the remote host and does the session exist?)",
+            session_name
+        ));
+    }
+    Ok(info)
+}
+
+/// Stop sharing a session by killing its tmate server
+pub fn stop_share(config: &Config, session_name: &str) -> Result<()> {
+    let cmd = format!("tmate -S {} kill-server", share_socket(session_name));
+    ssh::exec_remote_command(config, &cmd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        shell_words::split(line).unwrap()
+    }
+
+    #[test]
+    fn every_chained_command_must_be_read_only() {
+        assert!(is_read_only_command(&args("ls -F '#S'")));
+        assert!(is_read_only_command(&args(r"list-windows \; show-options -g")));
+        assert!(!is_read_only_command(&args(r"ls \; kill-server")));
+        assert!(!is_read_only_command(&args("ls; kill-server")));
+        assert!(!is_read_only_command(&args("display -p '#(rm -rf ~)'")));
+        assert_eq!(command_names(&args(r"send-keys 'a\;' \; lsw")), ["send-keys", "lsw"]);
+    }
+
+    #[test]
+    fn install_hint_follows_the_probe_after_the_marker() {
+        let stdout = "sh: tmux: not found\n@@vigil-os\nDarwin\n";
+        assert_eq!(os_probe(stdout), "Darwin\n");
+        assert!(util::tmux_install_hint(os_probe(stdout)).contains("brew install tmux"));
+        assert_eq!(os_probe("no marker"), "");
+    }
+
+    #[test]
+    fn kill_targets_name_their_sessions() {
+        assert_eq!(target_sessions(&args("kill-session -t =prod:")).unwrap(), ["prod"]);
+        assert_eq!(target_sessions(&args(r"kill-window -tdev:1 \; kill-pane -t %3")), None);
+        assert_eq!(target_sessions(&args("kill-session")), None);
+        // -a kills every session but the target
+        assert_eq!(target_sessions(&args("kill-session -a -t scratch")), None);
+        assert_eq!(target_sessions(&args("kill-session -aC -t scratch")), None);
+        // tmux's own options do not hide the command
+        assert_eq!(command_names(&args("-u kill-server")), ["kill-server"]);
+        assert_eq!(command_names(&args(r"-L other -2 kill-session -t x \; ls")), ["kill-session", "ls"]);
+        assert!(!is_read_only_command(&args("-u kill-server")));
+    }
+
+    #[test]
+    fn kills_use_exact_session_names() {
+        let config = Config::new("s".into(), false, "tmux".into(), String::new(), "ssh".into(), vec!["h".into()], "me".into(), false);
+        assert_eq!(kill_session_command(&config, "prod"), "tmux kill-session -t '=prod'");
+    }
+}
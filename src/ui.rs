@@ -1,31 +1,385 @@
 use anyhow::{anyhow, Context, Result};
-use std::io::{self, Write};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use crate::events;
+use crate::i18n::tr;
+use crate::picker;
+use crate::term;
 
-/// Display a list of sessions and prompt user to select one
-pub fn prompt_user_to_select_session(action: &str, sessions: &[String]) -> Result<String> {
-    eprintln!("[vigil] Select a session to {}:", action);
-    for (i, name) in sessions.iter().enumerate() {
-        eprintln!("  {}. {}", i + 1, name);
+/// When to emit ANSI colors in vigil's own output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Color theme, configurable via the `[theme]` table of the config file.
+/// Each entry is a space-separated style such as "bold red" or "bright-cyan".
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub status: String,
+    pub error: String,
+    pub warning: String,
+    pub session: String,
+    pub host: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            status: "cyan".into(),
+            error: "bold red".into(),
+            warning: "yellow".into(),
+            session: "bold green".into(),
+            host: "bold blue".into(),
+        }
+    }
+}
+
+/// Exit status when the user cancels an interactive prompt with Ctrl-C
+pub const EXIT_CANCELLED: i32 = 130;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static COLOR: AtomicBool = AtomicBool::new(false);
+static PLAIN: AtomicBool = AtomicBool::new(false);
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// Configure output behavior for the rest of the process
+pub fn init(quiet: bool, color: ColorChoice, theme: Theme, plain: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    COLOR.store(resolve_color(color), Ordering::Relaxed);
+    PLAIN.store(plain || dumb_terminal(), Ordering::Relaxed);
+    let _ = THEME.set(theme);
+
+    let t = self::theme();
+    for spec in [&t.status, &t.error, &t.warning, &t.session, &t.host] {
+        for word in spec.split_whitespace() {
+            if sgr_code(word).is_none() {
+                warn(&format!("unknown style '{}' in [theme] config", word));
+            }
+        }
+    }
+}
+
+fn theme() -> &'static Theme {
+    THEME.get_or_init(Theme::default)
+}
+
+/// Resolve a color choice against NO_COLOR and whether stderr is a terminal
+/// that understands escape sequences
+fn resolve_color(choice: ColorChoice) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+            !no_color && io::stderr().is_terminal() && !dumb_terminal()
+        }
+    }
+}
+
+/// TERM=dumb: no cursor movement, colors, or other escape sequences
+fn dumb_terminal() -> bool {
+    std::env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+/// SGR parameter for a single style word
+fn sgr_code(word: &str) -> Option<&'static str> {
+    Some(match word.to_ascii_lowercase().as_str() {
+        "bold" => "1",
+        "dim" => "2",
+        "italic" => "3",
+        "underline" => "4",
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        "bright-black" | "gray" | "grey" => "90",
+        "bright-red" => "91",
+        "bright-green" => "92",
+        "bright-yellow" => "93",
+        "bright-blue" => "94",
+        "bright-magenta" => "95",
+        "bright-cyan" => "96",
+        "bright-white" => "97",
+        _ => return None,
+    })
+}
+
+/// Translate a style spec like "bold red" into an SGR parameter list
+fn sgr_codes(spec: &str) -> String {
+    let codes: Vec<&str> = spec.split_whitespace().filter_map(sgr_code).collect();
+    codes.join(";")
+}
+
+/// Wrap text in the given style when color is enabled
+fn paint(spec: &str, text: &str) -> String {
+    if !COLOR.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+    let codes = sgr_codes(spec);
+    if codes.is_empty() {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", codes, text)
+}
+
+/// Highlight a session name
+pub fn session(name: &str) -> String {
+    paint(&theme().session, name)
+}
+
+/// Highlight a host name
+pub fn host(name: &str) -> String {
+    paint(&theme().host, name)
+}
+
+/// Sets the local terminal title for its lifetime, restoring the previous
+/// title (via the xterm title stack) when dropped.
+pub struct TitleGuard {
+    active: bool,
+}
+
+impl TitleGuard {
+    pub fn set(title: &str) -> Self {
+        let mut stderr = io::stderr();
+        if title.is_empty() || !stderr.is_terminal() || PLAIN.load(Ordering::Relaxed) {
+            return TitleGuard { active: false };
+        }
+        // Push the current title, then set both icon name and window title
+        let _ = write!(stderr, "\x1b[22;0t\x1b]0;{}\x07", title);
+        let _ = stderr.flush();
+        TitleGuard { active: true }
+    }
+}
+
+impl Drop for TitleGuard {
+    fn drop(&mut self) {
+        if self.active {
+            let mut stderr = io::stderr();
+            let _ = write!(stderr, "\x1b[23;0t");
+            let _ = stderr.flush();
+        }
+    }
+}
+
+/// While alive, Ctrl-C restores the terminal, prints "cancelled", and exits
+/// with [`EXIT_CANCELLED`] instead of killing vigil in the middle of a prompt.
+struct InterruptGuard {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    const MSG: &[u8] = b"\n[vigil] cancelled\n";
+    term::restore_termios();
+    // SAFETY: write(2) and _exit(2) are async-signal-safe
+    unsafe {
+        libc::write(libc::STDERR_FILENO, MSG.as_ptr().cast(), MSG.len());
+        libc::_exit(EXIT_CANCELLED);
+    }
+}
+
+impl InterruptGuard {
+    fn install() -> Self {
+        term::save_termios();
+        #[cfg(unix)]
+        {
+            let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // SAFETY: the handler only performs async-signal-safe calls
+            let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+            InterruptGuard { previous }
+        }
+        #[cfg(not(unix))]
+        InterruptGuard {}
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: reinstates whatever disposition was active before install()
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
+/// Display a list of sessions, with their notes, and prompt user to select one
+pub fn prompt_user_to_select_session(action: &str, sessions: &[String], notes: &BTreeMap<String, String>) -> Result<String> {
+    let question = tr(&format!("select.{}", action), &[]);
+    if !sessions.iter().any(|name| notes.contains_key(name)) {
+        return select_item(&question, sessions, session);
+    }
+    let labels: Vec<String> = sessions
+        .iter()
+        .map(|name| match notes.get(name) {
+            Some(note) => format!("{}  — {}", name, note),
+            None => name.clone(),
+        })
+        .collect();
+    let chosen = select_item(&question, &labels, session)?;
+    let index = labels.iter().position(|label| *label == chosen).unwrap_or(0);
+    Ok(sessions[index].clone())
+}
+
+/// Display a list of hosts and prompt user to select one
+pub fn prompt_user_to_select_host(hosts: &[String]) -> Result<String> {
+    select_item(&tr("select.host", &[]), hosts, host)
+}
+
+/// Display "host  session  details" lines and prompt user to select one
+pub fn prompt_user_to_select_workspace(lines: &[String]) -> Result<String> {
+    select_item(&tr("select.switch", &[]), lines, |line| line.to_string())
+}
+
+/// Prompt for one of `items`, each shown through `style`
+fn select_item(question: &str, items: &[String], style: fn(&str) -> String) -> Result<String> {
+    let _interrupt = InterruptGuard::install();
+    let header = format!("{} {}", paint(&theme().status, "[vigil]"), question);
+
+    // Arrow keys and mouse on a real terminal; numbered prompt otherwise
+    let plain = PLAIN.load(Ordering::Relaxed);
+    if !plain && io::stdin().is_terminal() && io::stderr().is_terminal() && picker::fits(items.len()) {
+        let hint = tr("picker.hint", &[]);
+        return match picker::select(&format!("{} {}", header, hint), items)? {
+            Some(idx) => Ok(items[idx].clone()),
+            None => {
+                eprintln!("{} {}", paint(&theme().status, "[vigil]"), tr("cancelled", &[]));
+                events::exit(EXIT_CANCELLED);
+            }
+        };
+    }
+
+    eprintln!("{}", header);
+    for (i, name) in items.iter().enumerate() {
+        eprintln!("  {}. {}", i + 1, style(name));
+    }
+    if !plain {
+        eprint!("{}", tr("prompt.number", &[]));
+        io::stderr().flush().ok();
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).context("failed to read selection")?;
+        let input = input.trim();
+        let idx = if input.is_empty() { 1 } else { input.parse::<usize>().unwrap_or(0) };
+        if idx == 0 || idx > items.len() {
+            return Err(anyhow!("invalid selection"));
+        }
+        return Ok(items[idx - 1].clone());
+    }
+
+    // One line per prompt with the same wording each time, repeated until the answer fits
+    loop {
+        eprint!("{}", tr("prompt.choose", &[("count", &items.len().to_string())]));
+        io::stderr().flush().ok();
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).context("failed to read selection")? == 0 {
+            return Err(anyhow!("no selection made"));
+        }
+        match input.trim() {
+            "" => return Ok(items[0].clone()),
+            "q" | "Q" => {
+                eprintln!("{} {}", paint(&theme().status, "[vigil]"), tr("cancelled", &[]));
+                events::exit(EXIT_CANCELLED);
+            }
+            answer => match answer.parse::<usize>() {
+                Ok(idx) if (1..=items.len()).contains(&idx) => return Ok(items[idx - 1].clone()),
+                _ => eprintln!("{}", tr("prompt.not_a_choice", &[("answer", answer)])),
+            },
+        }
+    }
+}
+
+/// Ask for a line of text on stderr; an empty answer (or end of input) gives `default`
+pub fn ask(question: &str, default: &str) -> Result<String> {
+    let _interrupt = InterruptGuard::install();
+    let prefix = paint(&theme().status, "[vigil]");
+    if default.is_empty() {
+        eprint!("{} {} ", prefix, question);
+    } else {
+        eprint!("{} {} [{}] ", prefix, question, default);
     }
-    eprint!("Enter number (or press Enter for 1): ");
     io::stderr().flush().ok();
 
     let mut input = String::new();
-    io::stdin().read_line(&mut input).context("failed to read selection")?;
+    io::stdin().read_line(&mut input).context("failed to read answer")?;
     let input = input.trim();
-    let idx = if input.is_empty() { 1 } else { input.parse::<usize>().unwrap_or(0) };
-    if idx == 0 || idx > sessions.len() {
-        return Err(anyhow!("invalid selection"));
-    }
-    Ok(sessions[idx - 1].clone())
+    Ok(if input.is_empty() { default.to_string() } else { input.to_string() })
 }
 
-/// Print status message to stderr
+/// Ask a yes/no question on stderr; anything but "y"/"yes" (or the
+/// language's own yes, such as "ja") means no
+pub fn confirm(question: &str) -> Result<bool> {
+    let _interrupt = InterruptGuard::install();
+    eprint!("{} {} {} ", paint(&theme().warning, "[vigil]"), question, tr("confirm.yes_no", &[]));
+    io::stderr().flush().ok();
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).context("failed to read answer")?;
+    let answer = input.trim().to_lowercase();
+    Ok(tr("confirm.yes_words", &[]).split_whitespace().any(|word| word == answer))
+}
+
+/// Ask for `expected` to be typed out exactly, for actions a stray "y" must
+/// not trigger
+pub fn confirm_by_typing(question: &str, expected: &str) -> Result<bool> {
+    let _interrupt = InterruptGuard::install();
+    eprint!("{} {} {}", paint(&theme().warning, "[vigil]"), question, tr("confirm.type", &[("expected", expected)]));
+    io::stderr().flush().ok();
+
+    // From the terminal even when stdin is piped (e.g. names for --kill --stdin)
+    let tty = std::fs::File::open(if cfg!(windows) { "CONIN$" } else { "/dev/tty" }).map_err(|_| {
+        eprintln!();
+        anyhow!("protected sessions and hosts need a typed confirmation, and no terminal is available")
+    })?;
+    let mut input = String::new();
+    io::BufReader::new(tty).read_line(&mut input).context("failed to read answer")?;
+    Ok(input.trim() == expected)
+}
+
+/// Print status message to stderr (suppressed by --quiet)
 pub fn status(msg: &str) {
-    eprintln!("[vigil] {}", msg);
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    eprintln!("{} {}", paint(&theme().status, "[vigil]"), msg);
+}
+
+/// Print warning message to stderr (suppressed by --quiet)
+pub fn warn(msg: &str) {
+    if QUIET.load(Ordering::Relaxed) {
+        return;
+    }
+    eprintln!("{} {} {}", paint(&theme().status, "[vigil]"), paint(&theme().warning, &tr("label.warning", &[])), msg);
 }
 
 /// Print error message to stderr
 pub fn error(msg: &str) {
-    eprintln!("[vigil] ERROR: {}", msg);
+    eprintln!("{} {} {}", paint(&theme().status, "[vigil]"), paint(&theme().error, &tr("label.error", &[])), msg);
+}
+
+/// Best-effort desktop notification via notify-send (Linux) or osascript (macOS)
+pub fn desktop_notify(msg: &str) {
+    let mut cmd = if cfg!(target_os = "macos") {
+        let mut c = Command::new("osascript");
+        c.arg("-e").arg(format!("display notification {:?} with title \"vigil\"", msg));
+        c
+    } else {
+        let mut c = Command::new("notify-send");
+        c.arg("vigil").arg(msg);
+        c
+    };
+    let _ = cmd.stdout(Stdio::null()).stderr(Stdio::null()).status();
 }
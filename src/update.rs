@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::config::FileConfig;
+use crate::events;
+use crate::ui;
+
+/// GitHub repository publishing vigil releases
+const RELEASE_REPO: &str = "volnuttz/vigil";
+
+/// Namespace release signatures are made for (ssh-keygen -Y sign -n ...)
+const SIGNATURE_NAMESPACE: &str = "vigil-release";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|a| a.name == name)
+    }
+}
+
+/// Release binary for this platform, e.g. "vigil-x86_64-linux"
+fn asset_name() -> String {
+    format!("vigil-{}-{}{}", std::env::consts::ARCH, std::env::consts::OS, std::env::consts::EXE_SUFFIX)
+}
+
+/// "v1.2.3" or "1.2.3" as comparable numbers; pre-release suffixes are ignored
+fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let core = v.trim_start_matches('v').split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|p| p.parse::<u64>().ok());
+    Some((parts.next()??, parts.next().flatten().unwrap_or(0), parts.next().flatten().unwrap_or(0)))
+}
+
+fn curl(args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--proto", "=https"])
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .context("failed to run curl (is it installed?)")?;
+    if !output.status.success() {
+        return Err(anyhow!("download failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(output.stdout)
+}
+
+fn latest_release() -> Result<Release> {
+    let url = format!("https://api.github.com/repos/{}/releases/latest", RELEASE_REPO);
+    let body = curl(&["-H", "Accept: application/vnd.github+json", &url])?;
+    serde_json::from_slice(&body).context("unexpected response from the GitHub releases API")
+}
+
+/// SHA-256 of a file as lowercase hex, via sha256sum or shasum
+fn sha256(path: &Path) -> Result<String> {
+    let attempts: [(&str, &[&str]); 2] = [("sha256sum", &[]), ("shasum", &["-a", "256"])];
+    for (prog, args) in attempts {
+        let Ok(output) = Command::new(prog).args(args).arg(path).stderr(Stdio::null()).output() else {
+            continue;
+        };
+        if let Some(sum) = String::from_utf8_lossy(&output.stdout).split_whitespace().next() {
+            return Ok(sum.to_ascii_lowercase());
+        }
+    }
+    Err(anyhow!("neither sha256sum nor shasum is available to verify the download"))
+}
+
+/// Expected checksum of `name` from a SHA256SUMS file ("<hex>  <name>" lines)
+fn expected_sum(sums: &str, name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (sum, file) = line.split_once(char::is_whitespace)?;
+        (file.trim().trim_start_matches('*') == name).then(|| sum.to_ascii_lowercase())
+    })
+}
+
+/// Check the SHA256SUMS signature against the configured allowed signers
+fn verify_signature(signers: &Path, sums: &Path, sig: &Path) -> Result<()> {
+    let input = fs::File::open(sums)?;
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-n", SIGNATURE_NAMESPACE, "-I", "release"])
+        .arg("-f")
+        .arg(signers)
+        .arg("-s")
+        .arg(sig)
+        .stdin(input)
+        .output()
+        .context("failed to run ssh-keygen")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "release signature does not verify against {}: {}",
+            signers.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Compare the running version with the latest release. With `check_only`,
+/// report and exit 1 when behind; otherwise download, verify the signed
+/// checksums against `release_signers`, and replace the running binary.
+pub fn run(file: &FileConfig, check_only: bool) -> Result<()> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release = latest_release()?;
+    let latest = release.tag_name.trim_start_matches('v');
+    let (Some(have), Some(want)) = (parse_version(current), parse_version(latest)) else {
+        return Err(anyhow!("cannot compare versions {} and {}", current, release.tag_name));
+    };
+
+    if have >= want {
+        println!("vigil {} is up to date (latest release: {})", current, latest);
+        return Ok(());
+    }
+    if check_only {
+        println!("vigil {} is out of date (latest release: {})", current, latest);
+        events::exit(1);
+    }
+
+    // The checksums come from the same release as the binary, so only a
+    // signature from a key the user holds proves where the binary came from
+    let signers = file.release_signers.as_ref().ok_or_else(|| {
+        anyhow!(
+            "self-update installs only signed releases; set release_signers in {} to an ssh \
+             allowed_signers file with the vigil release key, obtained separately from the release",
+            FileConfig::path().display()
+        )
+    })?;
+
+    let name = asset_name();
+    let binary = release
+        .asset(&name)
+        .ok_or_else(|| anyhow!("release {} has no binary for this platform ({})", latest, name))?;
+    let sums = release
+        .asset("SHA256SUMS")
+        .ok_or_else(|| anyhow!("release {} publishes no SHA256SUMS; refusing to install it", latest))?;
+
+    let exe = std::env::current_exe().context("cannot locate the running binary")?;
+    let exe = fs::canonicalize(&exe).unwrap_or(exe);
+    let dir = exe.parent().ok_or_else(|| anyhow!("cannot locate the running binary"))?;
+    let staged = Staged(dir.join(format!(".vigil-update-{}", std::process::id())));
+    let sums_path = Staged(dir.join(format!(".vigil-update-{}.sums", std::process::id())));
+
+    ui::status(&format!("Downloading vigil {}...", latest));
+    fs::write(&sums_path.0, curl(&[&sums.browser_download_url])?)
+        .with_context(|| format!("cannot write to {} (try reinstalling with the right permissions)", dir.display()))?;
+    let sig = release
+        .asset("SHA256SUMS.sig")
+        .ok_or_else(|| anyhow!("release {} is not signed; refusing to install it", latest))?;
+    let sig_path = Staged(dir.join(format!(".vigil-update-{}.sig", std::process::id())));
+    fs::write(&sig_path.0, curl(&[&sig.browser_download_url])?)?;
+    verify_signature(signers, &sums_path.0, &sig_path.0)?;
+    curl(&["-o", &staged.0.to_string_lossy(), &binary.browser_download_url])?;
+
+    let sums_text = fs::read_to_string(&sums_path.0)?;
+    let expected = expected_sum(&sums_text, &name)
+        .ok_or_else(|| anyhow!("SHA256SUMS of release {} does not list {}", latest, name))?;
+    // With the signed list, this ties the binary to the signature
+    let actual = sha256(&staged.0)?;
+    if actual != expected {
+        return Err(anyhow!("checksum mismatch for {} (corrupt download?): expected {}, got {}", name, expected, actual));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged.0, fs::Permissions::from_mode(0o755))?;
+    }
+    // Windows cannot overwrite a running executable, but it can rename it
+    #[cfg(windows)]
+    fs::rename(&exe, exe.with_extension("old.exe"))?;
+    fs::rename(&staged.0, &exe).with_context(|| format!("failed to replace {}", exe.display()))?;
+    ui::status(&format!("Updated {} from {} to {}.", exe.display(), current, latest));
+    Ok(())
+}
+
+/// Temporary download removed on drop, unless it was moved into place
+struct Staged(PathBuf);
+
+impl Drop for Staged {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
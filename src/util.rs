@@ -1,4 +1,5 @@
 use std::env;
+use std::path::PathBuf;
 
 /// Shell-escape a string for use in tmux commands
 pub fn shell_escape(s: &str) -> String {
@@ -7,18 +8,194 @@ pub fn shell_escape(s: &str) -> String {
     format!("'{}'", escaped)
 }
 
-/// Get the local system username
+/// Match `text` against a pattern where `*` stands for any run of characters
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let Some((first, rest)) = pattern.split_once('*') else {
+        return pattern == text;
+    };
+    let Some(mut remaining) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let last = parts.pop().unwrap_or("");
+    for part in parts {
+        match remaining.find(part) {
+            Some(at) => remaining = &remaining[at + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= last.len() && remaining.ends_with(last)
+}
+
+/// Read session names from stdin, one per line, skipping blanks and # comments
+pub fn read_names_from_stdin() -> std::io::Result<Vec<String>> {
+    use std::io::BufRead;
+
+    let mut names = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let name = line.trim();
+        if !name.is_empty() && !name.starts_with('#') {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+/// Get the local system username (Windows sets USERNAME rather than USER)
 pub fn get_local_username() -> String {
     env::var("USER")
         .or_else(|_| env::var("LOGNAME"))
+        .or_else(|_| env::var("USERNAME"))
         .unwrap_or_else(|_| "user".to_string())
 }
 
-/// Check if SSH binary is available in PATH
-pub fn check_ssh_available() -> bool {
+/// Directory holding vigil's config file ($XDG_CONFIG_HOME/vigil or ~/.config/vigil)
+pub fn config_dir() -> PathBuf {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".config"));
+    base.join("vigil")
+}
+
+/// Directory holding vigil's state such as logs ($XDG_STATE_HOME/vigil or ~/.local/state/vigil)
+pub fn state_dir() -> PathBuf {
+    let base = env::var_os("XDG_STATE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".local").join("state"));
+    base.join("vigil")
+}
+
+/// Directory for disposable cached data ($XDG_CACHE_HOME/vigil or ~/.cache/vigil)
+pub fn cache_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".cache"));
+    base.join("vigil")
+}
+
+/// Seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format a Unix timestamp as "YYYY-MM-DD HH:MM:SSZ" (UTC)
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Format a duration in seconds compactly, e.g. "1h02m", "3m05s", "12s"
+pub fn format_duration(secs: u64) -> String {
+    if secs >= 86_400 {
+        format!("{}d{:02}h", secs / 86_400, (secs % 86_400) / 3600)
+    } else if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Parse a duration such as "90s", "30m", "2h", "1d" or "1h30m" (a bare
+/// number counts as minutes)
+pub fn parse_duration(text: &str) -> anyhow::Result<std::time::Duration> {
+    let invalid = || anyhow::anyhow!("invalid duration '{}' (expected e.g. 30m, 2h or 1h30m)", text);
+    let too_long = || anyhow::anyhow!("duration '{}' is too long", text);
+    let text = text.trim();
+    if let Ok(mins) = text.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(mins.checked_mul(60).ok_or_else(too_long)?));
+    }
+    let mut secs = 0u64;
+    let mut number = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            _ => return Err(invalid()),
+        };
+        let n: u64 = number.parse().map_err(|_| invalid())?;
+        secs = n.checked_mul(unit).and_then(|n| secs.checked_add(n)).ok_or_else(too_long)?;
+        number.clear();
+    }
+    if !number.is_empty() || secs == 0 {
+        return Err(invalid());
+    }
+    Ok(std::time::Duration::from_secs(secs))
+}
+
+/// The local user's home directory (%USERPROFILE% on Windows when HOME is unset)
+pub fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .or_else(|| if cfg!(windows) { env::var_os("USERPROFILE") } else { None })
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Whether vigil runs inside Windows Subsystem for Linux
+pub fn is_wsl() -> bool {
+    env::var_os("WSL_DISTRO_NAME").is_some()
+        || std::path::Path::new("/proc/sys/fs/binfmt_misc/WSLInterop").exists()
+}
+
+/// Size of the controlling terminal as (columns, rows)
+#[cfg(unix)]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    // SAFETY: TIOCGWINSZ only writes into the winsize struct we pass in
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    for fd in [libc::STDOUT_FILENO, libc::STDIN_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_col > 0 {
+            return Some((ws.ws_col, ws.ws_row));
+        }
+    }
+    None
+}
+
+/// Size of the controlling terminal as (columns, rows)
+#[cfg(not(unix))]
+pub fn terminal_size() -> Option<(u16, u16)> {
+    crossterm::terminal::size().ok()
+}
+
+/// Check if the SSH client binary (ssh, ssh.exe, or plink) is available in PATH
+pub fn check_ssh_available(prog: &str) -> bool {
     use std::process::{Command, Stdio};
     
-    Command::new("ssh")
+    Command::new(prog)
         .arg("-V")
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -26,12 +203,91 @@ pub fn check_ssh_available() -> bool {
         .is_ok()
 }
 
-const TMUX_INSTALL_MESSAGE: &str = 
-    "tmux not found on remote host.\n  \
-     - Debian/Ubuntu: sudo apt-get install tmux\n  \
-     - RHEL/CentOS/Fedora: sudo yum install tmux (or dnf)\n  \
-     - macOS (Homebrew): brew install tmux";
+/// Remote command printing what [`tmux_install_hint`] needs: the kernel
+/// name, then /etc/os-release where there is one
+pub const OS_PROBE: &str = "uname -s; cat /etc/os-release 2>/dev/null";
+
+/// How to install tmux, or point vigil at one, in the user's language. With
+/// the output of [`OS_PROBE`] only the command for that system is given.
+pub fn tmux_install_hint(os_probe: &str) -> String {
+    use crate::i18n::tr;
+    let mut hint = tr("tmux.missing", &[]);
+    match tmux_install_command(os_probe) {
+        Some((os, command)) => hint += &format!("\n  - {}: {}", os, command),
+        None => hint += &format!("\n  - {}", tr("tmux.install_unknown", &[])),
+    }
+    hint + &format!("\n  - {}", tr("tmux.elsewhere", &[]))
+}
+
+/// The system's name and its tmux install command, from `uname -s` and the
+/// ID, ID_LIKE, and NAME fields of /etc/os-release
+fn tmux_install_command(os_probe: &str) -> Option<(String, &'static str)> {
+    let mut lines = os_probe.lines();
+    let kernel = lines.next().unwrap_or("").trim();
+    let field = |key: &str| {
+        os_probe
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+    };
+    match kernel {
+        "Darwin" => return Some(("macOS (Homebrew)".into(), "brew install tmux")),
+        "FreeBSD" => return Some(("FreeBSD".into(), "sudo pkg install tmux")),
+        "OpenBSD" => return Some(("OpenBSD".into(), "tmux ships with the base system; check PATH")),
+        "NetBSD" => return Some(("NetBSD".into(), "sudo pkgin install tmux")),
+        _ => {}
+    }
+    const DISTROS: [(&str, &str); 9] = [
+        ("nixos", "nix profile install nixpkgs#tmux (or add tmux to environment.systemPackages)"),
+        ("alpine", "sudo apk add tmux"),
+        ("arch", "sudo pacman -S tmux"),
+        ("debian", "sudo apt-get install tmux"),
+        ("fedora", "sudo dnf install tmux"),
+        ("rhel", "sudo dnf install tmux (yum on older releases)"),
+        ("suse", "sudo zypper install tmux"),
+        ("gentoo", "sudo emerge app-misc/tmux"),
+        ("void", "sudo xbps-install tmux"),
+    ];
+    // ID first, so Ubuntu (ID_LIKE=debian) and Rocky (ID_LIKE="rhel centos fedora") land right
+    let ids = field("ID").into_iter().chain(field("ID_LIKE")).collect::<Vec<_>>().join(" ");
+    let command = ids.split_whitespace().find_map(|id| {
+        let id = match id {
+            "ubuntu" => "debian",
+            "centos" | "rocky" | "almalinux" => "rhel",
+            "manjaro" | "endeavouros" => "arch",
+            "opensuse" | "opensuse-leap" | "opensuse-tumbleweed" | "sles" => "suse",
+            other => other,
+        };
+        DISTROS.iter().find(|(name, _)| *name == id).map(|(_, command)| *command)
+    })?;
+    Some((field("NAME").unwrap_or_else(|| kernel.to_string()), command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_command_follows_the_remote_system() {
+        let alpine = "Linux\nNAME=\"Alpine Linux\"\nID=alpine\n";
+        assert_eq!(tmux_install_command(alpine), Some(("Alpine Linux".into(), "sudo apk add tmux")));
+        let rocky = "Linux\nNAME=\"Rocky Linux\"\nID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\n";
+        assert_eq!(tmux_install_command(rocky).unwrap().1, "sudo dnf install tmux (yum on older releases)");
+        let mint = "Linux\nNAME=\"Linux Mint\"\nID=linuxmint\nID_LIKE=\"ubuntu debian\"\n";
+        assert_eq!(tmux_install_command(mint).unwrap().1, "sudo apt-get install tmux");
+        assert_eq!(tmux_install_command("FreeBSD\n").unwrap().1, "sudo pkg install tmux");
+        assert_eq!(tmux_install_command("Linux\n"), None);
+        assert_eq!(tmux_install_command(""), None);
+    }
 
-pub fn tmux_install_hint() -> &'static str {
-    TMUX_INSTALL_MESSAGE
+    #[test]
+    fn durations_parse_and_overflow_is_an_error() {
+        assert_eq!(parse_duration("1h30m").unwrap().as_secs(), 5400);
+        assert_eq!(parse_duration("45").unwrap().as_secs(), 2700);
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("999999999999999999").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+        assert!(parse_duration("9999999999999999d").is_err());
+    }
 }